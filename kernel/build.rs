@@ -1,5 +1,81 @@
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
 use glob;
+
+/// Builds the C programs in user/tests/progs and returns their ELF files
+fn c_programs() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-changed=../user/tests/Makefile");
+    println!("cargo:rerun-if-changed=../user/tests/progs");
+    println!("cargo:rerun-if-changed=../user/lib");
+    // the Makefile expects to be run from the top of the repository
+    let built = Command::new("make")
+        .current_dir("..")
+        .args(["-f", "user/tests/Makefile", "all"])
+        .status();
+    if !built.is_ok_and(|status| status.success()) {
+        println!("cargo:warning=building the user programs failed, using what is there");
+    }
+    glob::glob("../user/tests/progs/*.elf")
+        .expect("invalid path user/tests/progs/")
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Builds the Rust programs in user/ajinux/src/bin for the user target and
+/// returns the binaries
+fn rust_programs() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-changed=../user/ajinux");
+    println!("cargo:rerun-if-changed=../user/ajinux-macros");
+    println!("cargo:rerun-if-changed=../user/x86_64-ajinux.json");
+    // a target directory of our own, the one of this build is locked
+    let target_dir = Path::new(&env::var("OUT_DIR").unwrap()).join("user");
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let built = Command::new(cargo)
+        .current_dir("..")
+        .args(["build", "--release", "-p", "ajinux", "--bins"])
+        .args(["--target", "user/x86_64-ajinux.json"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .status();
+    if !built.is_ok_and(|status| status.success()) {
+        println!("cargo:warning=building the Rust user programs failed, leaving them out");
+        return Vec::new();
+    }
+    let bin_dir = target_dir.join("x86_64-ajinux").join("release");
+    glob::glob("../user/ajinux/src/bin/*.rs")
+        .expect("invalid path user/ajinux/src/bin/")
+        .filter_map(Result::ok)
+        .map(|source| bin_dir.join(source.file_stem().unwrap()))
+        .filter(|binary| binary.exists())
+        .collect()
+}
+
+/// Writes `PROGRAMS` for `process::programs` so that the user programs end
+/// up in the kernel image, named after their file without the extension
+fn user_programs() {
+    let mut programs = String::from("pub static PROGRAMS: &[(&str, &[u8])] = &[\n");
+    for path in c_programs().into_iter().chain(rust_programs()) {
+        let Ok(path) = fs::canonicalize(path) else {
+            continue;
+        };
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        writeln!(programs, "    ({:?}, include_bytes!({:?})),", name, path).unwrap();
+    }
+    programs.push_str("];\n");
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("user_programs.rs");
+    fs::write(out, programs).expect("unable to write user_programs.rs");
+}
+
 fn main() {
+    user_programs();
     // This does not work yet!
     println!("cargo:rerun-if-changed=cc/src/*.c");
     println!("cargo:rerun-if-changed=cc/inc/*.h");
@@ -0,0 +1,65 @@
+use super::{find_table, SdtHeader};
+use crate::cpu::MAX_CPUS;
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_LAPIC_ADDRESS_OVERRIDE: u8 = 5;
+const LAPIC_ENABLED: u32 = 1 << 0;
+const LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// What the kernel needs out of the Multiple APIC Description Table
+#[derive(Debug, Clone, Copy)]
+pub struct MadtInfo {
+    /// Physical address of the Local APIC registers
+    pub lapic_addr: u64,
+    /// APIC ids of the usable processors, in table order
+    pub apic_ids: [u8; MAX_CPUS],
+    pub cpu_count: usize,
+}
+
+impl MadtInfo {
+    pub fn apic_ids(&self) -> &[u8] {
+        &self.apic_ids[..self.cpu_count]
+    }
+}
+
+pub fn parse() -> Result<MadtInfo, &'static str> {
+    let header = find_table(b"APIC").ok_or("no MADT found")?;
+    let base = header as *const SdtHeader as *const u8;
+    let len = header.length as usize;
+    let read_u32 = |offset: usize| unsafe { (base.add(offset) as *const u32).read_unaligned() };
+
+    let mut info = MadtInfo {
+        lapic_addr: read_u32(core::mem::size_of::<SdtHeader>()) as u64,
+        apic_ids: [0; MAX_CPUS],
+        cpu_count: 0,
+    };
+    // the local apic address and flags come before the entries
+    let mut offset = core::mem::size_of::<SdtHeader>() + 8;
+    while offset + 2 <= len {
+        let kind = unsafe { *base.add(offset) };
+        let entry_len = unsafe { *base.add(offset + 1) } as usize;
+        if entry_len < 2 {
+            return Err("malformed MADT entry");
+        }
+        match kind {
+            ENTRY_LOCAL_APIC => {
+                let apic_id = unsafe { *base.add(offset + 3) };
+                let flags = read_u32(offset + 4);
+                if flags & (LAPIC_ENABLED | LAPIC_ONLINE_CAPABLE) != 0 && info.cpu_count < MAX_CPUS
+                {
+                    info.apic_ids[info.cpu_count] = apic_id;
+                    info.cpu_count += 1;
+                }
+            }
+            ENTRY_LAPIC_ADDRESS_OVERRIDE => {
+                info.lapic_addr = unsafe { (base.add(offset + 4) as *const u64).read_unaligned() };
+            }
+            _ => {}
+        }
+        offset += entry_len;
+    }
+    if info.cpu_count == 0 {
+        return Err("MADT lists no processors");
+    }
+    Ok(info)
+}
@@ -0,0 +1,88 @@
+use crate::paging::phys_to_virt;
+
+pub mod madt;
+
+/// Root System Description Pointer, the revision 2 fields are only valid
+/// when `revision >= 2`
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Header every System Description Table starts with
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Real mode segment of the Extended BIOS Data Area is stored here
+const EBDA_SEGMENT_PTR: u64 = 0x40e;
+const BIOS_AREA: (u64, u64) = (0xe0000, 0x100000);
+const RSDP_V1_LEN: usize = 20;
+
+fn checksum_ok(phys: u64, len: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(phys_to_virt(phys), len) };
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+fn scan_rsdp(start: u64, end: u64) -> Option<u64> {
+    (start..end).step_by(16).find(|&phys| {
+        let signature = unsafe { &*(phys_to_virt(phys) as *const [u8; 8]) };
+        signature == RSDP_SIGNATURE && checksum_ok(phys, RSDP_V1_LEN)
+    })
+}
+
+/// The RSDP sits in the first KiB of the EBDA or in the BIOS read only area
+pub fn find_rsdp() -> Option<&'static Rsdp> {
+    let ebda = unsafe { (phys_to_virt(EBDA_SEGMENT_PTR) as *const u16).read_unaligned() } as u64;
+    let ebda = ebda << 4;
+    let phys = scan_rsdp(ebda, ebda + 1024).or_else(|| scan_rsdp(BIOS_AREA.0, BIOS_AREA.1))?;
+    Some(unsafe { &*(phys_to_virt(phys) as *const Rsdp) })
+}
+
+/// Finds the table with `signature` through the XSDT, or the RSDT on
+/// revision 1 firmware
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+    let rsdp = find_rsdp()?;
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, 8)
+    } else {
+        (rsdp.rsdt_address as u64, 4)
+    };
+    let header = unsafe { &*(phys_to_virt(root) as *const SdtHeader) };
+    let count = (header.length as usize - core::mem::size_of::<SdtHeader>()) / entry_size;
+    let entries = root + core::mem::size_of::<SdtHeader>() as u64;
+    (0..count)
+        .map(|i| unsafe {
+            let entry = phys_to_virt(entries + (i * entry_size) as u64);
+            if entry_size == 8 {
+                (entry as *const u64).read_unaligned()
+            } else {
+                (entry as *const u32).read_unaligned() as u64
+            }
+        })
+        .map(|phys| (phys, unsafe { &*(phys_to_virt(phys) as *const SdtHeader) }))
+        .find(|(phys, table)| {
+            &table.signature == signature && checksum_ok(*phys, table.length as usize)
+        })
+        .map(|(_, table)| table)
+}
@@ -1,6 +1,6 @@
 use core::{ptr::{null_mut, NonNull}, alloc::{Allocator, GlobalAlloc}, cell::UnsafeCell};
 
-use crate::serial_info;
+use crate::{serial_info, sync::spinlock::SpinLock};
 
 use super::page_alloc::PageAlloc;
 
@@ -11,7 +11,8 @@ pub struct KernelAllocator {
     heap_end: UnsafeCell<*mut u8>,
 }
 
-pub static mut ALLOC: PageAlloc<4096> = PageAlloc::default();
+/// Every free page, shared by all CPUs and interrupt handlers
+pub static ALLOC: SpinLock<PageAlloc<4096>> = SpinLock::new(PageAlloc::default());
 
 // TODO: change
 const KERNEL_HEAP_START_DEFAULT: *mut u8 = null_mut();
@@ -30,26 +31,32 @@ unsafe impl Sync for KernelAllocator {}
 
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        return ALLOC.alloc_page();
+        return ALLOC.lock().alloc_page();
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
-        serial_info!("dealloc ran dremaining size: {:?} B", unsafe { ALLOC.total_size });
-        ALLOC.dealloc_page(ptr)
+        let remaining = ALLOC.lock().total_size;
+        serial_info!("dealloc ran dremaining size: {:?} B", remaining);
+        ALLOC.lock().dealloc_page(ptr)
     }
 }
 
 unsafe impl Allocator for KernelAllocator {
     fn allocate(&self, layout: core::alloc::Layout) -> Result<core::ptr::NonNull<[u8]>, core::alloc::AllocError> {
-        serial_info!("alloc ran: remaining size: {:?} B", unsafe { ALLOC.total_size });
-        let page = unsafe {(ALLOC.alloc_page())};
+        let remaining = ALLOC.lock().total_size;
+        serial_info!("alloc ran: remaining size: {:?} B", remaining);
+        let page = ALLOC.lock().alloc_page();
+        if page.is_null() {
+            return Err(core::alloc::AllocError);
+        }
         let allocation = unsafe { core::slice::from_raw_parts_mut(page, layout.align()) };
         return Ok(unsafe { NonNull::new_unchecked(allocation) });
     }
 
     unsafe fn deallocate(&self, ptr: core::ptr::NonNull<u8>, layout: core::alloc::Layout) {
-        serial_info!("dealloc ran dremaining size: {:?} B", unsafe { ALLOC.total_size });
+        let remaining = ALLOC.lock().total_size;
+        serial_info!("dealloc ran dremaining size: {:?} B", remaining);
         let page = ptr.as_ptr();
-        ALLOC.dealloc_page(page)
+        ALLOC.lock().dealloc_page(page)
     }
 }
\ No newline at end of file
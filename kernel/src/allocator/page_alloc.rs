@@ -1,4 +1,4 @@
-use core::cell::RefCell;
+use core::sync::atomic::{AtomicU16, Ordering};
 
 use bootloader::BootInfo;
 
@@ -8,32 +8,87 @@ use crate::{
 };
 
 pub struct PageAlloc<const PAGE_SIZE: u64> {
-    pub free_list: RefCell<LinkedList<[u8; 0]>>,
+    pub free_list: LinkedList<[u8; 0]>,
     pub bootinfo: Option<&'static BootInfo>,
     pub total_size: u64,
+    /// Extra references to each frame beyond its first owner, so frames
+    /// that come out of `alloc_page` start at 0 without any bookkeeping.
+    /// Covers every usable frame once `set_frame_refs` ran, frames past it
+    /// are never shared
+    frame_refs: &'static [AtomicU16],
 }
 
 impl<const PAGE_SIZE: u64> PageAlloc<PAGE_SIZE> {
+    /// A free page, null once there are none left
     pub fn alloc_page(&mut self) -> *mut u8 {
-        self.total_size -= PAGE_SIZE;
-        return self.free_list.borrow_mut().pop_head() as *mut u8;
+        let page = self.free_list.pop_head() as *mut u8;
+        if !page.is_null() {
+            self.total_size -= PAGE_SIZE;
+        }
+        page
     }
 
     pub fn dealloc_page(&mut self, page: *mut u8) {
         self.total_size += PAGE_SIZE;
-        self.free_list.borrow_mut().push_back(page as *mut Node<[u8;0]>);
+        self.free_list.push_back(page as *mut Node<[u8;0]>);
+    }
+
+    fn frame_refs(&self, frame: u64) -> Option<&'static AtomicU16> {
+        self.frame_refs.get(frame as usize)
+    }
+
+    /// Bytes of reference counts for frames up to `end`, in whole pages
+    pub fn frame_refs_size(end: u64) -> u64 {
+        (end / PAGE_SIZE * size_of::<AtomicU16>() as u64).next_multiple_of(PAGE_SIZE)
+    }
+
+    /// Keeps the reference counts for the frames below `end` at `table`
+    ///
+    /// # Safety
+    /// `table` has to be `frame_refs_size(end)` bytes nobody else uses
+    pub unsafe fn set_frame_refs(&mut self, table: *mut u8, end: u64) {
+        unsafe {
+            core::ptr::write_bytes(table, 0, Self::frame_refs_size(end) as usize);
+            self.frame_refs =
+                core::slice::from_raw_parts(table as *const AtomicU16, (end / PAGE_SIZE) as usize);
+        }
+    }
+
+    /// Adds a reference to the frame at `phys`. False if the frame cannot
+    /// be shared, the caller has to copy it instead
+    pub fn share_frame(&self, phys: u64) -> bool {
+        self.frame_refs(phys / PAGE_SIZE).is_some_and(|refs| {
+            refs.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_add(1))
+                .is_ok()
+        })
+    }
+
+    /// Drops a reference to the frame at `phys`. True if it was the last
+    /// one and the frame has to be freed
+    pub fn unshare_frame(&self, phys: u64) -> bool {
+        self.frame_refs(phys / PAGE_SIZE).map_or(true, |refs| {
+            refs.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+                .is_err()
+        })
+    }
+
+    /// How many owners the frame at `phys` has
+    pub fn frame_owners(&self, phys: u64) -> usize {
+        self.frame_refs(phys / PAGE_SIZE)
+            .map_or(1, |refs| refs.load(Ordering::Acquire) as usize + 1)
     }
 
     pub const fn default() -> Self {
         Self {
-            free_list: RefCell::new(LinkedList::default()),
+            free_list: LinkedList::default(),
             bootinfo: None,
             total_size: 0,
+            frame_refs: &[],
         }
     }
 
     pub fn print_reg(&self) {
-        for i in self.free_list.borrow().iter() {
+        for i in self.free_list.iter() {
             serial_info!("{:?}", unsafe { &*i });
         }
     }
@@ -46,9 +101,7 @@ impl<const PAGE_SIZE: u64> PageAlloc<PAGE_SIZE> {
         let mut i = start;
 
         while i < end {
-            self.free_list
-                .borrow_mut()
-                .push_back(Node::from(i as *mut u8));
+            self.free_list.push_back(Node::from(i as *mut u8));
             i += PAGE_SIZE;
             self.total_size += PAGE_SIZE;
         }
@@ -59,3 +112,50 @@ impl<const PAGE_SIZE: u64> PageAlloc<PAGE_SIZE> {
         }
     }
 }
+
+#[test_case]
+pub fn test_frame_refs() {
+    serial_info!("Testing frame reference counts");
+    static mut TABLE: [u16; 2048] = [u16::MAX; 2048];
+    let mut alloc = PageAlloc::<4096>::default();
+    let end = 2048 * 4096;
+    assert_eq!(PageAlloc::<4096>::frame_refs_size(end), 4096);
+    unsafe { alloc.set_frame_refs(core::ptr::addr_of_mut!(TABLE) as *mut u8, end) };
+    let phys = end - 4096;
+    assert_eq!(alloc.frame_owners(phys), 1);
+    assert!(alloc.share_frame(phys));
+    assert_eq!(alloc.frame_owners(phys), 2);
+    assert!(!alloc.unshare_frame(phys));
+    assert!(alloc.unshare_frame(phys));
+    // frames past the table can only ever have one owner
+    assert!(!alloc.share_frame(end));
+    assert!(alloc.unshare_frame(end));
+}
+
+#[test_case]
+pub fn test_high_frame_refs() {
+    serial_info!("Testing reference counts for the last usable frame");
+    let bootinfo = unsafe { crate::BOOT_INFO.expect("boot info not set up") };
+    let last = bootinfo
+        .memory_map
+        .iter()
+        .filter(|region| region.region_type == bootloader::bootinfo::MemoryRegionType::Usable)
+        .map(|region| region.range.end_addr())
+        .max()
+        .unwrap()
+        - 4096;
+    // past the first GiB on any machine with the 2 GiB qemu gets
+    assert!(last >= 1 << 30);
+    let alloc = crate::allocator::kernel_alloc::ALLOC.lock();
+    assert!(alloc.share_frame(last));
+    assert_eq!(alloc.frame_owners(last), 2);
+    assert!(!alloc.unshare_frame(last));
+}
+
+#[test_case]
+pub fn test_alloc_empty() {
+    serial_info!("Testing allocation from an empty page allocator");
+    let mut alloc = PageAlloc::<4096>::default();
+    assert!(alloc.alloc_page().is_null());
+    assert_eq!(alloc.total_size, 0);
+}
@@ -0,0 +1,14 @@
+pub mod percpu;
+pub mod smp;
+
+pub use percpu::{cpu_id, this_cpu, CpuLocal};
+
+pub const MAX_CPUS: usize = 16;
+
+/// One bit per cpu id
+pub type CpuMask = u32;
+pub const ALL_CPUS: CpuMask = ((1u64 << MAX_CPUS) - 1) as CpuMask;
+
+pub const fn cpu_mask(cpu_id: usize) -> CpuMask {
+    1 << cpu_id
+}
@@ -0,0 +1,268 @@
+use core::{arch::asm, mem::offset_of, sync::atomic::Ordering};
+
+use super::MAX_CPUS;
+use crate::{threading::PreemptGuard, utils::asm as x86};
+
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+/// `swapgs` exchanges it with `IA32_GS_BASE`
+pub const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// Per-CPU area, `IA32_GS_BASE` points at the one of the running CPU so the
+/// fields can be reached with a single `gs:` relative instruction. Ring 3
+/// runs with a GS base of 0 and the area in `IA32_KERNEL_GS_BASE`: every
+/// entry from ring 3 does `swapgs` first and every way back does it again
+/// last, so whatever ring 3 loads into GS never reaches the kernel. Ring 3
+/// can only load selectors, which all have base 0, so a thread that moved
+/// to another CPU in the kernel still goes back with the right GS base
+#[repr(C)]
+pub struct PerCpu {
+    /// Points back at this struct so `gs:[0]` gives a normal pointer
+    self_ptr: *const PerCpu,
+    cpu_id: usize,
+    current_thread: usize,
+    preempt_count: usize,
+    /// Interrupt handlers currently running on this CPU
+    irq_depth: usize,
+    need_resched: usize,
+    /// Kernel stack of the running thread, where syscalls switch to
+    kernel_stack: usize,
+    /// Where the syscall entry parks the user stack pointer
+    user_rsp: usize,
+}
+
+/// Offsets the syscall entry uses
+pub const KERNEL_STACK_OFFSET: usize = offset_of!(PerCpu, kernel_stack);
+pub const USER_RSP_OFFSET: usize = offset_of!(PerCpu, user_rsp);
+
+impl PerCpu {
+    pub const fn new(cpu_id: usize) -> Self {
+        Self {
+            self_ptr: core::ptr::null(),
+            cpu_id,
+            current_thread: 0,
+            preempt_count: 0,
+            irq_depth: 0,
+            need_resched: 0,
+            kernel_stack: 0,
+            user_rsp: 0,
+        }
+    }
+
+    pub fn cpu_id(&self) -> usize {
+        self.cpu_id
+    }
+
+    pub fn current_thread(&self) -> usize {
+        self.current_thread
+    }
+
+    pub fn preempt_count(&self) -> usize {
+        self.preempt_count
+    }
+
+    pub fn irq_depth(&self) -> usize {
+        self.irq_depth
+    }
+}
+
+static mut AREAS: [PerCpu; MAX_CPUS] = [const { PerCpu::new(0) }; MAX_CPUS];
+
+macro_rules! gs_read {
+    ($field:ident) => {{
+        let val: usize;
+        unsafe {
+            asm!(
+                "mov {}, qword ptr gs:[{off}]",
+                out(reg) val,
+                off = const offset_of!(PerCpu, $field),
+                options(nostack, preserves_flags, readonly)
+            )
+        };
+        val
+    }};
+}
+
+macro_rules! gs_write {
+    ($field:ident, $val:expr) => {{
+        let val: usize = $val;
+        unsafe {
+            asm!(
+                "mov qword ptr gs:[{off}], {}",
+                in(reg) val,
+                off = const offset_of!(PerCpu, $field),
+                options(nostack, preserves_flags)
+            )
+        };
+    }};
+}
+
+/// A single `inc`/`dec` cannot be torn by an interrupt on the same CPU
+macro_rules! gs_add {
+    ($field:ident, $op:literal) => {{
+        unsafe {
+            asm!(
+                concat!($op, " qword ptr gs:[{off}]"),
+                off = const offset_of!(PerCpu, $field),
+                options(nostack)
+            )
+        };
+    }};
+}
+
+/// Sets up the per-CPU area of `cpu_id` and points the GS base of the
+/// calling CPU at it. Has to run before anything that touches per-CPU state
+pub fn init(cpu_id: usize) {
+    assert!(cpu_id < MAX_CPUS, "cpu id {} out of range", cpu_id);
+    unsafe {
+        let area = core::ptr::addr_of_mut!(AREAS[cpu_id]);
+        *area = PerCpu::new(cpu_id);
+        (*area).self_ptr = area;
+        x86::wrmsr(IA32_GS_BASE, area as u64);
+        x86::wrmsr(IA32_KERNEL_GS_BASE, 0);
+    }
+    core::sync::atomic::fence(Ordering::SeqCst);
+}
+
+pub fn init_bsp() {
+    init(0);
+}
+
+pub fn this_cpu() -> &'static PerCpu {
+    let area = gs_read!(self_ptr) as *const PerCpu;
+    unsafe { &*area }
+}
+
+/// Per-CPU area of another CPU, for diagnostics
+pub fn cpu(cpu_id: usize) -> &'static PerCpu {
+    unsafe { &*core::ptr::addr_of!(AREAS[cpu_id]) }
+}
+
+pub fn cpu_id() -> usize {
+    gs_read!(cpu_id)
+}
+
+pub fn current_thread() -> usize {
+    gs_read!(current_thread)
+}
+
+pub fn set_current_thread(tid: usize) {
+    gs_write!(current_thread, tid)
+}
+
+pub fn set_kernel_stack(top: usize) {
+    gs_write!(kernel_stack, top)
+}
+
+pub fn preempt_count() -> usize {
+    gs_read!(preempt_count)
+}
+
+pub fn preempt_count_inc() {
+    gs_add!(preempt_count, "inc")
+}
+
+/// Returns the count from before the decrement
+pub fn preempt_count_dec() -> usize {
+    gs_add!(preempt_count, "dec");
+    gs_read!(preempt_count) + 1
+}
+
+pub fn need_resched() -> bool {
+    gs_read!(need_resched) != 0
+}
+
+pub fn set_need_resched(val: bool) {
+    gs_write!(need_resched, val as usize)
+}
+
+/// Called first thing in an interrupt handler
+pub fn irq_enter() {
+    gs_add!(irq_depth, "inc")
+}
+
+/// Called before the handler returns or switches threads
+pub fn irq_exit() {
+    gs_add!(irq_depth, "dec")
+}
+
+pub fn in_interrupt() -> bool {
+    gs_read!(irq_depth) != 0
+}
+
+/// A variable with one slot per CPU, declared with `cpu_local!`. The slots
+/// live in a plain array indexed by the cpu id from the GS area, not in the
+/// GS area itself, so any CPU can reach every slot and `T` has to be `Sync`.
+/// A thread only keeps to the slot of its CPU while it can't migrate
+pub struct CpuLocal<T> {
+    slots: [T; MAX_CPUS],
+}
+
+unsafe impl<T: Sync> Sync for CpuLocal<T> {}
+
+impl<T> CpuLocal<T> {
+    pub const fn new(slots: [T; MAX_CPUS]) -> Self {
+        Self { slots }
+    }
+
+    /// Slot of the running CPU, the guard keeps the thread from migrating
+    /// for as long as the reference is around
+    pub fn get<'a>(&'a self, _guard: &'a PreemptGuard) -> &'a T {
+        &self.slots[cpu_id()]
+    }
+
+    /// Runs `f` on the slot of the running CPU with interrupts disabled
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        x86::without_interrupts(|| f(&self.slots[cpu_id()]))
+    }
+
+    pub fn get_for(&self, cpu_id: usize) -> &T {
+        &self.slots[cpu_id]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter()
+    }
+}
+
+/// Declares a `CpuLocal` static, the initializer has to be a constant
+/// expression and is evaluated once per CPU slot
+///
+/// ```ignore
+/// cpu_local! {
+///     static TICKS: AtomicU64 = AtomicU64::new(0);
+/// }
+/// TICKS.with(|ticks| ticks.fetch_add(1, Ordering::Relaxed));
+/// ```
+#[macro_export]
+macro_rules! cpu_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::cpu::percpu::CpuLocal<$ty> =
+                $crate::cpu::percpu::CpuLocal::new([const { $init }; $crate::cpu::MAX_CPUS]);
+        )*
+    };
+}
+
+#[test_case]
+pub fn test_cpu_local() {
+    use core::sync::atomic::AtomicUsize;
+    crate::serial_info!("Testing per-CPU area and cpu_local");
+    cpu_local! {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    }
+    assert_eq!(cpu_id(), 0);
+    assert!(core::ptr::eq(this_cpu(), cpu(0)));
+    let guard = PreemptGuard::new();
+    COUNTER.get(&guard).fetch_add(1, Ordering::Relaxed);
+    drop(guard);
+    COUNTER.with(|counter| counter.fetch_add(1, Ordering::Relaxed));
+    assert_eq!(COUNTER.get_for(0).load(Ordering::Relaxed), 2);
+    assert_eq!(COUNTER.get_for(1).load(Ordering::Relaxed), 0);
+
+    let before = preempt_count();
+    preempt_count_inc();
+    assert_eq!(this_cpu().preempt_count(), before + 1);
+    assert_eq!(preempt_count_dec(), before + 1);
+    assert_eq!(preempt_count(), before);
+}
@@ -0,0 +1,300 @@
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bootloader::bootinfo::MemoryRegionType;
+
+use super::{percpu, MAX_CPUS};
+use crate::{
+    acpi::madt,
+    descriptors::gdt,
+    devices::lapic::LocalApic,
+    interrupts::{setup::load_idt, timer::PIT_},
+    io::time::{self, Instant},
+    paging::{self, PageTableFlags, PAGE_SIZE},
+    serial_info, syscall, threading,
+    utils::asm,
+    BOOT_INFO,
+};
+
+/// Real mode code has to start on a page boundary below 1 MiB, the SIPI
+/// vector is this address shifted right by 12
+const TRAMPOLINE_PHYS: u64 = 0x8000;
+const AP_STACK_SIZE: usize = 4096 * 4;
+const AP_START_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[repr(C, align(16))]
+struct ApStack([u8; AP_STACK_SIZE]);
+
+static mut AP_STACKS: [ApStack; MAX_CPUS] = [const { ApStack([0; AP_STACK_SIZE]) }; MAX_CPUS];
+
+/// Cpu id the BSP is currently starting, 0 if none. The AP claims it as soon
+/// as it is off the trampoline and sets it back to 0 once it is set up, so a
+/// late AP from an earlier timed out start can't take over a slot that isn't
+/// its own
+static AP_STARTING: AtomicUsize = AtomicUsize::new(0);
+const AP_CLAIMED: usize = usize::MAX;
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(1);
+/// Local APIC id of every cpu id handed out
+static APIC_IDS: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(0) }; MAX_CPUS];
+
+// The trampoline is copied to TRAMPOLINE_PHYS so every address in it is
+// computed relative to that. It goes real mode -> protected mode -> long
+// mode on the kernel page tables and calls ap_entry(cpu_id) on its own stack.
+// The four quads at the end are filled in by the BSP before each startup
+global_asm!(
+    r#"
+.pushsection .text.ap_trampoline, "ax"
+.code16
+.global ap_trampoline_start
+ap_trampoline_start:
+    cli
+    cld
+    xorw %ax, %ax
+    movw %ax, %ds
+    lgdtl (ap_tramp_gdtr - ap_trampoline_start + 0x8000)
+    movl %cr0, %eax
+    orl $1, %eax
+    movl %eax, %cr0
+    ljmpl $0x08, $(ap_tramp_32 - ap_trampoline_start + 0x8000)
+
+.code32
+ap_tramp_32:
+    movw $0x10, %ax
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %ss
+    // PAE
+    movl %cr4, %eax
+    orl $(1 << 5), %eax
+    movl %eax, %cr4
+    movl (ap_tramp_cr3 - ap_trampoline_start + 0x8000), %eax
+    movl %eax, %cr3
+    // EFER.LME and EFER.NXE, the kernel page tables use the NX bit
+    movl $0xc0000080, %ecx
+    rdmsr
+    orl $((1 << 8) | (1 << 11)), %eax
+    wrmsr
+    // PG and WP
+    movl %cr0, %eax
+    orl $((1 << 31) | (1 << 16)), %eax
+    movl %eax, %cr0
+    ljmpl $0x18, $(ap_tramp_64 - ap_trampoline_start + 0x8000)
+
+.code64
+ap_tramp_64:
+    xorw %ax, %ax
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %ss
+    movq (ap_tramp_stack - ap_trampoline_start + 0x8000), %rsp
+    movq (ap_tramp_cpu_id - ap_trampoline_start + 0x8000), %rdi
+    movq (ap_tramp_entry - ap_trampoline_start + 0x8000), %rax
+    callq *%rax
+    ud2
+
+.balign 8
+ap_tramp_gdt:
+    .quad 0
+    .quad 0x00cf9a000000ffff
+    .quad 0x00cf92000000ffff
+    .quad 0x00af9a000000ffff
+ap_tramp_gdtr:
+    .word ap_tramp_gdtr - ap_tramp_gdt - 1
+    .long ap_tramp_gdt - ap_trampoline_start + 0x8000
+
+.balign 8
+.global ap_tramp_cr3
+ap_tramp_cr3:
+    .quad 0
+.global ap_tramp_stack
+ap_tramp_stack:
+    .quad 0
+.global ap_tramp_entry
+ap_tramp_entry:
+    .quad 0
+.global ap_tramp_cpu_id
+ap_tramp_cpu_id:
+    .quad 0
+.global ap_trampoline_end
+ap_trampoline_end:
+.popsection
+"#,
+    options(att_syntax)
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+    static ap_tramp_cr3: u8;
+    static ap_tramp_stack: u8;
+    static ap_tramp_entry: u8;
+    static ap_tramp_cpu_id: u8;
+}
+
+/// Where a trampoline symbol ended up in the copy at TRAMPOLINE_PHYS
+fn trampoline_field(symbol: *const u8) -> *mut u64 {
+    let offset = symbol as u64 - core::ptr::addr_of!(ap_trampoline_start) as u64;
+    paging::phys_to_virt(TRAMPOLINE_PHYS + offset) as *mut u64
+}
+
+/// Copies the trampoline below 1 MiB and identity maps it so the AP keeps
+/// running once it turns paging on. Returns whether the identity mapping
+/// was added by us
+fn install_trampoline() -> Result<bool, &'static str> {
+    let boot_info = unsafe { BOOT_INFO.ok_or("boot info not set up")? };
+    let region = boot_info
+        .memory_map
+        .iter()
+        .find(|region| {
+            (region.range.start_addr()..region.range.end_addr()).contains(&TRAMPOLINE_PHYS)
+        })
+        .ok_or("trampoline page is not in the memory map")?;
+    // the bootloader is done with its own memory once the kernel runs
+    if region.region_type != MemoryRegionType::Bootloader {
+        return Err("trampoline page is in use");
+    }
+
+    unsafe {
+        let start = core::ptr::addr_of!(ap_trampoline_start);
+        let len = core::ptr::addr_of!(ap_trampoline_end) as usize - start as usize;
+        assert!(
+            len as u64 <= PAGE_SIZE,
+            "ap trampoline does not fit in a page"
+        );
+        core::ptr::copy_nonoverlapping(start, paging::phys_to_virt(TRAMPOLINE_PHYS), len);
+    }
+
+    match paging::translate(TRAMPOLINE_PHYS) {
+        Some(TRAMPOLINE_PHYS) => Ok(false),
+        Some(_) => Err("trampoline address is mapped elsewhere"),
+        None => {
+            paging::map_page(TRAMPOLINE_PHYS, TRAMPOLINE_PHYS, PageTableFlags::WRITABLE)?;
+            Ok(true)
+        }
+    }
+}
+
+fn start_ap(lapic: &LocalApic, apic_id: u8, cpu_id: usize) -> Result<(), &'static str> {
+    let cr3 = paging::read_cr3();
+    if cr3 >> 32 != 0 {
+        return Err("kernel page tables are above 4 GiB");
+    }
+    let stack_top =
+        unsafe { core::ptr::addr_of_mut!(AP_STACKS[cpu_id]) as u64 } + AP_STACK_SIZE as u64;
+    unsafe {
+        trampoline_field(core::ptr::addr_of!(ap_tramp_cr3)).write_volatile(cr3);
+        trampoline_field(core::ptr::addr_of!(ap_tramp_stack)).write_volatile(stack_top);
+        trampoline_field(core::ptr::addr_of!(ap_tramp_entry))
+            .write_volatile(ap_entry as extern "C" fn(usize) -> ! as usize as u64);
+        trampoline_field(core::ptr::addr_of!(ap_tramp_cpu_id)).write_volatile(cpu_id as u64);
+    }
+    APIC_IDS[cpu_id].store(apic_id, Ordering::Release);
+    AP_STARTING.store(cpu_id, Ordering::SeqCst);
+
+    // INIT, wait 10ms, then up to two SIPIs 200us apart
+    lapic.clear_errors();
+    lapic.send_init(apic_id);
+    time::sleep(Duration::from_millis(10));
+    let vector = (TRAMPOLINE_PHYS / PAGE_SIZE) as u8;
+    for _ in 0..2 {
+        lapic.send_startup(apic_id, vector);
+        PIT_.busy_wait_us(200);
+        if AP_STARTING.load(Ordering::Acquire) == 0 {
+            return Ok(());
+        }
+    }
+
+    let deadline = Instant::now() + AP_START_TIMEOUT;
+    while AP_STARTING.load(Ordering::Acquire) != 0 {
+        if deadline.has_passed() {
+            // Once the AP has claimed its slot it only has its own setup
+            // left, so only give up on one that never got there
+            if AP_STARTING
+                .compare_exchange(cpu_id, 0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return Err("application processor did not come up");
+            }
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+/// Finds the other CPUs in the MADT and brings them up one at a time.
+/// Needs the page allocator and a running timer. Returns how many CPUs are
+/// online afterwards. A cpu id and its stack are never handed out again after
+/// a failed start, the AP might still show up late and use them
+pub fn start_aps() -> Result<usize, &'static str> {
+    let madt = madt::parse()?;
+    LocalApic::setup(madt.lapic_addr)?;
+    let lapic = LocalApic::get().ok_or("local apic not set up")?;
+    lapic.enable();
+    let bsp_apic_id = lapic.id();
+    APIC_IDS[0].store(bsp_apic_id, Ordering::Release);
+
+    let identity_mapped = install_trampoline()?;
+    let ap_ids = madt.apic_ids().iter().filter(|&&id| id != bsp_apic_id);
+    for (cpu_id, &apic_id) in (1..).zip(ap_ids) {
+        if cpu_id == MAX_CPUS {
+            serial_info!("more than {} cpus, ignoring the rest", MAX_CPUS);
+            break;
+        }
+        if let Err(err) = start_ap(&lapic, apic_id, cpu_id) {
+            serial_info!("cpu with apic id {} failed to start: {}", apic_id, err);
+        }
+    }
+    if identity_mapped {
+        paging::unmap_page(TRAMPOLINE_PHYS);
+    }
+    Ok(online_cpus())
+}
+
+pub fn online_cpus() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// Where IPIs for `cpu_id` have to be sent
+pub fn apic_id(cpu_id: usize) -> u8 {
+    APIC_IDS[cpu_id].load(Ordering::Acquire)
+}
+
+/// First Rust code an AP runs, still on the stack the BSP handed it. An AP
+/// whose start already timed out parks itself without touching anything
+extern "C" fn ap_entry(cpu_id: usize) -> ! {
+    if AP_STARTING
+        .compare_exchange(cpu_id, AP_CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        loop {
+            asm::disable_interrupts();
+            asm::hlt();
+        }
+    }
+    percpu::init(cpu_id);
+    gdt::init_cpu(cpu_id);
+    syscall::entry::init_cpu();
+    load_idt();
+    if let Some(lapic) = LocalApic::get() {
+        lapic.enable();
+    }
+    ONLINE_CPUS.fetch_add(1, Ordering::AcqRel);
+    AP_STARTING.store(0, Ordering::Release);
+    ap_main(cpu_id)
+}
+
+/// Where every AP ends up once it is set up. It joins the scheduler if
+/// threads are up, otherwise it just parks itself
+pub fn ap_main(cpu_id: usize) -> ! {
+    serial_info!("cpu {} online", cpu_id);
+    if threading::initialized() {
+        threading::start_cpu(cpu_id);
+    }
+    loop {
+        asm::disable_interrupts();
+        asm::hlt();
+    }
+}
@@ -40,6 +40,11 @@ where
         Ok(())
     }
 
+    /// The queued elements from the oldest on, without taking them
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).filter_map(move |i| self.buf[(self.start + i) % SIZE])
+    }
+
     pub fn take(&mut self) -> Option<T> {
         if self.empty() {
             return None;
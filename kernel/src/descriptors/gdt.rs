@@ -2,7 +2,8 @@ use core::fmt::Debug;
 
 use bitfield_struct::bitfield;
 
-use crate::{utils::asm, serial_info};
+use super::tss::TaskStateSegment;
+use crate::{cpu::MAX_CPUS, serial_info, utils::asm};
 
 #[bitfield(u64)]
 pub struct GdtEntry {
@@ -43,6 +44,107 @@ pub struct GdtEntry {
     base2: u8,
 }
 
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+/// User data comes before user code, the order `sysret` expects
+pub const USER_DATA_SELECTOR: u16 = 0x18 | 3;
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+pub const TSS_SELECTOR: u16 = 0x28;
+
+const GRANULARITY_4K: u8 = 0b1000;
+const SIZE_32: u8 = 0b0100;
+const LONG_MODE: u8 = 0b0010;
+/// Access type of an available 64 bit TSS
+const TSS_AVAILABLE: u64 = 0x9 << 40;
+
+impl GdtEntry {
+    /// Flat segment, base and limit are ignored in long mode apart from
+    /// the flags
+    pub fn segment(executable: bool, dpl: u8) -> Self {
+        Self::new()
+            .with_limit(0xffff)
+            .with_limit2(0xf)
+            .with_rw(true)
+            .with_executable(executable)
+            .with_descriptor_type(true)
+            .with_privelege_level(dpl)
+            .with_present(true)
+            .with_flags(if executable {
+                GRANULARITY_4K | LONG_MODE
+            } else {
+                GRANULARITY_4K | SIZE_32
+            })
+    }
+}
+
+/// A system descriptor takes two slots, the second one holds the upper
+/// half of the base
+fn tss_descriptor(tss: *const TaskStateSegment) -> [u64; 2] {
+    let base = tss as u64;
+    let limit = core::mem::size_of::<TaskStateSegment>() as u64 - 1;
+    let low = (limit & 0xffff)
+        | (base & 0xff_ffff) << 16
+        | TSS_AVAILABLE
+        | 1 << 47
+        | ((base >> 24) & 0xff) << 56;
+    [low, base >> 32]
+}
+
+/// The small GDT every cpu loads for itself so that it can have its own TSS
+#[repr(C, align(16))]
+pub struct CpuGdt {
+    entries: [u64; 7],
+}
+
+static mut CPU_GDTS: [CpuGdt; MAX_CPUS] = [const { CpuGdt { entries: [0; 7] } }; MAX_CPUS];
+static mut CPU_TSS: [TaskStateSegment; MAX_CPUS] = [const { TaskStateSegment::new() }; MAX_CPUS];
+
+/// Builds and loads the GDT and TSS of `cpu_id` on the calling cpu, then
+/// reloads the segment registers. FS and GS are left alone so the per-CPU
+/// GS base survives
+pub fn init_cpu(cpu_id: usize) {
+    unsafe {
+        let tss = core::ptr::addr_of_mut!(CPU_TSS[cpu_id]);
+        *tss = TaskStateSegment::new();
+        let gdt = &mut *core::ptr::addr_of_mut!(CPU_GDTS[cpu_id]);
+        let [tss_low, tss_high] = tss_descriptor(tss);
+        gdt.entries = [
+            0,
+            GdtEntry::segment(true, 0).into(),
+            GdtEntry::segment(false, 0).into(),
+            GdtEntry::segment(false, 3).into(),
+            GdtEntry::segment(true, 3).into(),
+            tss_low,
+            tss_high,
+        ];
+        let pointer = GdtPointer {
+            size: core::mem::size_of::<CpuGdt>() as u16 - 1,
+            offset: gdt as *const CpuGdt as *const GlobalDescriptorTable,
+        };
+        asm::lgdt(&pointer);
+        core::arch::asm!(
+            "push {cs}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {ds:x}",
+            "mov es, {ds:x}",
+            "mov ss, {ds:x}",
+            "ltr {tss:x}",
+            cs = in(reg) KERNEL_CODE_SELECTOR as u64,
+            tmp = out(reg) _,
+            ds = in(reg) KERNEL_DATA_SELECTOR,
+            tss = in(reg) TSS_SELECTOR,
+        );
+    }
+}
+
+/// TSS of `cpu_id`, for setting the ring 0 and IST stacks
+pub fn tss(cpu_id: usize) -> &'static mut TaskStateSegment {
+    unsafe { &mut *core::ptr::addr_of_mut!(CPU_TSS[cpu_id]) }
+}
+
 #[repr(C)]
 pub struct GlobalDescriptorTable {
     null_descriptor: GdtEntry,
@@ -104,3 +206,12 @@ pub fn test_gdtsize() {
         8192 * core::mem::size_of::<GdtEntry>()
     )
 }
+
+#[test_case]
+pub fn test_segment_descriptors() {
+    serial_info!("Testing flat segment descriptors");
+    assert_eq!(u64::from(GdtEntry::segment(true, 0)), 0x00af_9a00_0000_ffff);
+    assert_eq!(u64::from(GdtEntry::segment(false, 0)), 0x00cf_9200_0000_ffff);
+    assert_eq!(u64::from(GdtEntry::segment(false, 3)), 0x00cf_f200_0000_ffff);
+    assert_eq!(u64::from(GdtEntry::segment(true, 3)), 0x00af_fa00_0000_ffff);
+}
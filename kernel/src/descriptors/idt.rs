@@ -83,6 +83,12 @@ impl InterruptDescriptorTable {
             interrupts: [Entry::missing(); 256 - 32],
         }
     }
+    /// Gate settings of `vector`, whatever its handler type
+    pub fn options(&self, vector: u8) -> IdtSettings {
+        let entries = self as *const Self as *const Entry<HandlerFunc>;
+        unsafe { (*entries.add(vector as usize)).options }
+    }
+
     pub fn load(&'static self) {
         unsafe {
             let ptr = DescriptorPointer {
@@ -94,27 +100,26 @@ impl InterruptDescriptorTable {
     }
 }
 
+/// Interrupt gates clear IF on entry, trap gates leave it alone
 pub enum GateType {
-    TrapGate = 0xE,
-    InterruptGate = 0xF,
+    InterruptGate = 0xE,
+    TrapGate = 0xF,
 }
 
 impl GateType {
     pub const fn from_bits(val: u16) -> Self {
-        let _v1 = 0xE << 8;
-        let _v2 = 0xF << 8;
-        if val == _v1 {
-            return Self::TrapGate;
-        } else if val == _v2 {
+        if val == 0xE {
             return Self::InterruptGate;
+        } else if val == 0xF {
+            return Self::TrapGate;
         } else {
             panic!("invalid gate type");
         }
     }
     pub const fn into_bits(self) -> u16 {
         match self {
-            Self::TrapGate => 0xE,
-            Self::InterruptGate => 0xF,
+            Self::InterruptGate => 0xE,
+            Self::TrapGate => 0xF,
         }
     }
 }
@@ -172,7 +177,7 @@ impl<T> fmt::Debug for Entry<T> {
 
 pub type HandlerFunc = extern "x86-interrupt" fn(ExceptionStackFrame);
 pub type HandlerFuncWithErrCode = extern "x86-interrupt" fn(ExceptionStackFrame, error_code: u64);
-pub type PageFaultHandlerFunc = HandlerFunc;
+pub type PageFaultHandlerFunc = HandlerFuncWithErrCode;
 pub type DivergingHandlerFunc = extern "x86-interrupt" fn(ExceptionStackFrame) -> !;
 pub type DivergingHandlerFuncWithErrCode =
     extern "x86-interrupt" fn(ExceptionStackFrame, error_code: u64) -> !;
@@ -190,6 +195,33 @@ impl<F> Entry<F> {
             phantom: PhantomData,
         }
     }
+    /// Points the gate at `addr` and marks it present. For handlers that
+    /// are not Rust functions, such as assembly stubs
+    ///
+    /// # Safety
+    /// `addr` has to be code that can be entered the way a gate of this
+    /// type enters it
+    pub unsafe fn set_handler_addr(&mut self, addr: u64) -> &mut Self {
+        self.pointer_low = addr as u16;
+        self.pointer_middle = (addr >> 16) as u16;
+        self.pointer_high = (addr >> 32) as u32;
+        self.gdt_selector = CS::get_reg();
+        self.options.set_present(true);
+        self
+    }
+
+    pub fn set_gate_type(&mut self, gate_type: GateType) -> &mut Self {
+        self.options.set_gate_type(gate_type);
+        self
+    }
+
+    /// Least privileged ring allowed to raise the vector with `int`, 3
+    /// opens it to user code. Hardware interrupts and exceptions ignore it
+    pub fn set_privilege_level(&mut self, dpl: u8) -> &mut Self {
+        self.options.set_privilege_level(dpl);
+        self
+    }
+
     pub fn handler_addr(&self) -> u64 {
         let addr = self.pointer_low as u64
             | (self.pointer_middle as u64) << 16
@@ -202,13 +234,7 @@ macro_rules! impl_set_handler_fn {
     ($h:ty) => {
         impl Entry<$h> {
             pub fn set_handler_fn(&mut self, handler: $h) -> &mut Self {
-                let addr = handler as u64;
-                self.pointer_low = addr as u16;
-                self.pointer_middle = (addr >> 16) as u16;
-                self.pointer_high = (addr >> 32) as u32;
-                self.gdt_selector = CS::get_reg();
-                self.options.set_present(true);
-                self
+                unsafe { self.set_handler_addr(handler as u64) }
             }
         }
     };
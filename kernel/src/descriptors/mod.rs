@@ -1,3 +1,4 @@
 pub mod gdt;
 pub mod idt;
 pub mod reg;
+pub mod tss;
@@ -0,0 +1,29 @@
+/// 64 bit Task State Segment, only used for the stacks the cpu switches to
+/// when an interrupt arrives from ring 3 or through an IST entry
+#[repr(C, packed(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    /// Stacks loaded on a privilege change, `rsp[0]` is used when entering ring 0
+    pub privilege_stack_table: [u64; 3],
+    reserved_2: u64,
+    pub interrupt_stack_table: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        Self {
+            reserved_1: 0,
+            privilege_stack_table: [0; 3],
+            reserved_2: 0,
+            interrupt_stack_table: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            // past the limit, so there is no io permission bitmap
+            iomap_base: core::mem::size_of::<Self>() as u16,
+        }
+    }
+}
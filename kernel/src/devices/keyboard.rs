@@ -3,7 +3,13 @@
 use bitfield_struct::bitfield;
 
 use super::{port::Port, vga::ConsoleDisplay};
-use crate::{datastructures::no_alloc::ringbuffer::RingBuf, io::writer::WRITER};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    io::writer::WRITER,
+    sync::waitqueue::WaitQueue,
+    threading::{self, policy::EnqueueReason},
+    utils::asm,
+};
 #[derive(Debug, Clone, Copy)]
 pub enum Key {
     Char(char),
@@ -427,7 +433,11 @@ pub struct Keyboard {
     pub shifter: bool,
     pub caps_lock: bool,
     pub caps_lock_pressed: bool,
+    /// Control as seen by the interrupt, which runs ahead of the buffer
+    ctrl_held: bool,
     buffer: RingBuf<KeyAction, 100>,
+    /// Threads blocked in `process_buf_wait`
+    waiters: WaitQueue,
 }
 
 impl Keyboard {
@@ -438,7 +448,9 @@ impl Keyboard {
             shifter: false,
             caps_lock: false,
             caps_lock_pressed: false,
+            ctrl_held: false,
             buffer: RingBuf::new(),
+            waiters: WaitQueue::new(),
         }
     }
 
@@ -527,8 +539,27 @@ impl Keyboard {
     }
 
     pub fn read_into_buf(&mut self) -> Result<(), &'static str> {
-        let action = self.read_raw();
-        self.buffer.push(action)
+        let code = self.scan_code();
+        self.push_scan_code(code)
+    }
+
+    /// Called from the interrupt for every scancode before it is queued,
+    /// true for the C of a Ctrl-C, which is not meant to be typed
+    pub fn is_interrupt_key(&mut self, code: u8) -> bool {
+        use Key::*;
+        use KeyAction::*;
+        match map_val_to_key_scan_code_1(code) {
+            Press(LCtrl) => self.ctrl_held = true,
+            Release(LCtrl) => self.ctrl_held = false,
+            Press(Char('c')) => return self.ctrl_held,
+            _ => {}
+        }
+        false
+    }
+
+    /// Queues the key action for a scancode that was already read
+    pub fn push_scan_code(&mut self, code: u8) -> Result<(), &'static str> {
+        self.buffer.push(map_val_to_key_scan_code_1(code))
     }
 
     pub fn process_buf(&mut self) -> Option<char> {
@@ -537,16 +568,59 @@ impl Keyboard {
         return modifier;
     }
 
+    /// Next printable key if one is already buffered, never blocks
+    pub fn poll_char(&mut self) -> Option<char> {
+        loop {
+            let action = asm::without_interrupts(|| self.buffer.take())?;
+            if let Some(c) = self.set_modifier(action) {
+                return Some(c);
+            }
+        }
+    }
+
+    /// Blocks on the keyboard interrupt until a printable key arrives
     pub fn process_buf_wait(&mut self) -> char {
+        self.process_buf_wait_or(|| false)
+            .expect("uninterruptible wait gave up")
+    }
+
+    /// Like `process_buf_wait`, but gives up with None once `interrupted`
+    /// holds on a wakeup
+    pub fn process_buf_wait_or(&mut self, interrupted: impl Fn() -> bool) -> Option<char> {
         loop {
-            let val = self.process_buf();
-            if let Some(c) = val {
+            asm::disable_interrupts();
+            let Some(action) = self.buffer.take() else {
+                if interrupted() {
+                    asm::enable_interrupts();
+                    return None;
+                }
+                self.wait_for_input();
+                continue;
+            };
+            asm::enable_interrupts();
+            if let Some(c) = self.set_modifier(action) {
                 WRITER.take().display.put_byte(c as u8);
-                return c;
+                return Some(c);
             }
         }
     }
 
+    /// Blocks the calling thread until the next keyboard interrupt, or just
+    /// halts if threads are not up yet. Expects interrupts to be disabled
+    fn wait_for_input(&mut self) {
+        if threading::initialized() {
+            self.waiters.park(None);
+            asm::enable_interrupts();
+        } else {
+            asm::enable_interrupts_and_hlt();
+        }
+    }
+
+    /// Called from the keyboard interrupt, wakes whoever is waiting on input
+    pub fn wake_waiter(&mut self) {
+        self.waiters.wake_one_with(EnqueueReason::InteractiveWakeup);
+    }
+
     pub fn read_raw(&self) -> KeyAction {
         map_val_to_key_scan_code_1(self.scan_code())
     }
@@ -0,0 +1,108 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    paging::{self, PageTableFlags},
+    utils::asm,
+};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+const REG_ID: u32 = 0x20;
+const REG_EOI: u32 = 0xb0;
+const REG_SPURIOUS: u32 = 0xf0;
+const REG_ERROR_STATUS: u32 = 0x280;
+const REG_ICR_LOW: u32 = 0x300;
+const REG_ICR_HIGH: u32 = 0x310;
+
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_ASSERT: u32 = 1 << 14;
+
+/// Virtual address of the register page, shared by every CPU since each
+/// one sees its own Local APIC at the same physical address
+static LAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+
+pub struct LocalApic {
+    base: u64,
+}
+
+impl LocalApic {
+    /// Reaches the register page through the physical memory window, mapping
+    /// it uncached if the bootloader did not. Only needs to run once, on the BSP
+    pub fn setup(phys: u64) -> Result<(), &'static str> {
+        let virt = paging::phys_offset() + phys;
+        if paging::translate(virt).is_none() {
+            paging::map_page(
+                virt,
+                phys,
+                PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE | PageTableFlags::NO_EXECUTE,
+            )?;
+        }
+        LAPIC_BASE.store(virt, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn get() -> Option<Self> {
+        match LAPIC_BASE.load(Ordering::Acquire) {
+            0 => None,
+            base => Some(Self { base }),
+        }
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        unsafe { ((self.base + reg as u64) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, reg: u32, val: u32) {
+        unsafe { ((self.base + reg as u64) as *mut u32).write_volatile(val) }
+    }
+
+    pub fn id(&self) -> u8 {
+        (self.read(REG_ID) >> 24) as u8
+    }
+
+    /// Software enables the Local APIC of the calling CPU
+    pub fn enable(&self) {
+        unsafe {
+            let base = asm::rdmsr(IA32_APIC_BASE);
+            asm::wrmsr(IA32_APIC_BASE, base | APIC_BASE_ENABLE);
+        }
+        self.write(REG_SPURIOUS, SPURIOUS_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    pub fn eoi(&self) {
+        self.write(REG_EOI, 0);
+    }
+
+    pub fn clear_errors(&self) {
+        // the register is latched by a write
+        self.write(REG_ERROR_STATUS, 0);
+    }
+
+    fn send_ipi(&self, apic_id: u8, icr: u32) {
+        self.write(REG_ICR_HIGH, (apic_id as u32) << 24);
+        self.write(REG_ICR_LOW, icr);
+        while self.read(REG_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn send_init(&self, apic_id: u8) {
+        self.send_ipi(apic_id, ICR_INIT | ICR_ASSERT);
+    }
+
+    /// Raises interrupt `vector` on the CPU with `apic_id`
+    pub fn send_fixed(&self, apic_id: u8, vector: u8) {
+        self.send_ipi(apic_id, ICR_ASSERT | vector as u32);
+    }
+
+    /// The target starts executing in real mode at `vector << 12`
+    pub fn send_startup(&self, apic_id: u8, vector: u8) {
+        self.send_ipi(apic_id, ICR_STARTUP | ICR_ASSERT | vector as u32);
+    }
+}
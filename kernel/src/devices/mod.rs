@@ -1,6 +1,8 @@
 pub mod keyboard;
+pub mod lapic;
 pub mod pic8259;
 pub mod pit;
 pub mod port;
 pub mod serial;
+pub mod speaker;
 pub mod vga;
@@ -4,44 +4,223 @@ use crate::{info, kprintln, ksprintln, serial_info};
 
 use super::port::Port;
 
-const TIMER_RATE: u32 = 1193182;
+pub const TIMER_RATE: u32 = 1193182;
 const TIMER_IDT_ENTRY: usize = 0x20;
 const TIMER_PERIOD_IO_PORT: u16 = 0x40;
 const TIMER_MODE_IO_PORT: u16 = 0x43;
+/// Channel 2 gate (bit 0) and output (bit 5) live on the keyboard controller port B
+const TIMER_GATE_IO_PORT: u16 = 0x61;
 const TIMER_SQUARE_WAVE: u8 = 0x36;
 const TIMER_ONE_SHOT: u8 = 0x30;
+const TIMER_READ_BACK: u8 = 0xC0;
 
 #[derive(Clone, Copy)]
 pub struct PitTimerEvent;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Channel {
+    Ch0 = 0,
+    Ch1 = 1,
+    Ch2 = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccessMode {
+    Latch = 0,
+    LoByte = 1,
+    HiByte = 2,
+    LoHiByte = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OperatingMode {
+    /// Mode 0: output goes high once the count reaches zero
+    InterruptOnTerminalCount = 0,
+    /// Mode 1: retriggered by the gate, only usable on channel 2
+    HardwareOneShot = 1,
+    /// Mode 2: one low pulse every `count` cycles
+    RateGenerator = 2,
+    /// Mode 3: 50% duty cycle at `TIMER_RATE / count`
+    SquareWave = 3,
+    /// Mode 4: one low pulse after `count` cycles
+    SoftwareStrobe = 4,
+    /// Mode 5: same as 4 but started by the gate
+    HardwareStrobe = 5,
+}
+
+pub const fn command_byte(channel: Channel, access: AccessMode, mode: OperatingMode) -> u8 {
+    ((channel as u8) << 6) | ((access as u8) << 4) | ((mode as u8) << 1)
+}
+
+/// PIT input cycles for an interval, clamped to what the 16 bit counter can hold
+pub const fn ms_to_count(interval_ms: u32) -> u16 {
+    let count = (TIMER_RATE as u64 * interval_ms as u64) / 1000;
+    if count > u16::MAX as u64 {
+        u16::MAX
+    } else if count == 0 {
+        1
+    } else {
+        count as u16
+    }
+}
+
+pub const fn hz_to_count(freq_hz: u32) -> u16 {
+    if freq_hz == 0 {
+        return u16::MAX;
+    }
+    let count = TIMER_RATE / freq_hz;
+    if count > u16::MAX as u32 {
+        u16::MAX
+    } else if count == 0 {
+        1
+    } else {
+        count as u16
+    }
+}
+
 #[allow(unused)]
 pub struct PIT {
     ch0: Port,
     ch1: Port,
     ch2: Port,
     cmd: Port,
+    gate: Port,
 }
 
 impl PIT {
     pub const fn new() -> Self {
         Self {
-            ch0: Port(TIMER_MODE_IO_PORT),
-            ch1: Port(TIMER_MODE_IO_PORT + 1),
-            ch2: Port(TIMER_MODE_IO_PORT + 2),
-            cmd: Port(TIMER_MODE_IO_PORT + 3),
+            ch0: Port(TIMER_PERIOD_IO_PORT),
+            ch1: Port(TIMER_PERIOD_IO_PORT + 1),
+            ch2: Port(TIMER_PERIOD_IO_PORT + 2),
+            cmd: Port(TIMER_MODE_IO_PORT),
+            gate: Port(TIMER_GATE_IO_PORT),
         }
     }
 
+    fn data_port(&self, channel: Channel) -> Port {
+        match channel {
+            Channel::Ch0 => self.ch0,
+            Channel::Ch1 => self.ch1,
+            Channel::Ch2 => self.ch2,
+        }
+    }
+
+    /// Programs `channel` in `mode` and loads the reload value, 0 means 65536
+    pub fn program(&self, channel: Channel, mode: OperatingMode, count: u16) {
+        let port = self.data_port(channel);
+        self.cmd
+            .send_byte(command_byte(channel, AccessMode::LoHiByte, mode));
+        port.send_byte(count as u8);
+        port.send_byte((count >> 8) as u8);
+    }
+
+    pub fn set_frequency(&self, channel: Channel, mode: OperatingMode, freq_hz: u32) {
+        self.program(channel, mode, hz_to_count(freq_hz));
+    }
+
+    /// Latches the current count of `channel` and reads it back
+    pub fn read_count(&self, channel: Channel) -> u16 {
+        let port = self.data_port(channel);
+        self.cmd.send_byte((channel as u8) << 6);
+        let lsb = port.read_byte() as u16;
+        let msb = port.read_byte() as u16;
+        (msb << 8) | lsb
+    }
+
+    /// Read-back command returning the status byte of `channel`
+    /// bit 7 is the output pin, 5:4 the access mode and 3:1 the operating mode
+    pub fn read_status(&self, channel: Channel) -> u8 {
+        self.cmd
+            .send_byte(TIMER_READ_BACK | (1 << 5) | (1 << (channel as u8 + 1)));
+        self.data_port(channel).read_byte()
+    }
+
+    /// Periodic interrupts on channel 0 every `interval_ms`
     pub fn setup(&self, interval_ms: u32) {
-        let count = (TIMER_RATE * 2 * interval_ms) / 1000;
+        let count = ms_to_count(interval_ms);
         serial_info!("Setting count to {:?}", count);
-        let lsb = (count) as u8;
-        serial_info!("lsb :{:?}", lsb);
-        let msb = (count >> 8) as u8;
-        serial_info!("msb :{:?}", msb);
+        self.set_periodic(interval_ms);
+    }
 
-        self.cmd.send_byte(TIMER_SQUARE_WAVE);
-        self.ch0.send_byte(lsb);
-        self.ch0.send_byte(msb);
+    pub fn set_periodic(&self, interval_ms: u32) {
+        self.program(
+            Channel::Ch0,
+            OperatingMode::SquareWave,
+            ms_to_count(interval_ms),
+        );
+        crate::io::time::set_tick_interval(interval_ms);
     }
+
+    /// Single interrupt on channel 0 after `count` cycles, the counter then
+    /// stays idle until it is reprogrammed
+    pub fn one_shot(&self, count: u16) {
+        self.program(Channel::Ch0, OperatingMode::InterruptOnTerminalCount, count);
+    }
+
+    /// Channel 2 only counts while its gate is high
+    pub fn set_ch2_gate(&self, enabled: bool) {
+        let val = self.gate.read_byte();
+        if enabled {
+            self.gate.send_byte(val | 0x01);
+        } else {
+            self.gate.send_byte(val & !0x01);
+        }
+    }
+
+    pub fn ch2_output(&self) -> bool {
+        self.gate.read_byte() & (1 << 5) != 0
+    }
+
+    /// Spins for `us` microseconds, timed by channel 2 so it works with
+    /// interrupts off and before the scheduler runs. Waits of more than
+    /// about 55ms are cut short. Takes channel 2 away from the speaker
+    pub fn busy_wait_us(&self, us: u32) {
+        let count = (TIMER_RATE as u64 * us as u64 / 1_000_000).clamp(1, u16::MAX as u64);
+        self.set_ch2_gate(false);
+        self.program(
+            Channel::Ch2,
+            OperatingMode::InterruptOnTerminalCount,
+            count as u16,
+        );
+        self.set_ch2_gate(true);
+        while !self.ch2_output() {
+            core::hint::spin_loop();
+        }
+        self.set_ch2_gate(false);
+    }
+}
+
+#[test_case]
+pub fn test_pit_command_byte() {
+    assert_eq!(
+        command_byte(
+            Channel::Ch0,
+            AccessMode::LoHiByte,
+            OperatingMode::SquareWave
+        ),
+        TIMER_SQUARE_WAVE
+    );
+    assert_eq!(
+        command_byte(
+            Channel::Ch0,
+            AccessMode::LoHiByte,
+            OperatingMode::InterruptOnTerminalCount
+        ),
+        TIMER_ONE_SHOT
+    );
+    assert_eq!(
+        command_byte(
+            Channel::Ch2,
+            AccessMode::LoHiByte,
+            OperatingMode::SquareWave
+        ),
+        0xB6
+    );
+    assert_eq!(ms_to_count(10), 11931);
+    assert_eq!(ms_to_count(1000), u16::MAX);
+    assert_eq!(hz_to_count(1000), 1193);
 }
@@ -50,6 +50,20 @@ impl SerialCom {
         return port.read_byte() & 0x20 > 0;
     }
 
+    /// Next byte from the receive FIFO, if one is waiting
+    pub fn try_read(&self) -> Option<u8> {
+        if self.serial_rcvd() {
+            Some(self.port.read_byte())
+        } else {
+            None
+        }
+    }
+
+    /// Raise IRQ 4 (COM1) or 3 (COM2) when a byte arrives
+    pub fn enable_rx_interrupt(&self) {
+        Port(self.port.0 + 1).send_byte(0x01);
+    }
+
     fn read_char(&self) -> u8 {
         while !self.serial_rcvd() {}
         return self.port.read_byte();
@@ -0,0 +1,155 @@
+use core::time::Duration;
+
+use super::{
+    pit::{hz_to_count, Channel, OperatingMode},
+    port::Port,
+};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    interrupts::timer::PIT_,
+    io::time::{self, Instant},
+    sync::spinlock::SpinLock,
+};
+
+/// Bit 0 gates PIT channel 2, bit 1 connects its output to the speaker
+const SPEAKER_IO_PORT: u16 = 0x61;
+const SPEAKER_ENABLE: u8 = 0x03;
+const MELODY_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// 0 is a rest
+    pub freq_hz: u32,
+    pub duration_ms: u32,
+}
+
+impl Note {
+    pub const fn new(freq_hz: u32, duration_ms: u32) -> Self {
+        Self {
+            freq_hz,
+            duration_ms,
+        }
+    }
+    pub const fn rest(duration_ms: u32) -> Self {
+        Self::new(0, duration_ms)
+    }
+}
+
+pub const PANIC_MELODY: [Note; 3] = [
+    Note::new(880, 150),
+    Note::new(660, 150),
+    Note::new(440, 300),
+];
+
+pub struct Speaker {
+    port: Port,
+    melody: RingBuf<Note, MELODY_LEN>,
+    note_end: Option<Instant>,
+}
+
+/// Shared between threads queueing notes and the timer interrupt playing
+/// them, so the lock keeps interrupts off while held
+pub static SPEAKER: SpinLock<Speaker> = SpinLock::new(Speaker::new());
+
+impl Speaker {
+    pub const fn new() -> Self {
+        Self {
+            port: Port(SPEAKER_IO_PORT),
+            melody: RingBuf::new(),
+            note_end: None,
+        }
+    }
+
+    /// Starts a tone that keeps playing until `stop`
+    pub fn play(&self, freq_hz: u32) {
+        if freq_hz == 0 {
+            self.stop();
+            return;
+        }
+        PIT_.program(
+            Channel::Ch2,
+            OperatingMode::SquareWave,
+            hz_to_count(freq_hz),
+        );
+        let val = self.port.read_byte();
+        if val & SPEAKER_ENABLE != SPEAKER_ENABLE {
+            self.port.send_byte(val | SPEAKER_ENABLE);
+        }
+    }
+
+    pub fn stop(&self) {
+        let val = self.port.read_byte();
+        self.port.send_byte(val & !SPEAKER_ENABLE);
+    }
+
+    /// Queues notes to be played from the timer interrupt
+    pub fn queue(&mut self, notes: &[Note]) -> Result<(), &'static str> {
+        for note in notes {
+            self.melody.push(*note)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.note_end.is_some() || !self.melody.empty()
+    }
+
+    pub fn cancel(&mut self) {
+        while self.melody.take().is_some() {}
+        self.note_end = None;
+        self.stop();
+    }
+
+    /// Moves on to the next queued note once the current one is over
+    pub fn tick(&mut self, now: u64) {
+        if let Some(end) = self.note_end {
+            if now < end.0 {
+                return;
+            }
+            self.note_end = None;
+        }
+        match self.melody.take() {
+            Some(note) => {
+                self.play(note.freq_hz);
+                let end = Instant(now) + Duration::from_millis(note.duration_ms as u64);
+                self.note_end = Some(end);
+                time::request_wakeup(end);
+            }
+            None => self.stop(),
+        }
+    }
+}
+
+fn speaker_tick(now: u64) {
+    let mut speaker = SPEAKER.lock();
+    if speaker.is_playing() {
+        speaker.tick(now);
+    }
+}
+
+pub fn setup() {
+    crate::interrupts::timer::register_timer_callback(speaker_tick)
+        .expect("no free timer callback for the speaker");
+}
+
+/// Blocking tone
+pub fn beep(freq_hz: u32, duration: Duration) {
+    SPEAKER.lock().play(freq_hz);
+    time::sleep(duration);
+    SPEAKER.lock().stop();
+}
+
+/// Non blocking, the notes are played from timer callbacks
+pub fn play_melody(notes: &[Note]) -> Result<(), &'static str> {
+    SPEAKER.lock().queue(notes)
+}
+
+/// Used by the panic handler where interrupts cannot be relied on
+pub fn panic_beep() {
+    let speaker = Speaker::new();
+    for note in PANIC_MELODY {
+        speaker.play(note.freq_hz);
+        super::vga::uncalibrated_delay(note.duration_ms as usize * 1000);
+    }
+    speaker.stop();
+}
@@ -166,7 +166,11 @@ impl VGADisplay {
     }
 }
 
-pub fn delay(n: usize) {
+/// Spins for `n` rounds of slow port reads. That is around a microsecond
+/// each on real hardware but nothing calibrates it and emulators go much
+/// faster, so it is only for places without a working timer like the panic
+/// handler. Use `PIT::busy_wait_us` or `time::sleep` for real timing
+pub fn uncalibrated_delay(n: usize) {
     for _i in 0..n {
         unsafe { asm::iodelay() };
     }
@@ -181,7 +185,7 @@ impl ConsoleDisplay for VGADisplay {
     fn put_byte(&mut self, ch: u8) -> Result<(), ConsoleErrType> {
         let cursor = self.get_cursor();
 
-        // delay(10000);
+        // uncalibrated_delay(10000);
 
         match ch {
             b'\n' => {
@@ -0,0 +1,140 @@
+use core::arch::global_asm;
+
+use crate::process::user::UserFrame;
+
+/// What the entry stubs leave on the kernel stack for the handler. `regs`
+/// ends with the frame the CPU pushed, so an interrupt from ring 3 saves the
+/// same `UserFrame` at the top of the kernel stack as a syscall does
+#[repr(C)]
+pub struct InterruptFrame {
+    /// Pushed by the CPU for some exceptions, 0 for the rest
+    pub error_code: u64,
+    pub regs: UserFrame,
+}
+
+impl InterruptFrame {
+    pub fn from_user(&self) -> bool {
+        self.regs.cs & 3 == 3
+    }
+}
+
+// Ring 3 runs with a GS base of 0 and the per-CPU area parked in
+// `IA32_KERNEL_GS_BASE`, so every stub starts with `swapgs` when it came
+// from there and swaps back right before `iretq`. Interrupts stay off
+// in between: these are all interrupt gates, and the way out clears IF
+// again in case the handler turned it on, `iretq` restores it.
+//
+// For vectors with an error code the CPU put it where rax belongs in a
+// `UserFrame`, so it is exchanged with rax and pushed below the registers.
+// The 21 words on the stack leave it 8 bytes off the alignment a call needs
+global_asm!(
+    r#"
+.macro save_frame_regs
+    push rdi
+    push rsi
+    push rdx
+    push r10
+    push r8
+    push r9
+    push rcx
+    push r11
+    push rbx
+    push rbp
+    push r12
+    push r13
+    push r14
+    push r15
+.endm
+
+.macro interrupt_stub name, handler
+.global \name
+\name:
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    swapgs
+1:  push rax
+    save_frame_regs
+    push 0
+    lea rax, [rip + \handler]
+    jmp interrupt_common
+.endm
+
+.macro error_code_stub name, handler
+.global \name
+\name:
+    test byte ptr [rsp + 16], 3
+    jz 1f
+    swapgs
+1:  xchg rax, [rsp]
+    save_frame_regs
+    push rax
+    lea rax, [rip + \handler]
+    jmp interrupt_common
+.endm
+
+interrupt_common:
+    mov rdi, rsp
+    sub rsp, 8
+    call rax
+    add rsp, 16
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    pop r11
+    pop rcx
+    pop r9
+    pop r8
+    pop r10
+    pop rdx
+    pop rsi
+    pop rdi
+    pop rax
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    cli
+    swapgs
+1:  iretq
+
+interrupt_stub timer_entry, {timer}
+interrupt_stub keyboard_entry, {keyboard}
+interrupt_stub serial_entry, {serial}
+interrupt_stub resched_entry, {resched}
+interrupt_stub divide_error_entry, {divide_error}
+interrupt_stub invalid_opcode_entry, {invalid_opcode}
+error_code_stub segment_not_present_entry, {segment_not_present}
+error_code_stub stack_segment_entry, {stack_segment}
+error_code_stub general_protection_entry, {general_protection}
+error_code_stub page_fault_entry, {page_fault}
+"#,
+    timer = sym super::timer::timer_interrupt,
+    keyboard = sym super::keyboard::keyboard_interrupt,
+    serial = sym super::serial::serial_interrupt,
+    resched = sym super::ipi::resched_interrupt,
+    divide_error = sym super::faults::divide_error_handler,
+    invalid_opcode = sym super::faults::invalid_opcode_handler,
+    segment_not_present = sym super::faults::segment_not_present_handler,
+    stack_segment = sym super::faults::stack_segment_handler,
+    general_protection = sym super::faults::general_protection_handler,
+    page_fault = sym super::faults::page_fault_handler,
+);
+
+extern "C" {
+    pub fn timer_entry();
+    pub fn keyboard_entry();
+    pub fn serial_entry();
+    pub fn resched_entry();
+    pub fn divide_error_entry();
+    pub fn invalid_opcode_entry();
+    pub fn segment_not_present_entry();
+    pub fn stack_segment_entry();
+    pub fn general_protection_entry();
+    pub fn page_fault_entry();
+}
+
+/// Address of an entry stub for `set_handler_addr`
+pub fn addr(stub: unsafe extern "C" fn()) -> u64 {
+    stub as usize as u64
+}
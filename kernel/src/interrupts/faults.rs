@@ -0,0 +1,66 @@
+use super::entry::InterruptFrame;
+use crate::{
+    paging::AddressSpace,
+    process::{signal, Fault},
+    serial_info,
+    syscall::uaccess,
+    utils::asm,
+};
+
+/// A fault in ring 3 becomes a signal for the process, one in the kernel
+/// is a bug unless it was a user memory access with a fixup
+fn fault(name: &'static str, vector: u8, frame: &mut InterruptFrame, address: u64) {
+    let fault = Fault {
+        name,
+        vector,
+        rip: frame.regs.rip,
+        error_code: frame.error_code,
+        address,
+    };
+    if frame.from_user() {
+        signal::deliver_fault(fault, &mut frame.regs);
+        return;
+    }
+    if let Some(resume) = uaccess::fixup(fault.rip) {
+        frame.regs.rip = resume;
+        return;
+    }
+    panic!("kernel {}, rsp {:#x}", fault, frame.regs.rsp);
+}
+
+pub extern "C" fn divide_error_handler(frame: &mut InterruptFrame) {
+    fault("divide error", 0, frame, 0);
+}
+
+pub extern "C" fn invalid_opcode_handler(frame: &mut InterruptFrame) {
+    fault("invalid opcode", 6, frame, 0);
+}
+
+pub extern "C" fn segment_not_present_handler(frame: &mut InterruptFrame) {
+    fault("segment not present", 11, frame, 0);
+}
+
+pub extern "C" fn stack_segment_handler(frame: &mut InterruptFrame) {
+    fault("stack segment fault", 12, frame, 0);
+}
+
+pub extern "C" fn general_protection_handler(frame: &mut InterruptFrame) {
+    fault("general protection fault", 13, frame, 0);
+}
+
+/// Error code bits of a write to a present page
+const PF_PRESENT_WRITE: u64 = 0b11;
+
+/// Writes to copy-on-write pages are resolved here, from ring 3 or from the
+/// kernel writing to a user buffer
+pub extern "C" fn page_fault_handler(frame: &mut InterruptFrame) {
+    let address = asm::read_cr2();
+    if frame.error_code & PF_PRESENT_WRITE == PF_PRESENT_WRITE && AddressSpace::is_user(address) {
+        match AddressSpace::break_cow(address) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => serial_info!("copy on write at {:#x} failed: {}", address, err),
+        }
+    }
+    fault("page fault", 14, frame, address);
+}
@@ -0,0 +1,21 @@
+use super::entry::InterruptFrame;
+use crate::{cpu::percpu, devices::lapic::LocalApic, process::signal, threading::scheduler};
+
+/// Sent to a cpu that should go through the scheduler, because a thread was
+/// queued on it while idle or its running thread used up its slice. Only the
+/// boot cpu gets timer ticks, so this is also where a process running in
+/// ring 3 on another cpu notices its signals
+pub const RESCHED_VECTOR: u8 = 0xf0;
+
+pub extern "C" fn resched_interrupt(frame: &mut InterruptFrame) {
+    percpu::irq_enter();
+    percpu::set_need_resched(true);
+    if let Some(lapic) = LocalApic::get() {
+        lapic.eoi();
+    }
+    percpu::irq_exit();
+    if frame.from_user() {
+        signal::deliver(&mut frame.regs);
+    }
+    scheduler::preempt_on_interrupt_return();
+}
@@ -1,8 +1,24 @@
-use crate::{descriptors::idt::ExceptionStackFrame, io::reader::READER};
+use crate::{cpu::percpu, io::reader::READER, process::signal, task, threading::scheduler};
 
-use super::setup::PIC;
+use super::{entry::InterruptFrame, setup::PIC};
 
-pub extern "x86-interrupt" fn keyboard_interrupt(_frame: ExceptionStackFrame) {
-    let _scan_code = READER.take().input.read_into_buf();
+pub extern "C" fn keyboard_interrupt(frame: &mut InterruptFrame) {
+    percpu::irq_enter();
+    let mut reader = READER.take();
+    let scan_code = reader.input.scan_code();
+    if reader.input.is_interrupt_key(scan_code) {
+        signal::interrupt_foreground();
+    } else {
+        let _ = reader.input.push_scan_code(scan_code);
+        task::keyboard::add_scancode(scan_code);
+    }
+    // a reader of the foreground process gives up on the signal
+    reader.input.wake_waiter();
+    drop(reader);
     PIC.eoi(1);
+    percpu::irq_exit();
+    if frame.from_user() {
+        signal::deliver(&mut frame.regs);
+    }
+    scheduler::preempt_on_interrupt_return();
 }
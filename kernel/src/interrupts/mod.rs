@@ -1,3 +1,7 @@
+pub mod entry;
+pub mod faults;
+pub mod ipi;
 pub mod keyboard;
+pub mod serial;
 pub mod setup;
 pub mod timer;
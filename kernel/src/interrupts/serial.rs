@@ -0,0 +1,14 @@
+use crate::{cpu::percpu, process::signal, task, threading::scheduler};
+
+use super::{entry::InterruptFrame, setup::PIC};
+
+pub extern "C" fn serial_interrupt(frame: &mut InterruptFrame) {
+    percpu::irq_enter();
+    task::serial::receive();
+    PIC.eoi(task::serial::COM1_IRQ);
+    percpu::irq_exit();
+    if frame.from_user() {
+        signal::deliver(&mut frame.regs);
+    }
+    scheduler::preempt_on_interrupt_return();
+}
@@ -1,9 +1,10 @@
-use crate::devices::{pic8259::*};
+use crate::devices::pic8259::*;
 
-use crate::interrupts::keyboard::keyboard_interrupt;
-use crate::interrupts::timer::timer_interrupt;
+use crate::interrupts::entry::{self, *};
+use crate::interrupts::ipi::RESCHED_VECTOR;
+use crate::syscall::entry::{int80_entry, SYSCALL_VECTOR};
 
-use crate::{error, serial_info};
+use crate::error;
 use crate::{descriptors::idt::*, sync::shitlock::Racy};
 use lazy_static::lazy_static;
 
@@ -20,37 +21,78 @@ extern "x86-interrupt" fn double_fault_handler(_frame: ExceptionStackFrame, _err
 
 extern "x86-interrupt" fn breakpoint_handler(_frame: ExceptionStackFrame) {}
 
-extern "x86-interrupt" fn segment_not_present_handler(_frame: ExceptionStackFrame, _err_code: u64) {
-    serial_info!("Segment not present error");
-    // PIC.eoi(1);
-}
-extern "x86-interrupt" fn page_fault_handler(_frame: ExceptionStackFrame) {
-    serial_info!("page faulty");
-    // PIC.eoi(1);
-}
-
 pub fn interrupt_setup() {
     PIC.remap(0x20, 0x28);
+    install_handlers(&mut _IDT.take());
+    load_idt();
+}
+
+/// Fills in every gate the kernel handles. The ones ring 3 can land in go
+/// through the entry stubs, which switch the GS base
+fn install_handlers(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt.interrupts[0]
+            .set_handler_addr(entry::addr(timer_entry))
+            .set_gate_type(GateType::InterruptGate); // timer
 
-    _IDT.take().interrupts[0]
-        .set_handler_fn(timer_interrupt)
-        .options
-        .set_gate_type(GateType::InterruptGate); // timer
+        idt.interrupts[1]
+            .set_handler_addr(entry::addr(keyboard_entry))
+            .set_gate_type(GateType::InterruptGate); // keyboard
 
-    _IDT.take().interrupts[1]
-        .set_handler_fn(keyboard_interrupt)
-        .options
-        .set_gate_type(GateType::TrapGate); // timer
+        idt.interrupts[4]
+            .set_handler_addr(entry::addr(serial_entry))
+            .set_gate_type(GateType::InterruptGate); // COM1
 
-    _IDT.take().breakpoint.set_handler_fn(breakpoint_handler);
-    _IDT.take()
-        .double_fault
-        .set_handler_fn(double_fault_handler);
-    _IDT.take()
-        .segment_not_present
-        .set_handler_fn(segment_not_present_handler);
+        idt.interrupts[RESCHED_VECTOR as usize - 0x20]
+            .set_handler_addr(entry::addr(resched_entry))
+            .set_gate_type(GateType::InterruptGate); // reschedule IPI
 
-    _IDT.take().page_fault.set_handler_fn(page_fault_handler);
+        // the only vector ring 3 may raise with `int`. An interrupt gate,
+        // nothing may come in before the GS base is switched
+        idt.interrupts[SYSCALL_VECTOR as usize - 0x20]
+            .set_handler_addr(entry::addr(int80_entry))
+            .set_gate_type(GateType::InterruptGate)
+            .set_privilege_level(3);
 
+        idt.segment_not_present
+            .set_handler_addr(entry::addr(segment_not_present_entry));
+        idt.page_fault.set_handler_addr(entry::addr(page_fault_entry));
+        idt.divide_error
+            .set_handler_addr(entry::addr(divide_error_entry));
+        idt.invalid_opcode
+            .set_handler_addr(entry::addr(invalid_opcode_entry));
+        idt.stack_segment_fault
+            .set_handler_addr(entry::addr(stack_segment_entry));
+        idt.general_protection_fault
+            .set_handler_addr(entry::addr(general_protection_entry));
+    }
+
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.double_fault.set_handler_fn(double_fault_handler);
+}
+
+/// Also used by the application processors, they share the BSP's table
+pub fn load_idt() {
     _IDT.take_static().load();
 }
+
+#[test_case]
+pub fn test_user_gates() {
+    crate::serial_info!("Testing which gates ring 3 can use");
+    let mut idt = InterruptDescriptorTable::new();
+    install_handlers(&mut idt);
+    for vector in 0..=255u8 {
+        let options = idt.options(vector);
+        if vector == SYSCALL_VECTOR {
+            assert!(options.present());
+            assert_eq!(options.privilege_level(), 3);
+            assert_eq!(
+                options.gate_type().into_bits(),
+                GateType::InterruptGate.into_bits()
+            );
+        } else {
+            // `int n` from ring 3 raises #GP for all of these
+            assert_eq!(options.privilege_level(), 0, "vector {:#x}", vector);
+        }
+    }
+}
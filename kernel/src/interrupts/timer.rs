@@ -1,30 +1,111 @@
-use core::sync::atomic::AtomicIsize;
+use core::sync::atomic::{AtomicIsize, Ordering};
 
-use crate::descriptors::idt::ExceptionStackFrame;
-use crate::devices::pit::PIT;
-use crate::interrupts::setup::PIC;
+use crate::cpu::percpu;
+use crate::devices::pit::{ms_to_count, Channel, PIT};
+use crate::interrupts::{entry::InterruptFrame, setup::PIC};
+use crate::io::time;
+use crate::process::signal;
+use crate::sync::shitlock::Racy;
+use crate::threading::scheduler;
 
 pub static PIT_: PIT = PIT::new();
 
 pub struct TimerEvents {
     pub count: AtomicIsize,
+    /// Ticks covered by the one-shot currently armed, 0 when running periodic
+    one_shot_ticks: AtomicIsize,
 }
 impl TimerEvents {
-    pub fn new(&mut self) {
-        self.count
-            .fetch_add(1, core::sync::atomic::Ordering::AcqRel);
+    pub fn new(&self) {
+        let skipped = self.one_shot_ticks.swap(0, Ordering::AcqRel);
+        if skipped > 0 {
+            self.count.fetch_add(skipped, Ordering::AcqRel);
+            PIT_.set_periodic(time::tick_interval_ms());
+        } else {
+            self.count.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+    pub fn ticks(&self) -> u64 {
+        self.count.load(Ordering::Acquire) as u64
+    }
+
+    /// Longest one-shot the 16 bit counter allows, in ticks
+    pub fn max_one_shot_ticks(&self) -> u64 {
+        (u16::MAX / ms_to_count(time::tick_interval_ms())) as u64
+    }
+
+    /// Replaces the next `ticks` periodic interrupts with a single one.
+    /// Must be called with interrupts disabled
+    pub fn arm_one_shot(&self, ticks: u64) -> u64 {
+        let ticks = ticks.min(self.max_one_shot_ticks());
+        if ticks <= 1 {
+            return 0;
+        }
+        let count = ms_to_count(time::tick_interval_ms()) as u64 * ticks;
+        PIT_.one_shot(count as u16);
+        self.one_shot_ticks.store(ticks as isize, Ordering::Release);
+        ticks
+    }
+
+    /// Another interrupt woke us before the one-shot fired, account for
+    /// the whole ticks that did pass and go back to periodic mode.
+    /// Must be called with interrupts disabled
+    pub fn disarm_one_shot(&self) {
+        let armed = self.one_shot_ticks.swap(0, Ordering::AcqRel);
+        if armed <= 0 {
+            return;
+        }
+        let per_tick = ms_to_count(time::tick_interval_ms()) as u64;
+        let programmed = per_tick * armed as u64;
+        let remaining = PIT_.read_count(Channel::Ch0) as u64;
+        let elapsed = programmed.saturating_sub(remaining) / per_tick;
+        self.count.fetch_add(elapsed as isize, Ordering::AcqRel);
+        PIT_.set_periodic(time::tick_interval_ms());
     }
+
     pub const fn default() -> Self {
         Self {
             count: AtomicIsize::new(0),
+            one_shot_ticks: AtomicIsize::new(0),
         }
     }
 }
 
-pub static mut TIMER_EVENTS: TimerEvents = TimerEvents::default();
+pub static TIMER_EVENTS: TimerEvents = TimerEvents::default();
+
+/// Called from the timer interrupt with the current tick count
+pub type TimerCallback = fn(u64);
+const MAX_TIMER_CALLBACKS: usize = 8;
+
+lazy_static::lazy_static! {
+    static ref TIMER_CALLBACKS: Racy<[Option<TimerCallback>; MAX_TIMER_CALLBACKS]> =
+        Racy::from([None; MAX_TIMER_CALLBACKS]);
+}
 
-pub extern "x86-interrupt" fn timer_interrupt(_frame: ExceptionStackFrame) {
+pub fn register_timer_callback(callback: TimerCallback) -> Result<(), &'static str> {
+    let mut callbacks = TIMER_CALLBACKS.take();
+    let slot = callbacks
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or("timer callbacks full")?;
+    *slot = Some(callback);
+    Ok(())
+}
+
+pub extern "C" fn timer_interrupt(frame: &mut InterruptFrame) {
+    percpu::irq_enter();
     // let ptr = frame.instruction_pointer as *const u64;
-    unsafe { TIMER_EVENTS.new() };
+    TIMER_EVENTS.new();
+    let now = TIMER_EVENTS.ticks();
+    for callback in TIMER_CALLBACKS.take().iter().flatten() {
+        callback(now);
+    }
+    let from_user = frame.from_user();
+    scheduler::tick(now, from_user);
     PIC.eoi(0);
+    percpu::irq_exit();
+    if from_user {
+        signal::deliver(&mut frame.regs);
+    }
+    scheduler::preempt_on_interrupt_return();
 }
@@ -1,2 +1,3 @@
 pub mod reader;
+pub mod time;
 pub mod writer;
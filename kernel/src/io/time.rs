@@ -0,0 +1,223 @@
+use core::{
+    ops::{Add, Sub},
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    interrupts::timer::TIMER_EVENTS,
+    sync::spinlock::SpinLock,
+    threading::{self, scheduler},
+    utils::asm,
+};
+
+/// Length of one timer tick, updated whenever the PIT is reprogrammed
+static TICK_INTERVAL_MS: AtomicU32 = AtomicU32::new(10);
+
+pub fn set_tick_interval(interval_ms: u32) {
+    TICK_INTERVAL_MS.store(interval_ms.max(1), Ordering::Release);
+}
+
+pub fn tick_interval_ms() -> u32 {
+    TICK_INTERVAL_MS.load(Ordering::Acquire)
+}
+
+pub fn tick_interval() -> Duration {
+    Duration::from_millis(tick_interval_ms() as u64)
+}
+
+const MAX_DEADLINES: usize = 16;
+
+/// Ticks somebody asked to be woken at, lets idle skip the ticks before the
+/// earliest one. A deadline only leaves the set once it has passed, so one
+/// requester being woken never drops the deadline of another
+struct Deadlines {
+    ticks: [u64; MAX_DEADLINES],
+    len: usize,
+    /// Latest deadline that did not fit, idle keeps ticking periodically
+    /// until it has passed so none of the dropped ones is slept through
+    overflow: u64,
+}
+
+impl Deadlines {
+    const fn new() -> Self {
+        Self {
+            ticks: [0; MAX_DEADLINES],
+            len: 0,
+            overflow: 0,
+        }
+    }
+
+    fn insert(&mut self, tick: u64) {
+        let ticks = &mut self.ticks[..self.len];
+        if ticks.contains(&tick) {
+            return;
+        }
+        if self.len < MAX_DEADLINES {
+            self.ticks[self.len] = tick;
+            self.len += 1;
+            return;
+        }
+        // full, keep the earliest ones
+        let latest = ticks.iter_mut().max().unwrap();
+        let dropped = if tick < *latest {
+            core::mem::replace(latest, tick)
+        } else {
+            tick
+        };
+        self.overflow = self.overflow.max(dropped);
+    }
+
+    /// Forgets the deadlines up to `now` and returns the tick idle may
+    /// sleep until
+    fn next(&mut self, now: u64) -> u64 {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if self.ticks[i] > now {
+                self.ticks[kept] = self.ticks[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+        if self.overflow > now {
+            return now;
+        }
+        self.ticks[..self.len]
+            .iter()
+            .copied()
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+static DEADLINES: SpinLock<Deadlines> = SpinLock::new(Deadlines::new());
+
+/// Rounds up so that sleeping never returns before the requested time
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let tick_ms = TICK_INTERVAL_MS.load(Ordering::Acquire) as u128;
+    let ms = duration.as_millis();
+    ((ms + tick_ms - 1) / tick_ms) as u64
+}
+
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    Duration::from_millis(ticks * TICK_INTERVAL_MS.load(Ordering::Acquire) as u64)
+}
+
+/// Point in time measured in timer ticks since boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(pub u64);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(TIMER_EVENTS.ticks())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now() - *self
+    }
+
+    pub fn has_passed(&self) -> bool {
+        Self::now() >= *self
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+    fn add(self, rhs: Duration) -> Self::Output {
+        Instant(self.0 + duration_to_ticks(rhs))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+    fn sub(self, rhs: Self) -> Self::Output {
+        ticks_to_duration(self.0.saturating_sub(rhs.0))
+    }
+}
+
+/// Keeps tickless idle from sleeping past `deadline`
+pub fn request_wakeup(deadline: Instant) {
+    DEADLINES.lock().insert(deadline.0);
+}
+
+/// Blocks the calling thread until the deadline tick has been reached.
+/// Before threads are up the cpu just halts
+pub fn sleep_until(deadline: Instant) {
+    if threading::initialized() {
+        while !deadline.has_passed() {
+            scheduler::sleep_until(deadline);
+        }
+        return;
+    }
+    loop {
+        asm::disable_interrupts();
+        if deadline.has_passed() {
+            asm::enable_interrupts();
+            break;
+        }
+        request_wakeup(deadline);
+        idle_once();
+    }
+}
+
+pub fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration);
+}
+
+/// Tickless halt, the periodic ticks up to the nearest deadline are folded
+/// into a single PIT one-shot. The scheduler charges the skipped ticks on
+/// the next one it sees. Must be entered with interrupts disabled and
+/// returns with them enabled
+pub fn idle_once() {
+    let now = TIMER_EVENTS.ticks();
+    let next = DEADLINES.lock().next(now);
+    TIMER_EVENTS.arm_one_shot(next.saturating_sub(now));
+    asm::enable_interrupts_and_hlt();
+    asm::disable_interrupts();
+    TIMER_EVENTS.disarm_one_shot();
+    asm::enable_interrupts();
+}
+
+/// Nothing left to do, wait for interrupts forever
+pub fn idle() -> ! {
+    loop {
+        asm::disable_interrupts();
+        idle_once();
+    }
+}
+
+#[test_case]
+pub fn test_duration_to_ticks() {
+    set_tick_interval(10);
+    assert_eq!(duration_to_ticks(Duration::from_millis(0)), 0);
+    assert_eq!(duration_to_ticks(Duration::from_millis(1)), 1);
+    assert_eq!(duration_to_ticks(Duration::from_millis(10)), 1);
+    assert_eq!(duration_to_ticks(Duration::from_millis(25)), 3);
+    assert_eq!(Instant(5) + Duration::from_millis(20), Instant(7));
+    assert_eq!(Instant(7) - Instant(5), Duration::from_millis(20));
+    assert_eq!(Instant(5) - Instant(7), Duration::from_millis(0));
+}
+
+#[test_case]
+pub fn test_deadlines() {
+    let mut deadlines = Deadlines::new();
+    assert_eq!(deadlines.next(0), u64::MAX);
+    deadlines.insert(30);
+    deadlines.insert(10);
+    deadlines.insert(10);
+    assert_eq!(deadlines.next(0), 10);
+    // waking the first sleeper keeps the later deadline
+    assert_eq!(deadlines.next(10), 30);
+    assert_eq!(deadlines.next(30), u64::MAX);
+
+    for tick in 100..100 + MAX_DEADLINES as u64 {
+        deadlines.insert(tick);
+    }
+    deadlines.insert(50);
+    assert_eq!(
+        deadlines.next(0),
+        0,
+        "dropped deadline has to keep idle ticking"
+    );
+    assert_eq!(deadlines.next(100 + MAX_DEADLINES as u64 - 1), u64::MAX);
+}
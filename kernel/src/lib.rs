@@ -10,7 +10,8 @@
 #![allow(clippy::needless_return)]
 #![feature(let_chains)]
 
-// extern crate alloc;
+extern crate alloc;
+pub mod acpi;
 pub mod addr;
 pub mod descriptors;
 pub mod devices;
@@ -27,14 +28,19 @@ pub use core::{
 
 use bootloader::{BootInfo, bootinfo::{MemoryRegionType, self}};
 
-use crate::{devices::vga::Color, io::writer::set_color, allocator::kernel_alloc::ALLOC};
+use crate::{devices::vga::Color, io::writer::set_color, allocator::{kernel_alloc::ALLOC, page_alloc::PageAlloc}};
 pub mod allocator;
 pub mod cc;
+pub mod cpu;
 pub mod datastructures;
 pub mod io;
 pub mod logging;
 pub mod paging;
 pub mod process;
+pub mod shell;
+pub mod syscall;
+pub mod task;
+pub mod threading;
 
 
 pub static mut BOOT_INFO: Option<&'static BootInfo> = None;
@@ -47,20 +53,42 @@ pub fn setup_boot_info(val: &'static BootInfo) {
 
 pub fn discover_pages() {
     let bootinfo = unsafe { BOOT_INFO.unwrap() };
-    let usable_regions = bootinfo
-        .memory_map
-        .iter()
-        .filter(|region| region.region_type == MemoryRegionType::Usable);
+    let usable_regions = || {
+        bootinfo
+            .memory_map
+            .iter()
+            .filter(|region| region.region_type == MemoryRegionType::Usable)
+    };
+
+    // the frame reference counts cover all usable memory and come out of
+    // the first region big enough for them
+    let end = usable_regions()
+        .map(|region| region.range.end_addr())
+        .max()
+        .unwrap_or(0);
+    let table_size = PageAlloc::<4096>::frame_refs_size(end);
+    let mut table = None;
 
-    for region in usable_regions {
+    for region in usable_regions() {
         serial_info!("Setting up apges in region {:?}", region);
-        unsafe {
-            ALLOC.add_region(
-                region.range.start_addr() + bootinfo.physical_memory_offset,
-                region.range.end_addr() + bootinfo.physical_memory_offset,
-            )
-        };
+        let mut start = region.range.start_addr();
+        if table.is_none() && region.range.end_addr() - start >= table_size {
+            table = Some(start);
+            start += table_size;
+        }
+        ALLOC.lock().add_region(
+            start + bootinfo.physical_memory_offset,
+            region.range.end_addr() + bootinfo.physical_memory_offset,
+        );
     }
+
+    let table = table.expect("no room for frame reference counts");
+    unsafe {
+        ALLOC.lock().set_frame_refs(
+            (table + bootinfo.physical_memory_offset) as *mut u8,
+            end,
+        )
+    };
 }
 
 
@@ -69,6 +97,7 @@ pub fn discover_pages() {
 pub fn panic(_info: &PanicInfo) -> ! {
     set_color(Color::pack(Color::Black, Color::Red));
     ksprintln!("{}", _info);
+    devices::speaker::panic_beep();
     loop {}
 }
 
@@ -86,10 +115,9 @@ mod test {
         }
     }
 
-    use bootloader::{bootinfo::MemoryRegionType, BootInfo};
+    use bootloader::BootInfo;
     // extern crate alloc;
     use crate::{
-        allocator::page_alloc::PageAlloc,
         devices::{
             pit::PIT,
             vga::{Color, ConsoleDisplay},
@@ -106,34 +134,21 @@ mod test {
 
     bootloader::entry_point!(kernel_main);
 
-    static mut ALLOC: PageAlloc<4096> = PageAlloc::default();
-
     pub fn kernel_main(bootinfo: &'static BootInfo) -> ! {
+        cpu::percpu::init_bsp();
         unsafe { utils::asm::disable_interrupts() }; // this fails if no handler is installed
 
         unsafe { PIT_.setup(10) };
 
+        // the tests allocate from the same pages the kernel would
+        setup_boot_info(bootinfo);
+        discover_pages();
+
         test_main();
 
         interrupts::setup::interrupt_setup();
         unsafe { utils::asm::enable_interrupts() }; // this fails if no handler is installed
 
-        let usable_regions = bootinfo
-            .memory_map
-            .iter()
-            .filter(|region| region.region_type == MemoryRegionType::Usable);
-
-        // println!("{:#?}", bootinfo.physical_memory_offset as *mut ());
-        for region in usable_regions {
-            ksprintln!("Setting up apges in region {:?}", region);
-            unsafe {
-                ALLOC.add_region(
-                    region.range.start_addr() + bootinfo.physical_memory_offset,
-                    region.range.end_addr() + bootinfo.physical_memory_offset,
-                )
-            };
-        }
-
         // WRITER.take().display.clear();
         loop {
             READER.take().input.process_buf_wait();
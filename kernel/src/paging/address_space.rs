@@ -0,0 +1,318 @@
+use super::{
+    active_pml4, alloc_table, entry_in, flush, frame_owners, free_frame, index, kernel_pml4,
+    map_page_in, phys_to_virt, share_frame, table_at, unmap_page_in, PageTableFlags, ADDR_MASK,
+    PAGE_SIZE,
+};
+
+/// Lowest address a process can map. The bootloader places the kernel, its
+/// stack and the physical memory window in the first few PML4 slots
+pub const USER_START: u64 = 0x0000_1000_0000_0000;
+/// End of the lower canonical half
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
+/// Initial stack pointer of a process, the page below it is its stack
+pub const USER_STACK_TOP: u64 = USER_END - PAGE_SIZE;
+/// `mmap` hands out addresses downwards from here, what is above is kept
+/// free for the stack
+pub const MMAP_TOP: u64 = USER_STACK_TOP - 0x100_0000;
+
+/// Permissions of a user page that carry over to a copy of it
+const USER_FLAGS: PageTableFlags = PageTableFlags::WRITABLE.union(PageTableFlags::NO_EXECUTE);
+/// Same for a page that is shared, which is never writable
+const COW_FLAGS: PageTableFlags = PageTableFlags::USER
+    .union(PageTableFlags::NO_EXECUTE)
+    .union(PageTableFlags::COPY_ON_WRITE);
+
+/// Page tables of one process. The PML4 entries of the kernel are copied in
+/// so the kernel half is shared, kernel mappings added in a new PML4 slot
+/// later on are not seen by spaces that exist already
+pub struct AddressSpace {
+    pml4: u64,
+    /// Where the heap `brk` moves starts, right after the loaded image
+    heap_start: u64,
+    brk: u64,
+    /// Lowest address `mmap` has handed out
+    mmap_bottom: u64,
+}
+
+impl AddressSpace {
+    pub fn new() -> Result<Self, &'static str> {
+        let pml4 = alloc_table()?;
+        let kernel = table_at(kernel_pml4());
+        let table = table_at(pml4);
+        for (slot, &entry) in kernel.entries.iter().enumerate() {
+            if entry & PageTableFlags::USER.bits() == 0 {
+                table.entries[slot] = entry;
+            }
+        }
+        Ok(Self {
+            pml4,
+            heap_start: USER_START,
+            brk: USER_START,
+            mmap_bottom: MMAP_TOP,
+        })
+    }
+
+    /// Physical address of the PML4, what goes into CR3
+    pub fn pml4(&self) -> u64 {
+        self.pml4
+    }
+
+    pub fn is_user(virt: u64) -> bool {
+        (USER_START..USER_END).contains(&virt)
+    }
+
+    /// Maps a fresh zeroed page at `virt` and returns where the kernel can
+    /// reach it. `USER` is always added to `flags`
+    pub fn map_user(&mut self, virt: u64, flags: PageTableFlags) -> Result<*mut u8, &'static str> {
+        if !Self::is_user(virt) || virt % PAGE_SIZE != 0 {
+            return Err("not a page aligned user address");
+        }
+        let top = table_at(self.pml4).entries[index(virt, 3)];
+        if top & PageTableFlags::PRESENT.bits() != 0 && top & PageTableFlags::USER.bits() == 0 {
+            return Err("address belongs to the kernel");
+        }
+        if entry_in(self.pml4, virt).is_some() {
+            return Err("page is already mapped");
+        }
+        let phys = alloc_table()?;
+        if let Err(err) = map_page_in(self.pml4, virt, phys, flags | PageTableFlags::USER) {
+            free_frame(phys);
+            return Err(err);
+        }
+        Ok(phys_to_virt(phys))
+    }
+
+    /// Widens the permissions of the user page at `virt` to allow whatever
+    /// `flags` allows as well
+    pub fn grant(&mut self, virt: u64, flags: PageTableFlags) -> Result<(), &'static str> {
+        let entry = entry_in(self.pml4, virt).ok_or("user page not mapped")?;
+        if *entry & PageTableFlags::USER.bits() == 0 {
+            return Err("address belongs to the kernel");
+        }
+        *entry |= (flags & PageTableFlags::WRITABLE).bits();
+        if !flags.contains(PageTableFlags::NO_EXECUTE) {
+            *entry &= !PageTableFlags::NO_EXECUTE.bits();
+        }
+        flush(virt);
+        Ok(())
+    }
+
+    /// Kernel pointer to the byte `virt` is mapped to
+    pub fn kernel_ptr(&self, virt: u64) -> Option<*mut u8> {
+        let entry = *entry_in(self.pml4, virt)?;
+        if entry & PageTableFlags::USER.bits() == 0 {
+            return None;
+        }
+        Some(phys_to_virt((entry & ADDR_MASK) + virt % PAGE_SIZE))
+    }
+
+    /// Copies `bytes` to `virt`, every page they touch has to be mapped
+    pub fn write(&mut self, mut virt: u64, mut bytes: &[u8]) -> Result<(), &'static str> {
+        while !bytes.is_empty() {
+            let dst = self.kernel_ptr(virt).ok_or("user page not mapped")?;
+            let len = bytes.len().min((PAGE_SIZE - virt % PAGE_SIZE) as usize);
+            unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), dst, len) };
+            bytes = &bytes[len..];
+            virt += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Puts an empty heap at `start`, rounded up to a page
+    pub fn set_heap_start(&mut self, start: u64) {
+        self.heap_start = align_up(start);
+        self.brk = self.heap_start;
+    }
+
+    /// Current end of the heap
+    pub fn brk(&self) -> u64 {
+        self.brk
+    }
+
+    /// Moves the end of the heap to `brk`, mapping or freeing the pages in
+    /// between
+    pub fn set_brk(&mut self, brk: u64) -> Result<u64, &'static str> {
+        if brk < self.heap_start || brk > self.mmap_bottom {
+            return Err("break outside of the heap");
+        }
+        let (old_end, new_end) = (align_up(self.brk), align_up(brk));
+        if new_end > old_end {
+            self.map_range(
+                old_end,
+                new_end,
+                PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+            )?;
+        } else {
+            self.unmap(new_end, old_end - new_end);
+        }
+        self.brk = brk;
+        Ok(brk)
+    }
+
+    /// Maps `len` bytes of fresh zeroed pages below everything `mmap` gave
+    /// out so far and returns their address
+    pub fn map_anonymous(&mut self, len: u64, flags: PageTableFlags) -> Result<u64, &'static str> {
+        let len = align_up(len);
+        let start = self
+            .mmap_bottom
+            .checked_sub(len)
+            .filter(|&start| len != 0 && start >= align_up(self.brk))
+            .ok_or("out of address space for mappings")?;
+        self.map_range(start, start + len, flags)?;
+        self.mmap_bottom = start;
+        Ok(start)
+    }
+
+    /// Maps fresh pages from `start` up to `end`, all or none of them
+    fn map_range(
+        &mut self,
+        start: u64,
+        end: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), &'static str> {
+        let mut page = start;
+        while page < end {
+            if let Err(err) = self.map_user(page, flags) {
+                self.unmap(start, page - start);
+                return Err(err);
+            }
+            page += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Drops the user pages between `virt` and `virt + len`, the ones that
+    /// are not mapped are skipped
+    pub fn unmap(&mut self, virt: u64, len: u64) {
+        let mut page = virt & !(PAGE_SIZE - 1);
+        while page < virt.saturating_add(len) && Self::is_user(page) {
+            if let Some(phys) = unmap_page_in(self.pml4, page) {
+                free_frame(phys);
+            }
+            page += PAGE_SIZE;
+        }
+    }
+
+    /// Calls `f` with the address and the page table entry of every page
+    /// mapped in the user half
+    pub fn for_each_page(
+        &mut self,
+        f: &mut dyn FnMut(u64, &mut u64) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        for (slot, &entry) in table_at(self.pml4).entries.iter().enumerate() {
+            let present = entry & PageTableFlags::PRESENT.bits() != 0;
+            if present && entry & PageTableFlags::USER.bits() != 0 {
+                walk(entry & ADDR_MASK, 2, (slot as u64) << 39, f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A new space sharing every user page with this one, for fork.
+    /// Writable pages turn read-only and copy-on-write in both. Pages the
+    /// frame allocator cannot share are copied right away
+    pub fn try_clone(&mut self) -> Result<Self, &'static str> {
+        let mut copy = Self::new()?;
+        copy.heap_start = self.heap_start;
+        copy.brk = self.brk;
+        copy.mmap_bottom = self.mmap_bottom;
+        self.for_each_page(&mut |virt, entry| {
+            let phys = *entry & ADDR_MASK;
+            let flags = PageTableFlags::from_bits_truncate(*entry);
+            if !share_frame(phys) {
+                let page = copy.map_user(virt, flags & USER_FLAGS)?;
+                let src = phys_to_virt(phys) as *const u8;
+                unsafe { core::ptr::copy_nonoverlapping(src, page, PAGE_SIZE as usize) };
+                return Ok(());
+            }
+            if flags.contains(PageTableFlags::WRITABLE) {
+                *entry &= !PageTableFlags::WRITABLE.bits();
+                *entry |= PageTableFlags::COPY_ON_WRITE.bits();
+                flush(virt);
+            }
+            let shared = PageTableFlags::from_bits_truncate(*entry) & COW_FLAGS;
+            map_page_in(copy.pml4, virt, phys, shared).inspect_err(|_| free_frame(phys))
+        })?;
+        Ok(copy)
+    }
+
+    /// Gives the active space its own writable copy of the copy-on-write
+    /// page holding `virt`, in place if nobody else maps the frame anymore.
+    /// False if the page is not copy-on-write
+    pub fn break_cow(virt: u64) -> Result<bool, &'static str> {
+        let Some(entry) = entry_in(active_pml4(), virt) else {
+            return Ok(false);
+        };
+        let flags = PageTableFlags::from_bits_truncate(*entry);
+        if !flags.contains(PageTableFlags::COPY_ON_WRITE | PageTableFlags::USER) {
+            return Ok(false);
+        }
+        let phys = *entry & ADDR_MASK;
+        let writable = (flags - PageTableFlags::COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+        if frame_owners(phys) == 1 {
+            *entry = phys | writable.bits();
+        } else {
+            let copy = alloc_table()?;
+            let src = phys_to_virt(phys) as *const u8;
+            unsafe { core::ptr::copy_nonoverlapping(src, phys_to_virt(copy), PAGE_SIZE as usize) };
+            *entry = copy | writable.bits();
+            free_frame(phys);
+        }
+        flush(virt);
+        Ok(true)
+    }
+}
+
+fn align_up(addr: u64) -> u64 {
+    (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// Visits the leaf entries below the table at `phys` of `level`, which maps
+/// the range starting at `base`
+fn walk(
+    phys: u64,
+    level: u32,
+    base: u64,
+    f: &mut dyn FnMut(u64, &mut u64) -> Result<(), &'static str>,
+) -> Result<(), &'static str> {
+    for (i, entry) in table_at(phys).entries.iter_mut().enumerate() {
+        if *entry & PageTableFlags::PRESENT.bits() == 0 {
+            continue;
+        }
+        let virt = base | (i as u64) << (12 + 9 * level);
+        if level == 0 {
+            f(virt, entry)?;
+        } else {
+            walk(*entry & ADDR_MASK, level - 1, virt, f)?;
+        }
+    }
+    Ok(())
+}
+
+/// Frees the table at `phys` of `level` and everything below it
+fn free_tree(phys: u64, level: u32) {
+    for &entry in table_at(phys).entries.iter() {
+        if entry & PageTableFlags::PRESENT.bits() == 0 {
+            continue;
+        }
+        if level == 0 {
+            free_frame(entry & ADDR_MASK);
+        } else {
+            free_tree(entry & ADDR_MASK, level - 1);
+        }
+    }
+    free_frame(phys);
+}
+
+impl Drop for AddressSpace {
+    /// The space must not be loaded on any cpu anymore
+    fn drop(&mut self) {
+        for &entry in table_at(self.pml4).entries.iter() {
+            let present = entry & PageTableFlags::PRESENT.bits() != 0;
+            if present && entry & PageTableFlags::USER.bits() != 0 {
+                free_tree(entry & ADDR_MASK, 2);
+            }
+        }
+        free_frame(self.pml4);
+    }
+}
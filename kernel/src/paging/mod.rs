@@ -1 +1,214 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+use bitflags::bitflags;
+
+use crate::{allocator::kernel_alloc::ALLOC, BOOT_INFO};
+
+pub mod address_space;
+
+pub use address_space::AddressSpace;
+
+pub const PAGE_SIZE: u64 = 4096;
+const ENTRIES: usize = 512;
+const ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+static KERNEL_PML4: AtomicU64 = AtomicU64::new(0);
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PageTableFlags: u64 {
+        const PRESENT = 1 << 0;
+        const WRITABLE = 1 << 1;
+        const USER = 1 << 2;
+        const WRITE_THROUGH = 1 << 3;
+        const NO_CACHE = 1 << 4;
+        const ACCESSED = 1 << 5;
+        const DIRTY = 1 << 6;
+        const HUGE = 1 << 7;
+        const GLOBAL = 1 << 8;
+        /// Software bit: a read-only page that gets its own copy on the
+        /// first write, see `AddressSpace::break_cow`
+        const COPY_ON_WRITE = 1 << 9;
+        const NO_EXECUTE = 1 << 63;
+    }
+}
+
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [u64; ENTRIES],
+}
+
+/// Where the bootloader mapped all of physical memory
+pub fn phys_offset() -> u64 {
+    unsafe {
+        BOOT_INFO
+            .expect("boot info not set up")
+            .physical_memory_offset
+    }
+}
+
+pub fn phys_to_virt(phys: u64) -> *mut u8 {
+    (phys + phys_offset()) as *mut u8
+}
+
+/// Only valid for addresses inside the physical memory window, which is
+/// where every page from `ALLOC` lives
+pub fn virt_to_phys(virt: *const u8) -> u64 {
+    virt as u64 - phys_offset()
+}
+
+pub fn read_cr3() -> u64 {
+    let cr3: u64;
+    unsafe { asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+    cr3
+}
+
+/// Physical address of the PML4 in use
+pub fn active_pml4() -> u64 {
+    read_cr3() & ADDR_MASK
+}
+
+/// Switches to the address space rooted at `pml4`, skipped if it is active
+pub fn write_cr3(pml4: u64) {
+    if active_pml4() != pml4 {
+        unsafe { asm!("mov cr3, {}", in(reg) pml4, options(nostack, preserves_flags)) };
+    }
+}
+
+/// PML4 the bootloader handed over, kernel threads run on it. The first
+/// call has to happen on the boot page tables, `threading::init` does that
+pub fn kernel_pml4() -> u64 {
+    let _ = KERNEL_PML4.compare_exchange(0, active_pml4(), Ordering::AcqRel, Ordering::Acquire);
+    KERNEL_PML4.load(Ordering::Acquire)
+}
+
+pub fn flush(virt: u64) {
+    unsafe { asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags)) };
+}
+
+fn table_at(phys: u64) -> &'static mut PageTable {
+    unsafe { &mut *(phys_to_virt(phys) as *mut PageTable) }
+}
+
+fn index(virt: u64, level: u32) -> usize {
+    ((virt >> (12 + 9 * level)) & 0x1ff) as usize
+}
+
+/// Zeroed page for a page table, returns its physical address
+fn alloc_table() -> Result<u64, &'static str> {
+    let page = ALLOC.lock().alloc_page();
+    if page.is_null() {
+        return Err("out of memory for page tables");
+    }
+    unsafe { core::ptr::write_bytes(page, 0, PAGE_SIZE as usize) };
+    Ok(virt_to_phys(page))
+}
+
+/// Drops one owner of a page from `ALLOC` by its physical address, the
+/// page goes back once the last one is gone
+fn free_frame(phys: u64) {
+    let mut alloc = ALLOC.lock();
+    if alloc.unshare_frame(phys) {
+        alloc.dealloc_page(phys_to_virt(phys));
+    }
+}
+
+/// Adds an owner to a page from `ALLOC`, false if it cannot be shared
+fn share_frame(phys: u64) -> bool {
+    ALLOC.lock().share_frame(phys)
+}
+
+fn frame_owners(phys: u64) -> usize {
+    ALLOC.lock().frame_owners(phys)
+}
+
+/// Walks down to the page table holding `virt`, creating missing tables
+/// with `table_flags` on the way
+fn walk_create(
+    pml4: u64,
+    virt: u64,
+    table_flags: PageTableFlags,
+) -> Result<&'static mut PageTable, &'static str> {
+    let mut table = table_at(pml4);
+    for level in (1..4).rev() {
+        let entry = &mut table.entries[index(virt, level)];
+        if *entry & PageTableFlags::PRESENT.bits() == 0 {
+            *entry = alloc_table()? | table_flags.bits();
+        } else if *entry & PageTableFlags::HUGE.bits() != 0 {
+            return Err("address is covered by a huge page");
+        } else {
+            *entry |= table_flags.bits();
+        }
+        table = table_at(*entry & ADDR_MASK);
+    }
+    Ok(table)
+}
+
+/// Maps one 4 KiB page in the address space rooted at `pml4`
+pub fn map_page_in(
+    pml4: u64,
+    virt: u64,
+    phys: u64,
+    flags: PageTableFlags,
+) -> Result<(), &'static str> {
+    let table_flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | (flags & PageTableFlags::USER);
+    let table = walk_create(pml4, virt, table_flags)?;
+    table.entries[index(virt, 0)] = (phys & ADDR_MASK) | (flags | PageTableFlags::PRESENT).bits();
+    flush(virt);
+    Ok(())
+}
+
+pub fn map_page(virt: u64, phys: u64, flags: PageTableFlags) -> Result<(), &'static str> {
+    map_page_in(active_pml4(), virt, phys, flags)
+}
+
+/// Page table entry mapping `virt`, None if any level is missing.
+/// Huge pages are returned as the entry of the level that maps them
+pub fn entry_in(pml4: u64, virt: u64) -> Option<&'static mut u64> {
+    let mut table = table_at(pml4);
+    for level in (0..4).rev() {
+        let entry = &mut table.entries[index(virt, level)];
+        if *entry & PageTableFlags::PRESENT.bits() == 0 {
+            return None;
+        }
+        if level == 0 || *entry & PageTableFlags::HUGE.bits() != 0 {
+            return Some(entry);
+        }
+        table = table_at(*entry & ADDR_MASK);
+    }
+    None
+}
+
+/// Physical address `virt` is mapped to in the active address space
+pub fn translate(virt: u64) -> Option<u64> {
+    let mut table = table_at(active_pml4());
+    for level in (0..4).rev() {
+        let entry = table.entries[index(virt, level)];
+        if entry & PageTableFlags::PRESENT.bits() == 0 {
+            return None;
+        }
+        if level == 0 || entry & PageTableFlags::HUGE.bits() != 0 {
+            let page_mask = (1u64 << (12 + 9 * level)) - 1;
+            return Some((entry & ADDR_MASK & !page_mask) | (virt & page_mask));
+        }
+        table = table_at(entry & ADDR_MASK);
+    }
+    None
+}
+
+/// Removes the mapping and returns the physical page it pointed at
+pub fn unmap_page_in(pml4: u64, virt: u64) -> Option<u64> {
+    let entry = entry_in(pml4, virt)?;
+    let phys = *entry & ADDR_MASK;
+    *entry = 0;
+    flush(virt);
+    Some(phys)
+}
+
+pub fn unmap_page(virt: u64) -> Option<u64> {
+    unmap_page_in(active_pml4(), virt)
+}
@@ -0,0 +1,314 @@
+use super::stack::InitialStack;
+use crate::{
+    paging::{
+        address_space::{USER_END, USER_STACK_TOP, USER_START},
+        AddressSpace, PageTableFlags, PAGE_SIZE,
+    },
+    serial_info,
+};
+
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 62;
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+/// Pages of stack a new process starts with, more than the arguments on
+/// it may take up
+pub const USER_STACK_PAGES: u64 = 4;
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// The fields of a program header the loader needs
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+}
+
+impl Segment {
+    fn parse(bytes: &[u8]) -> Self {
+        Self {
+            kind: read_u32(bytes, 0),
+            flags: read_u32(bytes, 4),
+            offset: read_u64(bytes, 8),
+            vaddr: read_u64(bytes, 16),
+            filesz: read_u64(bytes, 32),
+            memsz: read_u64(bytes, 40),
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.vaddr + self.memsz
+    }
+
+    fn page_flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::empty();
+        if self.flags & PF_W != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if self.flags & PF_X == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        flags
+    }
+}
+
+/// A checked ELF64 executable, every `PT_LOAD` segment lies in user space,
+/// inside the file and apart from the others
+pub struct Elf<'a> {
+    image: &'a [u8],
+    pub entry: u64,
+    phoff: usize,
+    phnum: usize,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(image: &'a [u8]) -> Result<Self, &'static str> {
+        if image.len() < EHDR_SIZE || image[..4] != MAGIC {
+            return Err("bad ELF magic");
+        }
+        if image[4] != CLASS_64 || image[5] != LITTLE_ENDIAN {
+            return Err("not a little endian ELF64 file");
+        }
+        if read_u16(image, 18) != EM_X86_64 {
+            return Err("unsupported machine type");
+        }
+        if read_u16(image, 16) != ET_EXEC {
+            return Err("not a static executable");
+        }
+        let phoff = read_u64(image, 32) as usize;
+        let phnum = read_u16(image, 56) as usize;
+        if read_u16(image, 54) as usize != PHDR_SIZE {
+            return Err("unexpected program header size");
+        }
+        if phoff
+            .checked_add(phnum * PHDR_SIZE)
+            .map_or(true, |end| end > image.len())
+        {
+            return Err("program headers out of bounds");
+        }
+        let elf = Self {
+            image,
+            entry: read_u64(image, 24),
+            phoff,
+            phnum,
+        };
+        elf.check_segments()?;
+        Ok(elf)
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        (0..self.phnum).map(|i| {
+            let at = self.phoff + i * PHDR_SIZE;
+            Segment::parse(&self.image[at..at + PHDR_SIZE])
+        })
+    }
+
+    pub fn phent(&self) -> usize {
+        PHDR_SIZE
+    }
+
+    pub fn phnum(&self) -> usize {
+        self.phnum
+    }
+
+    /// Where the program headers end up in memory, if a segment loads them
+    pub fn phdr_addr(&self) -> Option<u64> {
+        let phoff = self.phoff as u64;
+        let end = phoff + (self.phnum * PHDR_SIZE) as u64;
+        self.loadable()
+            .find(|seg| seg.offset <= phoff && end <= seg.offset + seg.filesz)
+            .map(|seg| seg.vaddr + (phoff - seg.offset))
+    }
+
+    fn loadable(&self) -> impl Iterator<Item = Segment> + '_ {
+        self.segments().filter(|seg| seg.kind == PT_LOAD)
+    }
+
+    fn check_segments(&self) -> Result<(), &'static str> {
+        let mut executable_entry = false;
+        for (i, seg) in self.loadable().enumerate() {
+            if seg.filesz > seg.memsz {
+                return Err("segment file size larger than memory size");
+            }
+            let in_file = seg
+                .offset
+                .checked_add(seg.filesz)
+                .is_some_and(|end| end <= self.image.len() as u64);
+            if !in_file {
+                return Err("segment data out of bounds");
+            }
+            let in_user = AddressSpace::is_user(seg.vaddr)
+                && seg
+                    .vaddr
+                    .checked_add(seg.memsz)
+                    .is_some_and(|end| end <= USER_END);
+            if !in_user {
+                return Err("segment outside of user space");
+            }
+            if self
+                .loadable()
+                .take(i)
+                .any(|other| seg.vaddr < other.end() && other.vaddr < seg.end())
+            {
+                return Err("overlapping segments");
+            }
+            if seg.flags & PF_X != 0 && (seg.vaddr..seg.end()).contains(&self.entry) {
+                executable_entry = true;
+            }
+        }
+        if !executable_entry {
+            return Err("entry point is not in an executable segment");
+        }
+        Ok(())
+    }
+
+    /// Maps the segments and a stack into a new address space. Segments
+    /// that share a page get the permissions of both
+    pub fn load(&self) -> Result<AddressSpace, &'static str> {
+        let mut space = AddressSpace::new()?;
+        for seg in self.loadable() {
+            let flags = seg.page_flags();
+            let mut page = seg.vaddr & !(PAGE_SIZE - 1);
+            while page < seg.end() {
+                if space.kernel_ptr(page).is_some() {
+                    space.grant(page, flags)?;
+                } else {
+                    space.map_user(page, flags)?;
+                }
+                page += PAGE_SIZE;
+            }
+            // fresh pages are zeroed, which takes care of .bss
+            let start = seg.offset as usize;
+            space.write(seg.vaddr, &self.image[start..start + seg.filesz as usize])?;
+        }
+        let image_end = self
+            .loadable()
+            .map(|seg| seg.end())
+            .max()
+            .unwrap_or(USER_START);
+        space.set_heap_start(image_end);
+        for i in 1..=USER_STACK_PAGES {
+            space.map_user(
+                USER_STACK_TOP - i * PAGE_SIZE,
+                PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+            )?;
+        }
+        Ok(space)
+    }
+}
+
+/// Loads `image` with its stack set up for `args` and `env`, returns the
+/// space and the stack pointer to start with
+pub fn load_with_args<'a>(
+    image: &'a [u8],
+    args: &[&str],
+    env: &[&str],
+) -> Result<(Elf<'a>, AddressSpace, u64), &'static str> {
+    let elf = Elf::parse(image)?;
+    let mut space = elf.load()?;
+    let mut stack = InitialStack::new(&mut space)?;
+    for arg in args {
+        stack.arg(arg.as_bytes())?;
+    }
+    for var in env {
+        stack.env(var.as_bytes())?;
+    }
+    let sp = stack.finish(&elf)?;
+    Ok((elf, space, sp))
+}
+
+/// Loads `image` and starts it as a new process with `args` as its command
+/// line, returns its pid
+pub fn spawn(name: &'static str, image: &[u8], args: &[&str]) -> Result<usize, &'static str> {
+    let (elf, space, sp) = load_with_args(image, args, super::DEFAULT_ENV)?;
+    serial_info!("starting {} at {:#x}", name, elf.entry);
+    super::spawn(name, space, elf.entry, sp)
+}
+
+/// Header of a little endian x86_64 executable entering at `USER_START`,
+/// with one executable 4 KiB `PT_LOAD` segment at each of `vaddrs`
+#[cfg(test)]
+fn test_image(vaddrs: &[u64]) -> [u8; EHDR_SIZE + 2 * PHDR_SIZE] {
+    let mut image = [0u8; EHDR_SIZE + 2 * PHDR_SIZE];
+    image[..4].copy_from_slice(&MAGIC);
+    image[4] = CLASS_64;
+    image[5] = LITTLE_ENDIAN;
+    image[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+    image[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+    image[24..32].copy_from_slice(&USER_START.to_le_bytes());
+    image[32..40].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+    image[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    image[56..58].copy_from_slice(&(vaddrs.len() as u16).to_le_bytes());
+    for (i, &vaddr) in vaddrs.iter().enumerate() {
+        let phdr = &mut image[EHDR_SIZE + i * PHDR_SIZE..][..PHDR_SIZE];
+        phdr[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        phdr[4..8].copy_from_slice(&PF_X.to_le_bytes());
+        phdr[16..24].copy_from_slice(&vaddr.to_le_bytes());
+        phdr[40..48].copy_from_slice(&0x1000u64.to_le_bytes());
+    }
+    image
+}
+
+#[test_case]
+pub fn test_elf_errors() {
+    serial_info!("Testing ELF header checks");
+    // two executable segments, the second starts inside the first
+    let mut image = test_image(&[USER_START, USER_START + 0x800]);
+    assert_eq!(Elf::parse(&image).err(), Some("overlapping segments"));
+
+    image[56..58].copy_from_slice(&1u16.to_le_bytes());
+    assert!(Elf::parse(&image).is_ok());
+
+    image[18] = 3;
+    assert_eq!(Elf::parse(&image).err(), Some("unsupported machine type"));
+    image[0] = 0;
+    assert_eq!(Elf::parse(&image).err(), Some("bad ELF magic"));
+}
+
+#[test_case]
+pub fn test_initial_stack() {
+    serial_info!("Testing the initial process stack");
+    let image = test_image(&[USER_START]);
+    let (_, space, sp) = load_with_args(&image, &["prog", "-v"], &["HOME=/"]).unwrap();
+    assert_eq!(sp % 16, 0);
+    let word = |at: u64| unsafe { *(space.kernel_ptr(at).unwrap() as *const u64) };
+    let string = |at: u64| unsafe { core::ffi::CStr::from_ptr(space.kernel_ptr(at).unwrap().cast()) };
+    assert_eq!(word(sp), 2);
+    assert_eq!(string(word(sp + 8)).to_bytes(), b"prog");
+    assert_eq!(string(word(sp + 16)).to_bytes(), b"-v");
+    assert_eq!(word(sp + 24), 0);
+    assert_eq!(string(word(sp + 32)).to_bytes(), b"HOME=/");
+    assert_eq!(word(sp + 40), 0);
+    // no segment holds the headers, so the auxv starts at AT_PHENT
+    let mut auxv = sp + 48;
+    let mut entry = None;
+    while word(auxv) != 0 {
+        if word(auxv) == 9 {
+            entry = Some(word(auxv + 8));
+        }
+        auxv += 16;
+    }
+    assert_eq!(entry, Some(USER_START));
+}
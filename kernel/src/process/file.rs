@@ -0,0 +1,218 @@
+use crate::{sync::shitlock::Racy, threading::schedlock::locked};
+
+use super::pipe;
+
+/// Open files shared by every process
+pub const MAX_FILES: usize = 64;
+/// Descriptors a process can have open at once
+pub const MAX_FDS: usize = 16;
+
+/// What an open file reads from and writes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// Keyboard in, screen out
+    Console,
+    PipeRead(usize),
+    PipeWrite(usize),
+}
+
+/// An open file, shared by every descriptor that was duplicated from the
+/// one it was opened on, in this process or across `fork`
+struct OpenFile {
+    kind: FileKind,
+    refs: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref FILES: Racy<[Option<OpenFile>; MAX_FILES]> =
+        Racy::from([(); MAX_FILES].map(|_| None));
+}
+
+/// Takes a free slot of the file table for `kind` with one reference.
+/// A pipe end has to be counted in the pipe already
+pub fn open(kind: FileKind) -> Result<usize, &'static str> {
+    locked(|| {
+        let mut files = FILES.take();
+        let slot = files
+            .iter()
+            .position(|file| file.is_none())
+            .ok_or("file table full")?;
+        files[slot] = Some(OpenFile { kind, refs: 1 });
+        Ok(slot)
+    })
+}
+
+/// Opens both ends of a new pipe, the read end first
+pub fn open_pipe() -> Result<[usize; 2], &'static str> {
+    let pipe = pipe::create()?;
+    let read = open(FileKind::PipeRead(pipe)).inspect_err(|_| {
+        pipe::close_end(pipe, false);
+        pipe::close_end(pipe, true);
+    })?;
+    let write = open(FileKind::PipeWrite(pipe)).inspect_err(|_| {
+        release(read);
+        pipe::close_end(pipe, true);
+    })?;
+    Ok([read, write])
+}
+
+pub fn kind(file: usize) -> FileKind {
+    locked(|| FILES.take()[file].as_ref().expect("file was closed").kind)
+}
+
+fn share(file: usize) {
+    locked(|| FILES.take()[file].as_mut().expect("file was closed").refs += 1)
+}
+
+/// Drops a reference to `file`, the last one closes it
+pub fn release(file: usize) {
+    locked(|| {
+        let mut files = FILES.take();
+        let slot = &mut files[file];
+        let open = slot.as_mut().expect("file was closed");
+        open.refs -= 1;
+        if open.refs > 0 {
+            return;
+        }
+        let kind = open.kind;
+        *slot = None;
+        drop(files);
+        match kind {
+            FileKind::Console => {}
+            FileKind::PipeRead(pipe) => pipe::close_end(pipe, false),
+            FileKind::PipeWrite(pipe) => pipe::close_end(pipe, true),
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fd {
+    file: usize,
+    /// Closed by `execve`
+    cloexec: bool,
+}
+
+/// The descriptors of a process, each one a reference to an open file
+pub struct FdTable {
+    fds: [Option<Fd>; MAX_FDS],
+}
+
+impl FdTable {
+    pub const fn new() -> Self {
+        Self {
+            fds: [None; MAX_FDS],
+        }
+    }
+
+    /// Stdin, stdout and stderr on a fresh console file
+    pub fn with_console() -> Result<Self, &'static str> {
+        let file = open(FileKind::Console)?;
+        let mut table = Self::new();
+        for fd in 0..3 {
+            if fd > 0 {
+                share(file);
+            }
+            table.fds[fd] = Some(Fd {
+                file,
+                cloexec: false,
+            });
+        }
+        Ok(table)
+    }
+
+    fn slot(&self, fd: i32) -> Option<Fd> {
+        usize::try_from(fd).ok().and_then(|fd| *self.fds.get(fd)?)
+    }
+
+    /// Open file behind `fd`
+    pub fn get(&self, fd: i32) -> Option<usize> {
+        self.slot(fd).map(|slot| slot.file)
+    }
+
+    pub fn cloexec(&self, fd: i32) -> Option<bool> {
+        self.slot(fd).map(|slot| slot.cloexec)
+    }
+
+    pub fn set_cloexec(&mut self, fd: i32, cloexec: bool) -> Option<()> {
+        let slot = self.fds.get_mut(usize::try_from(fd).ok()?)?.as_mut()?;
+        slot.cloexec = cloexec;
+        Some(())
+    }
+
+    /// Puts the reference to `file` the caller holds on the lowest free
+    /// descriptor from `min` up. None if they are all taken, the caller
+    /// still holds the reference then
+    pub fn install(&mut self, file: usize, min: usize) -> Option<i32> {
+        let fd = (min..MAX_FDS).find(|&fd| self.fds[fd].is_none())?;
+        self.fds[fd] = Some(Fd {
+            file,
+            cloexec: false,
+        });
+        Some(fd as i32)
+    }
+
+    /// Another descriptor for the file behind `fd`, the lowest free one from
+    /// `min` up. The flags are not copied
+    pub fn dup(&mut self, fd: i32, min: usize) -> Result<i32, &'static str> {
+        let file = self.get(fd).ok_or("bad file descriptor")?;
+        share(file);
+        self.install(file, min).ok_or_else(|| {
+            release(file);
+            "too many open files"
+        })
+    }
+
+    /// Makes `new` refer to the file behind `old`, closing what `new` had
+    /// open before
+    pub fn dup2(&mut self, old: i32, new: i32) -> Result<i32, &'static str> {
+        let file = self.get(old).ok_or("bad file descriptor")?;
+        let slot = usize::try_from(new)
+            .ok()
+            .filter(|&new| new < MAX_FDS)
+            .ok_or("bad file descriptor")?;
+        if old == new {
+            return Ok(new);
+        }
+        share(file);
+        let replaced = self.fds[slot].replace(Fd {
+            file,
+            cloexec: false,
+        });
+        if let Some(replaced) = replaced {
+            release(replaced.file);
+        }
+        Ok(new)
+    }
+
+    pub fn close(&mut self, fd: i32) -> Result<(), &'static str> {
+        let slot = usize::try_from(fd)
+            .ok()
+            .and_then(|fd| self.fds.get_mut(fd))
+            .and_then(|slot| slot.take())
+            .ok_or("bad file descriptor")?;
+        release(slot.file);
+        Ok(())
+    }
+
+    /// A copy for a forked child, every open file gains a reference
+    pub fn fork(&self) -> Self {
+        for slot in self.fds.iter().flatten() {
+            share(slot.file);
+        }
+        Self { fds: self.fds }
+    }
+
+    pub fn close_on_exec(&mut self) {
+        for fd in 0..MAX_FDS {
+            if self.fds[fd].is_some_and(|slot| slot.cloexec) {
+                let _ = self.close(fd as i32);
+            }
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        for fd in 0..MAX_FDS {
+            let _ = self.close(fd as i32);
+        }
+    }
+}
@@ -1 +1,453 @@
-pub mod tcb;
\ No newline at end of file
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    paging::{self, AddressSpace},
+    serial_info,
+    sync::{shitlock::Racy, waitqueue::WaitQueue},
+    threading::{self, schedlock::locked, THREADS},
+};
+use file::FdTable;
+use signal::{SigAction, SigSet, NSIG};
+use user::UserFrame;
+
+pub mod elf;
+pub mod file;
+pub mod pipe;
+pub mod programs;
+pub mod signal;
+pub mod stack;
+pub mod tcb;
+pub mod user;
+
+pub const MAX_PROCESSES: usize = 32;
+/// Parent of the processes the kernel starts itself, never a real process
+pub const KERNEL_PID: usize = 0;
+/// Adopts orphans and reaps them, set up by `init`
+pub const INIT_PID: usize = 1;
+/// Returned when every slot is taken
+pub const PROCESS_TABLE_FULL: &str = "process table full";
+
+/// Environment the kernel starts programs with
+pub const DEFAULT_ENV: &[&str] = &["HOME=/", "PATH=/", "TERM=vga"];
+
+/// A CPU exception raised by user code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub name: &'static str,
+    pub vector: u8,
+    pub rip: u64,
+    pub error_code: u64,
+    /// Faulting address for page faults, 0 otherwise
+    pub address: u64,
+}
+
+impl Fault {
+    /// Signal a Unix kernel would send for this exception
+    pub fn signal(&self) -> i32 {
+        match self.vector {
+            0 => signal::SIGFPE,
+            6 => signal::SIGILL,
+            _ => signal::SIGSEGV,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:#x} (error {:#x}, address {:#x})",
+            self.name, self.rip, self.error_code, self.address
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    Exited(i32),
+    /// Killed by the kernel after a fault in ring 3
+    Killed(Fault),
+    /// Ended by the default action of a signal
+    Signaled(i32),
+}
+
+/// Status word bit for a process ended by a signal that dumps core
+pub const WCOREFLAG: i32 = 0x80;
+
+impl ProcessState {
+    /// The status word `waitpid` reports, in the usual Unix encoding
+    pub fn wait_status(&self) -> i32 {
+        let signaled = |sig: i32| match signal::default_action(sig) {
+            signal::DefaultAction::Core => sig | WCOREFLAG,
+            _ => sig,
+        };
+        match self {
+            Self::Running => 0,
+            Self::Exited(code) => (code & 0xff) << 8,
+            Self::Killed(fault) => signaled(fault.signal()),
+            Self::Signaled(sig) => signaled(*sig),
+        }
+    }
+}
+
+impl fmt::Display for ProcessState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Exited(code) => write!(f, "exited with status {}", code),
+            Self::Killed(fault) => write!(f, "killed by {}", fault),
+            Self::Signaled(sig) => write!(f, "killed by {}", signal::name(*sig)),
+        }
+    }
+}
+
+/// A slot of the process table. Once the process ends it stays behind as a
+/// zombie until its parent collects the state
+pub struct Process {
+    pub pid: usize,
+    pub parent: usize,
+    pub name: &'static str,
+    pub state: ProcessState,
+    /// None once the process is gone and its pages are freed
+    space: Option<AddressSpace>,
+    /// Registers the thread starts out with, taken when it first runs
+    start: Option<UserFrame>,
+    /// Indexed by signal number, 0 is unused
+    actions: [SigAction; NSIG],
+    /// Stopped by a signal until `SIGCONT`
+    stopped: bool,
+    /// Emptied when the process ends
+    files: FdTable,
+}
+
+lazy_static::lazy_static! {
+    static ref PROCESSES: Racy<[Option<Process>; MAX_PROCESSES]> =
+        Racy::from([(); MAX_PROCESSES].map(|_| None));
+}
+
+/// Woken whenever a process ends or changes parent
+static CHILD_EXIT: WaitQueue = WaitQueue::new();
+
+/// Next pid to hand out. Pids only go up, so one that was collected never
+/// reaches a process that took over its slot later
+static NEXT_PID: AtomicUsize = AtomicUsize::new(INIT_PID);
+
+/// The process with `pid` in `processes`
+fn find(processes: &mut [Option<Process>; MAX_PROCESSES], pid: usize) -> Option<&mut Process> {
+    processes
+        .iter_mut()
+        .flatten()
+        .find(|process| process.pid == pid)
+}
+
+/// Takes a free slot of the process table and returns the pid. The files
+/// are closed if there is none
+fn insert(
+    parent: usize,
+    name: &'static str,
+    space: Option<AddressSpace>,
+    start: Option<UserFrame>,
+    actions: [SigAction; NSIG],
+    mut files: FdTable,
+) -> Result<usize, &'static str> {
+    locked(|| {
+        let mut processes = PROCESSES.take();
+        let Some(slot) = processes.iter().position(|process| process.is_none()) else {
+            files.close_all();
+            return Err(PROCESS_TABLE_FULL);
+        };
+        let pid = NEXT_PID.fetch_add(1, Ordering::Relaxed);
+        processes[slot] = Some(Process {
+            pid,
+            parent,
+            name,
+            state: ProcessState::Running,
+            space,
+            start,
+            actions,
+            stopped: false,
+            files,
+        });
+        Ok(pid)
+    })
+}
+
+/// Starts the thread of the fresh process `pid` with `blocked` as its
+/// signal mask. Process threads are never joined, the parent collects the
+/// process instead
+fn start_thread(
+    pid: usize,
+    name: &'static str,
+    pml4: u64,
+    blocked: SigSet,
+) -> Result<usize, &'static str> {
+    let thread = threading::spawn_with(name, user_main, pid, |tcb| {
+        tcb.pml4 = pml4;
+        tcb.process = Some(pid);
+        tcb.blocked = blocked;
+    });
+    match thread {
+        Ok(_) => Ok(pid),
+        Err(err) => {
+            locked(|| {
+                let mut processes = PROCESSES.take();
+                let slot = processes
+                    .iter_mut()
+                    .find(|slot| slot.as_ref().is_some_and(|process| process.pid == pid));
+                if let Some(mut process) = slot.and_then(|slot| slot.take()) {
+                    process.files.close_all();
+                }
+            });
+            Err(err)
+        }
+    }
+}
+
+/// Starts a process that runs `entry` in ring 3 on `space` with its stack
+/// pointer at `user_stack`. The kernel is its parent and it starts with
+/// stdin, stdout and stderr on the console. Returns its pid
+pub fn spawn(
+    name: &'static str,
+    space: AddressSpace,
+    entry: u64,
+    user_stack: u64,
+) -> Result<usize, &'static str> {
+    let pml4 = space.pml4();
+    let start = UserFrame::new(entry, user_stack);
+    let actions = [SigAction::DEFAULT; NSIG];
+    let files = FdTable::with_console()?;
+    let pid = insert(KERNEL_PID, name, Some(space), Some(start), actions, files)?;
+    start_thread(pid, name, pml4, SigSet::EMPTY)
+}
+
+/// Kernel side of a process thread, it only ever leaves through a fault or
+/// `exit_current`
+fn user_main(pid: usize) -> usize {
+    let start = locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = find(&mut processes, pid).expect("process vanished");
+        process.start.take().expect("process started twice")
+    });
+    user::return_to_user(start)
+}
+
+/// Creates pid 1, a kernel thread that takes over orphans and reaps them.
+/// Has to run before any other process is started
+pub fn init() -> Result<(), &'static str> {
+    let actions = [SigAction::DEFAULT; NSIG];
+    let pid = insert(KERNEL_PID, "init", None, None, actions, FdTable::new())?;
+    if pid != INIT_PID {
+        return Err("processes were started before init");
+    }
+    threading::spawn_with("init", reap_orphans, pid, |tcb| tcb.process = Some(pid))?;
+    Ok(())
+}
+
+fn reap_orphans(_: usize) -> usize {
+    loop {
+        match waitpid(INIT_PID, None, true) {
+            Ok(Some((pid, state))) => {
+                release(pid);
+                serial_info!("init reaped orphan {}: {}", pid, state)
+            }
+            _ => CHILD_EXIT.wait(|| has_children(INIT_PID)),
+        }
+    }
+}
+
+fn has_children(parent: usize) -> bool {
+    PROCESSES
+        .take()
+        .iter()
+        .flatten()
+        .any(|process| process.parent == parent)
+}
+
+/// The first ended child of `parent` that matches `which`. None if the
+/// matching children are all still running
+fn ended_child(
+    parent: usize,
+    which: Option<usize>,
+) -> Result<Option<(usize, ProcessState)>, &'static str> {
+    let processes = PROCESSES.take();
+    let mut found = false;
+    for process in processes.iter().flatten() {
+        if process.parent != parent || which.is_some_and(|pid| pid != process.pid) {
+            continue;
+        }
+        found = true;
+        if process.state != ProcessState::Running {
+            return Ok(Some((process.pid, process.state)));
+        }
+    }
+    if found {
+        Ok(None)
+    } else {
+        Err("no such child")
+    }
+}
+
+/// Finds an ended child of `parent`, any child if `which` is None. With
+/// `block` set it waits for one to end, otherwise it returns None. A signal
+/// for a waiting process also ends the wait with None. The child stays a
+/// zombie until the caller is done with its state and calls `release`
+pub fn waitpid(
+    parent: usize,
+    which: Option<usize>,
+    block: bool,
+) -> Result<Option<(usize, ProcessState)>, &'static str> {
+    let mut result = Ok(None);
+    CHILD_EXIT.wait(|| {
+        result = ended_child(parent, which);
+        !block || !matches!(result, Ok(None)) || signal::pending()
+    });
+    result
+}
+
+/// Frees the slot of the ended process `pid`, once its parent collected
+/// the state with `waitpid`
+pub fn release(pid: usize) {
+    locked(|| {
+        let mut processes = PROCESSES.take();
+        let slot = processes.iter_mut().find(|slot| {
+            slot.as_ref()
+                .is_some_and(|process| process.pid == pid && process.state != ProcessState::Running)
+        });
+        if let Some(slot) = slot {
+            *slot = None;
+        }
+    })
+}
+
+/// Waits for process `pid`, started by the kernel, to end. Frees its slot
+/// and returns how it ended
+pub fn wait(pid: usize) -> Result<ProcessState, &'static str> {
+    let (_, state) = waitpid(KERNEL_PID, Some(pid), true)?.expect("blocking wait returned early");
+    release(pid);
+    Ok(state)
+}
+
+/// Process the running thread belongs to
+pub fn current_pid() -> Option<usize> {
+    locked(|| THREADS.take().threads[threading::current().id()].process)
+}
+
+/// Parent of process `pid`
+pub fn parent_of(pid: usize) -> Result<usize, &'static str> {
+    locked(|| {
+        find(&mut PROCESSES.take(), pid)
+            .map(|process| process.parent)
+            .ok_or("no such process")
+    })
+}
+
+/// Runs `f` on the address space of the running process. Only the thread of
+/// a process swaps or frees its space, so it stays put without holding the
+/// scheduler lock for all of `f`
+pub fn with_current_space<R>(f: impl FnOnce(&mut AddressSpace) -> R) -> Result<R, &'static str> {
+    let pid = current_pid().ok_or("not a process")?;
+    let space = locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = find(&mut processes, pid).ok_or("no such process")?;
+        let space = process
+            .space
+            .as_mut()
+            .ok_or("process has no address space")?;
+        Ok::<_, &'static str>(space as *mut AddressSpace)
+    })?;
+    Ok(f(unsafe { &mut *space }))
+}
+
+/// Runs `f` on the descriptors of the running process, with the scheduler
+/// lock held
+pub fn with_current_files<R>(f: impl FnOnce(&mut FdTable) -> R) -> Result<R, &'static str> {
+    let pid = current_pid().ok_or("not a process")?;
+    locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = find(&mut processes, pid).ok_or("no such process")?;
+        Ok(f(&mut process.files))
+    })
+}
+
+/// Copies the running process into a new child that resumes from `frame`
+/// with 0 in rax. The descriptors follow along and share their open files
+/// with the parent, and so do the signal actions and mask but not the
+/// pending signals. Returns the pid of the child
+pub fn fork(frame: &UserFrame) -> Result<usize, &'static str> {
+    let parent = current_pid().ok_or("not a process")?;
+    let space = with_current_space(|space| space.try_clone())??;
+    let (name, actions, files, blocked) = locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = find(&mut processes, parent)?;
+        let blocked = THREADS.take().threads[threading::current().id()].blocked;
+        Some((process.name, process.actions, process.files.fork(), blocked))
+    })
+    .ok_or("no such process")?;
+    let pml4 = space.pml4();
+    let start = UserFrame { rax: 0, ..*frame };
+    let pid = insert(parent, name, Some(space), Some(start), actions, files)?;
+    start_thread(pid, name, pml4, blocked)
+}
+
+/// Replaces the image of the running process with `space`, loaded by the
+/// caller so that a bad file leaves the process as it was. Signal handlers
+/// are reset, the mask stays, and descriptors marked close-on-exec are
+/// closed
+pub fn exec(name: &'static str, space: AddressSpace) -> Result<(), &'static str> {
+    let pid = current_pid().ok_or("not a process")?;
+    let old = locked(|| {
+        THREADS.take().threads[threading::current().id()].pml4 = space.pml4();
+        paging::write_cr3(space.pml4());
+        let mut processes = PROCESSES.take();
+        let process = find(&mut processes, pid).expect("process vanished");
+        process.name = name;
+        signal::reset_on_exec(&mut process.actions);
+        process.files.close_on_exec();
+        process.space.replace(space)
+    });
+    drop(old);
+    Ok(())
+}
+
+/// Ends the process of the running thread. Its pages are freed and its
+/// files closed before the thread exits, its children go to init and its
+/// parent is woken up and sent `SIGCHLD`
+pub fn exit_current(state: ProcessState) -> ! {
+    let pid = current_pid().expect("kernel thread tried to exit a process");
+    let (name, parent, space) = locked(|| {
+        let tid = threading::current().id();
+        THREADS.take().threads[tid].pml4 = 0;
+        paging::write_cr3(paging::kernel_pml4());
+        let mut processes = PROCESSES.take();
+        for child in processes.iter_mut().flatten() {
+            if child.parent == pid {
+                child.parent = INIT_PID;
+            }
+        }
+        let process = find(&mut processes, pid).expect("process vanished");
+        process.state = state;
+        process.files.close_all();
+        CHILD_EXIT.wake_all();
+        (process.name, process.parent, process.space.take())
+    });
+    // neither the kernel nor init take signals
+    let _ = signal::send(parent, signal::SIGCHLD);
+    if state != ProcessState::Exited(0) {
+        serial_info!("process {} ({}) {}", pid, name, state);
+    }
+    drop(space);
+    let code = match state {
+        ProcessState::Exited(code) => code as usize,
+        _ => usize::MAX,
+    };
+    threading::exit(code)
+}
+
+/// Ends the process after a fault it has no handler for
+pub fn kill_current(fault: Fault) -> ! {
+    exit_current(ProcessState::Killed(fault))
+}
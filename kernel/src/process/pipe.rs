@@ -0,0 +1,186 @@
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    sync::{shitlock::Racy, waitqueue::WaitQueue},
+    threading::schedlock::locked,
+};
+
+use super::signal;
+
+pub const MAX_PIPES: usize = 16;
+/// Bytes a pipe holds before writers have to wait
+pub const PIPE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeError {
+    /// A signal came in before anything was moved
+    Interrupted,
+    /// Written to with every read end closed
+    Broken,
+}
+
+struct Pipe {
+    buf: RingBuf<u8, PIPE_SIZE>,
+    /// Open files for each end, the pipe is freed once both are 0
+    readers: usize,
+    writers: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref PIPES: Racy<[Option<Pipe>; MAX_PIPES]> =
+        Racy::from([(); MAX_PIPES].map(|_| None));
+}
+
+/// Woken when a pipe gets data or loses its last writer
+static READABLE: [WaitQueue; MAX_PIPES] = [const { WaitQueue::new() }; MAX_PIPES];
+/// Woken when a pipe gets room or loses its last reader
+static WRITABLE: [WaitQueue; MAX_PIPES] = [const { WaitQueue::new() }; MAX_PIPES];
+
+/// Makes an empty pipe with one reader and one writer, returns its index
+pub fn create() -> Result<usize, &'static str> {
+    locked(|| {
+        let mut pipes = PIPES.take();
+        let slot = pipes
+            .iter()
+            .position(|pipe| pipe.is_none())
+            .ok_or("pipe table full")?;
+        pipes[slot] = Some(Pipe {
+            buf: RingBuf::new(),
+            readers: 1,
+            writers: 1,
+        });
+        Ok(slot)
+    })
+}
+
+/// Another open file for one end of `pipe`
+pub fn open_end(pipe: usize, write: bool) {
+    locked(|| {
+        let mut pipes = PIPES.take();
+        let pipe = pipes[pipe].as_mut().expect("pipe end outlived its pipe");
+        if write {
+            pipe.writers += 1;
+        } else {
+            pipe.readers += 1;
+        }
+    })
+}
+
+/// Drops an open file for one end of `pipe`. The other side is woken when
+/// the last one goes, so readers see EOF and writers a broken pipe
+pub fn close_end(pipe: usize, write: bool) {
+    locked(|| {
+        let mut pipes = PIPES.take();
+        let slot = &mut pipes[pipe];
+        let ends = slot.as_mut().expect("pipe end outlived its pipe");
+        let left = if write {
+            ends.writers -= 1;
+            ends.writers
+        } else {
+            ends.readers -= 1;
+            ends.readers
+        };
+        if ends.readers == 0 && ends.writers == 0 {
+            *slot = None;
+        }
+        if left == 0 {
+            let waiting = if write { &READABLE } else { &WRITABLE };
+            waiting[pipe].wake_all();
+        }
+    })
+}
+
+/// Moves what is buffered into `buf`, 0 means every writer is gone. With
+/// `block` set it waits until there is something to read
+pub fn read(pipe: usize, buf: &mut [u8], block: bool) -> Result<usize, PipeError> {
+    let mut result = Ok(0);
+    signal::interruptible(|| {
+        READABLE[pipe].wait(|| {
+            let mut pipes = PIPES.take();
+            let ends = pipes[pipe].as_mut().expect("pipe end outlived its pipe");
+            let mut read = 0;
+            while read < buf.len() {
+                let Some(byte) = ends.buf.take() else {
+                    break;
+                };
+                buf[read] = byte;
+                read += 1;
+            }
+            result = Ok(read);
+            if read > 0 || ends.writers == 0 || !block || buf.is_empty() {
+                return true;
+            }
+            if signal::pending() {
+                result = Err(PipeError::Interrupted);
+                return true;
+            }
+            false
+        })
+    });
+    if matches!(result, Ok(read) if read > 0) {
+        WRITABLE[pipe].wake_all();
+    }
+    result
+}
+
+/// Moves as much of `data` into the pipe as fits, waiting until at least
+/// some of it does
+pub fn write(pipe: usize, data: &[u8]) -> Result<usize, PipeError> {
+    let mut result = Ok(0);
+    signal::interruptible(|| {
+        WRITABLE[pipe].wait(|| {
+            let mut pipes = PIPES.take();
+            let ends = pipes[pipe].as_mut().expect("pipe end outlived its pipe");
+            if ends.readers == 0 {
+                result = Err(PipeError::Broken);
+                return true;
+            }
+            let written = data
+                .iter()
+                .take_while(|&&byte| ends.buf.push(byte).is_ok())
+                .count();
+            result = Ok(written);
+            if written > 0 || data.is_empty() {
+                return true;
+            }
+            if signal::pending() {
+                result = Err(PipeError::Interrupted);
+                return true;
+            }
+            false
+        })
+    });
+    if matches!(result, Ok(written) if written > 0) {
+        READABLE[pipe].wake_all();
+    }
+    result
+}
+
+#[test_case]
+pub fn test_pipe() {
+    crate::serial_info!("Testing pipes");
+    let pipe = create().unwrap();
+    assert_eq!(write(pipe, b"hello"), Ok(5));
+    let mut buf = [0; 3];
+    assert_eq!(read(pipe, &mut buf, true), Ok(3));
+    assert_eq!(&buf, b"hel");
+    assert_eq!(read(pipe, &mut buf, false), Ok(2));
+    assert_eq!(read(pipe, &mut buf, false), Ok(0));
+    // fills up without blocking and leaves the rest to the caller
+    assert_eq!(write(pipe, &[7; PIPE_SIZE + 10]), Ok(PIPE_SIZE));
+    open_end(pipe, true);
+    close_end(pipe, true);
+    close_end(pipe, true);
+    let mut drained = 0;
+    while let Ok(read @ 1..) = read(pipe, &mut buf, true) {
+        drained += read;
+    }
+    assert_eq!(drained, PIPE_SIZE);
+    assert_eq!(read(pipe, &mut buf, true), Ok(0));
+    close_end(pipe, false);
+    assert!(locked(|| PIPES.take()[pipe].is_none()));
+
+    let pipe = create().unwrap();
+    close_end(pipe, false);
+    assert_eq!(write(pipe, b"x"), Err(PipeError::Broken));
+    close_end(pipe, true);
+}
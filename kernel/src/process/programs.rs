@@ -0,0 +1,14 @@
+// `PROGRAMS` lists the user programs build.rs found, as (name, ELF image)
+include!(concat!(env!("OUT_DIR"), "/user_programs.rs"));
+
+/// Name and ELF image of the program called `name`
+pub fn find(name: &str) -> Option<(&'static str, &'static [u8])> {
+    PROGRAMS
+        .iter()
+        .find(|(program, _)| *program == name)
+        .copied()
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    PROGRAMS.iter().map(|&(name, _)| name)
+}
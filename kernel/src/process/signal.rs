@@ -0,0 +1,545 @@
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{tcb::ThreadState, user::UserFrame, Fault, ProcessState, PROCESSES};
+use crate::{
+    descriptors::gdt::{USER_CODE_SELECTOR, USER_DATA_SELECTOR},
+    paging::AddressSpace,
+    serial_info,
+    sync::waitqueue::WaitQueue,
+    syscall::uaccess::{copy_to_user, UserPtr},
+    threading::{
+        self, context::RFLAGS_IF, policy::EnqueueReason, schedlock::locked, scheduler, THREADS,
+    },
+};
+
+/// Signals are numbered 1 to `NSIG - 1`, the numbers are the Linux ones
+pub const NSIG: usize = 32;
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGWINCH: i32 = 28;
+
+/// `handler` values that are not functions
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/// `restorer` is set, the only way a handler can return
+pub const SA_RESTORER: u64 = 0x0400_0000;
+/// The signal is not blocked while its handler runs
+pub const SA_NODEFER: u64 = 0x4000_0000;
+/// The action goes back to the default once the handler was called
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+/// `sigprocmask` operations
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+/// Flags that user code may change through `sigreturn`: CF, PF, AF, ZF,
+/// SF, TF, DF, OF and AC
+const USER_RFLAGS: u64 = 0x0004_0dd5;
+/// Below the interrupted stack pointer, the ABI lets leaf functions use it
+const RED_ZONE: u64 = 128;
+
+/// A set of signals, bit `n - 1` stands for signal `n`
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SigSet(pub u64);
+
+impl SigSet {
+    pub const EMPTY: Self = Self(0);
+    /// Neither can be blocked, caught or ignored
+    const UNBLOCKABLE: Self = Self(1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1));
+
+    fn bit(sig: i32) -> u64 {
+        1 << (sig - 1)
+    }
+
+    pub fn contains(&self, sig: i32) -> bool {
+        self.0 & Self::bit(sig) != 0
+    }
+
+    pub fn add(&mut self, sig: i32) {
+        self.0 |= Self::bit(sig);
+    }
+
+    pub fn remove(&mut self, sig: i32) {
+        self.0 &= !Self::bit(sig);
+    }
+
+    /// Lowest numbered signal in the set
+    fn first(&self) -> Option<i32> {
+        (self.0 != 0).then(|| self.0.trailing_zeros() as i32 + 1)
+    }
+
+    /// The set with the signals that cannot be blocked taken out
+    fn blockable(self) -> Self {
+        Self(self.0 & !Self::UNBLOCKABLE.0)
+    }
+}
+
+/// What a process wants done with a signal. Laid out like Linux's
+/// `struct kernel_sigaction`, which is what `sigaction` copies in and out
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of the handler
+    pub handler: u64,
+    pub flags: u64,
+    /// Where the handler returns to, has to call `sigreturn`
+    pub restorer: u64,
+    /// Blocked on top of the current mask while the handler runs
+    pub mask: SigSet,
+}
+
+impl SigAction {
+    pub const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        flags: 0,
+        restorer: 0,
+        mask: SigSet::EMPTY,
+    };
+
+    fn is_handler(&self) -> bool {
+        self.handler != SIG_DFL && self.handler != SIG_IGN
+    }
+}
+
+/// What happens to a signal nobody installed a handler for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    /// Terminate, a Unix kernel would also dump core
+    Core,
+    Stop,
+    Continue,
+    Ignore,
+}
+
+pub fn default_action(sig: i32) -> DefaultAction {
+    match sig {
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV => DefaultAction::Core,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+pub fn is_valid(sig: i32) -> bool {
+    (1..NSIG as i32).contains(&sig)
+}
+
+pub fn name(sig: i32) -> &'static str {
+    match sig {
+        SIGHUP => "SIGHUP",
+        SIGINT => "SIGINT",
+        SIGQUIT => "SIGQUIT",
+        SIGILL => "SIGILL",
+        SIGTRAP => "SIGTRAP",
+        SIGABRT => "SIGABRT",
+        SIGBUS => "SIGBUS",
+        SIGFPE => "SIGFPE",
+        SIGKILL => "SIGKILL",
+        SIGUSR1 => "SIGUSR1",
+        SIGSEGV => "SIGSEGV",
+        SIGUSR2 => "SIGUSR2",
+        SIGPIPE => "SIGPIPE",
+        SIGALRM => "SIGALRM",
+        SIGTERM => "SIGTERM",
+        SIGCHLD => "SIGCHLD",
+        SIGCONT => "SIGCONT",
+        SIGSTOP => "SIGSTOP",
+        SIGTSTP => "SIGTSTP",
+        SIGTTIN => "SIGTTIN",
+        SIGTTOU => "SIGTTOU",
+        SIGURG => "SIGURG",
+        SIGWINCH => "SIGWINCH",
+        _ => "signal",
+    }
+}
+
+/// What a handler finds on its stack above the return address. `sigreturn`
+/// puts the registers and the mask back from it
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    signo: u64,
+    blocked: SigSet,
+    regs: UserFrame,
+}
+
+/// Stopped processes wait here for `SIGCONT`
+static CONTINUED: WaitQueue = WaitQueue::new();
+/// Process Ctrl-C goes to, 0 if the shell has none running
+static FOREGROUND: AtomicUsize = AtomicUsize::new(0);
+
+/// Thread of process `pid`, each process has exactly one
+fn thread_of(pid: usize) -> Option<usize> {
+    THREADS
+        .take()
+        .iter()
+        .find(|tcb| tcb.process == Some(pid) && tcb.is_alive())
+        .map(|tcb| tcb.thread_id)
+}
+
+/// Makes `sig` pending for process `pid`. A thread blocked in an
+/// interruptible wait is woken so it can give up with `EINTR`, one running
+/// in ring 3 on another cpu is interrupted so it notices right away
+pub fn send(pid: usize, sig: i32) -> Result<(), &'static str> {
+    if sig != 0 && !is_valid(sig) {
+        return Err("invalid signal");
+    }
+    if pid == super::INIT_PID {
+        return Err("init cannot be signalled");
+    }
+    locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = super::find(&mut processes, pid).ok_or("no such process")?;
+        if sig == 0 || process.state != ProcessState::Running {
+            return Ok(());
+        }
+        if sig == SIGCONT || sig == SIGKILL {
+            process.stopped = false;
+        }
+        let action = process.actions[sig as usize];
+        drop(processes);
+        if sig == SIGCONT {
+            CONTINUED.wake_all();
+        }
+        let ignored = match action.handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(
+                default_action(sig),
+                DefaultAction::Ignore | DefaultAction::Continue
+            ),
+            _ => false,
+        };
+        if ignored && !SigSet::UNBLOCKABLE.contains(sig) {
+            return Ok(());
+        }
+        let Some(tid) = thread_of(pid) else {
+            return Ok(());
+        };
+        let (interrupt, running) = {
+            let mut threads = THREADS.take();
+            let tcb = &mut threads.threads[tid];
+            tcb.pending.add(sig);
+            let deliverable = !tcb.blocked.contains(sig);
+            let interrupt = deliverable && tcb.interruptible && tcb.state == ThreadState::Blocked;
+            if interrupt {
+                // the same as a timed out wait, so the wait queue drops it
+                tcb.wake_at = None;
+                tcb.timed_out = tcb.wait_channel != 0;
+                tcb.wait_channel = 0;
+            }
+            (interrupt, deliverable && tcb.state == ThreadState::Running)
+        };
+        if interrupt {
+            scheduler::wake(tid, EnqueueReason::Wakeup);
+        } else if running {
+            scheduler::notify(tid);
+        }
+        Ok(())
+    })
+}
+
+/// Whether the running thread has a signal that is not blocked
+pub fn pending() -> bool {
+    locked(|| {
+        let threads = THREADS.take();
+        let tcb = &threads.threads[threading::current().id()];
+        tcb.pending.0 & !tcb.blocked.0 != 0
+    })
+}
+
+/// Runs `f` with the running thread marked as woken by signals. Waits in
+/// `f` have to check `pending` every time they wake up
+pub fn interruptible<R>(f: impl FnOnce() -> R) -> R {
+    let tid = threading::current().id();
+    let set = |on: bool| locked(|| THREADS.take().threads[tid].interruptible = on);
+    set(true);
+    let ret = f();
+    set(false);
+    ret
+}
+
+/// Takes the lowest deliverable signal off the running thread
+fn take_pending() -> Option<i32> {
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tcb = &mut threads.threads[threading::current().id()];
+        let sig = SigSet(tcb.pending.0 & !tcb.blocked.0).first()?;
+        tcb.pending.remove(sig);
+        Some(sig)
+    })
+}
+
+fn current_action(pid: usize, sig: i32) -> SigAction {
+    locked(|| {
+        super::find(&mut PROCESSES.take(), pid)
+            .map_or(SigAction::DEFAULT, |process| process.actions[sig as usize])
+    })
+}
+
+/// Replaces the action for `sig` of the running process, returns the old one
+pub fn set_action(sig: i32, action: Option<SigAction>) -> Result<SigAction, &'static str> {
+    if !is_valid(sig) {
+        return Err("invalid signal");
+    }
+    let pid = super::current_pid().ok_or("not a process")?;
+    if action.is_some() && SigSet::UNBLOCKABLE.contains(sig) {
+        return Err("signal cannot be caught");
+    }
+    if action.is_some_and(|action| action.is_handler() && action.flags & SA_RESTORER == 0) {
+        return Err("handler without a restorer");
+    }
+    locked(|| {
+        let mut processes = PROCESSES.take();
+        let process = super::find(&mut processes, pid).ok_or("no such process")?;
+        let old = process.actions[sig as usize];
+        if let Some(mut action) = action {
+            action.mask = action.mask.blockable();
+            process.actions[sig as usize] = action;
+        }
+        Ok(old)
+    })
+}
+
+/// Changes the blocked mask of the running thread by `how`, returns the
+/// old mask
+pub fn set_blocked(how: u32, set: Option<SigSet>) -> Result<SigSet, &'static str> {
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tcb = &mut threads.threads[threading::current().id()];
+        let old = tcb.blocked;
+        if let Some(set) = set {
+            tcb.blocked = match how {
+                SIG_BLOCK => SigSet(old.0 | set.0),
+                SIG_UNBLOCK => SigSet(old.0 & !set.0),
+                SIG_SETMASK => set,
+                _ => return Err("invalid sigprocmask operation"),
+            }
+            .blockable();
+        }
+        Ok(old)
+    })
+}
+
+/// The process Ctrl-C is sent to, None once it is back at the shell
+pub fn set_foreground(pid: Option<usize>) {
+    FOREGROUND.store(pid.unwrap_or(0), Ordering::Release);
+}
+
+/// Ctrl-C from the keyboard, runs in the interrupt handler
+pub fn interrupt_foreground() {
+    let pid = FOREGROUND.load(Ordering::Acquire);
+    if pid != 0 {
+        let _ = send(pid, SIGINT);
+    }
+}
+
+/// Parks a stopped process until `SIGCONT` or `SIGKILL`, other signals
+/// stay pending until then
+fn stop_current(pid: usize) {
+    serial_info!("process {} stopped", pid);
+    let tid = threading::current().id();
+    let stopped = || {
+        let killed = THREADS.take().threads[tid].pending.contains(SIGKILL);
+        !killed && super::find(&mut PROCESSES.take(), pid).is_some_and(|process| process.stopped)
+    };
+    locked(|| super::find(&mut PROCESSES.take(), pid).map(|p| p.stopped = true));
+    while locked(stopped) {
+        interruptible(|| CONTINUED.wait(|| !stopped()));
+    }
+}
+
+/// Pushes a frame for `sig` on the user stack of `regs` and points `regs`
+/// at the handler. The registers are what `sigreturn` resumes with
+fn push_frame(
+    regs: &mut UserFrame,
+    sig: i32,
+    action: &SigAction,
+    blocked: SigSet,
+) -> Result<(), &'static str> {
+    // `iretq` to a kernel or non-canonical address would fault in ring 0
+    if !AddressSpace::is_user(action.handler) {
+        return Err("bad handler address");
+    }
+    let frame = SignalFrame {
+        signo: sig as u64,
+        blocked,
+        regs: *regs,
+    };
+    let base = regs
+        .rsp
+        .checked_sub(RED_ZONE + size_of::<SignalFrame>() as u64)
+        .ok_or("user stack overflow")?
+        & !15;
+    // the handler starts as if called, with its return address at rsp
+    let sp = base - 8;
+    UserPtr::<SignalFrame>::new(base)
+        .write(frame)
+        .and_then(|_| copy_to_user(sp, &action.restorer.to_le_bytes()))
+        .map_err(|_| "bad user stack")?;
+    *regs = UserFrame {
+        rdi: sig as u64,
+        ..UserFrame::new(action.handler, sp)
+    };
+    Ok(())
+}
+
+/// Runs the handler of `sig` or blocks it for the duration, taking the
+/// mask and flags of `action` into account. Ends the process if the frame
+/// does not fit on its stack or the handler is not in user memory
+fn enter_handler(regs: &mut UserFrame, sig: i32, action: SigAction) {
+    let pid = super::current_pid().expect("signal for a kernel thread");
+    let blocked = set_blocked(SIG_SETMASK, None).expect("reading the mask fails");
+    if push_frame(regs, sig, &action, blocked).is_err() {
+        super::exit_current(ProcessState::Signaled(SIGSEGV));
+    }
+    let mut mask = action.mask;
+    if action.flags & SA_NODEFER == 0 {
+        mask.add(sig);
+    }
+    let _ = set_blocked(SIG_BLOCK, Some(mask));
+    if action.flags & SA_RESETHAND != 0 {
+        locked(|| {
+            if let Some(process) = super::find(&mut PROCESSES.take(), pid) {
+                process.actions[sig as usize] = SigAction::DEFAULT;
+            }
+        });
+    }
+}
+
+/// Acts on the pending signals on the way back to ring 3, from a syscall or
+/// an interrupt that came in from there. Default actions are carried out
+/// here, a handler is set up in `frame` and the rest stay pending until it
+/// calls `sigreturn`
+pub fn deliver(frame: &mut UserFrame) {
+    let Some(pid) = super::current_pid() else {
+        return;
+    };
+    while let Some(sig) = take_pending() {
+        let action = current_action(pid, sig);
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL => match default_action(sig) {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Stop => stop_current(pid),
+                DefaultAction::Terminate | DefaultAction::Core => {
+                    super::exit_current(ProcessState::Signaled(sig))
+                }
+            },
+            _ => {
+                enter_handler(frame, sig, action);
+                return;
+            }
+        }
+    }
+}
+
+/// Turns a CPU exception in ring 3 into its signal. With a handler that is
+/// not blocked the process goes on there, and `sigreturn` takes it back to
+/// the faulting instruction with `regs`. Otherwise the process is killed
+pub fn deliver_fault(fault: Fault, regs: &mut UserFrame) {
+    let sig = fault.signal();
+    let pid = super::current_pid().expect("kernel thread faulted in ring 3");
+    let action = current_action(pid, sig);
+    let blocked = set_blocked(SIG_SETMASK, None).expect("reading the mask fails");
+    if action.is_handler() && !blocked.contains(sig) {
+        enter_handler(regs, sig, action);
+        return;
+    }
+    super::kill_current(fault)
+}
+
+/// Puts back the registers and mask from the frame at the user stack
+/// pointer of `regs`, which the restorer left there. Returns the restored
+/// rax. A frame that cannot be read ends the process
+pub fn sigreturn(regs: &mut UserFrame) -> u64 {
+    let Ok(frame) = UserPtr::<SignalFrame>::new(regs.rsp).read() else {
+        super::exit_current(ProcessState::Signaled(SIGSEGV));
+    };
+    let _ = set_blocked(SIG_SETMASK, Some(frame.blocked));
+    *regs = UserFrame {
+        cs: USER_CODE_SELECTOR as u64,
+        ss: USER_DATA_SELECTOR as u64,
+        rflags: frame.regs.rflags & USER_RFLAGS | RFLAGS_IF,
+        ..frame.regs
+    };
+    regs.rax
+}
+
+/// Handlers do not survive `execve`, the code they point to is gone.
+/// Ignored signals stay ignored
+pub fn reset_on_exec(actions: &mut [SigAction; NSIG]) {
+    for action in actions.iter_mut() {
+        if action.handler != SIG_IGN {
+            *action = SigAction::DEFAULT;
+        }
+    }
+}
+
+#[test_case]
+pub fn test_signal_sets() {
+    crate::serial_info!("Testing signal sets and default actions");
+    let mut set = SigSet::EMPTY;
+    set.add(SIGTERM);
+    set.add(SIGINT);
+    assert!(set.contains(SIGINT) && !set.contains(SIGKILL));
+    assert_eq!(set.first(), Some(SIGINT));
+    set.remove(SIGINT);
+    assert_eq!(set.first(), Some(SIGTERM));
+    set.add(SIGKILL);
+    set.add(SIGSTOP);
+    assert_eq!(set.blockable(), SigSet(1 << (SIGTERM - 1)));
+    assert_eq!(default_action(SIGSEGV), DefaultAction::Core);
+    assert_eq!(default_action(SIGTSTP), DefaultAction::Stop);
+    assert_eq!(default_action(SIGCHLD), DefaultAction::Ignore);
+    assert_eq!(default_action(SIGPIPE), DefaultAction::Terminate);
+    assert!(!is_valid(0) && !is_valid(NSIG as i32) && is_valid(SIGUSR1));
+    assert_eq!(size_of::<SigAction>(), 32);
+    assert_eq!(
+        ProcessState::Signaled(SIGSEGV).wait_status(),
+        SIGSEGV | super::WCOREFLAG
+    );
+    assert_eq!(ProcessState::Signaled(SIGTERM).wait_status(), SIGTERM);
+}
+
+#[test_case]
+pub fn test_handler_address() {
+    crate::serial_info!("Testing that handlers have to be in user memory");
+    let action = SigAction {
+        handler: sigreturn as fn(&mut UserFrame) -> u64 as usize as u64,
+        flags: SA_RESTORER,
+        ..SigAction::DEFAULT
+    };
+    let start = crate::paging::address_space::USER_START;
+    let mut regs = UserFrame::new(start, start);
+    assert!(push_frame(&mut regs, SIGUSR1, &action, SigSet::EMPTY).is_err());
+    assert_eq!(regs.rip, start);
+}
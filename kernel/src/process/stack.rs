@@ -0,0 +1,146 @@
+use super::elf::Elf;
+use crate::{
+    paging::{address_space::USER_STACK_TOP, AddressSpace, PAGE_SIZE},
+    utils::asm,
+};
+
+/// Most `argv` or `envp` entries a process can start with
+pub const MAX_ARGS: usize = 32;
+/// Longest single argument or environment variable, with the NUL
+pub const MAX_ARG_LEN: usize = 512;
+/// Room for the strings on the initial stack, the rest is left to the program
+pub const ARG_MAX: u64 = 2 * PAGE_SIZE;
+
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_RANDOM: u64 = 25;
+
+/// Words below the strings: argc, both pointer arrays with their NULLs
+/// and the auxv pairs
+const MAX_WORDS: usize = 1 + 2 * (MAX_ARGS + 1) + 2 * 7;
+
+/// Lays out the System V initial process stack in a space that is not
+/// loaded yet. Strings go at the top, the 16 `AT_RANDOM` bytes first, then
+/// `finish` puts argc, argv, envp and the auxv below them
+pub struct InitialStack<'a> {
+    space: &'a mut AddressSpace,
+    sp: u64,
+    random: u64,
+    argv: [u64; MAX_ARGS],
+    argc: usize,
+    envp: [u64; MAX_ARGS],
+    envc: usize,
+}
+
+impl<'a> InitialStack<'a> {
+    /// The stack pages have to be mapped already
+    pub fn new(space: &'a mut AddressSpace) -> Result<Self, &'static str> {
+        let mut stack = Self {
+            space,
+            sp: USER_STACK_TOP,
+            random: 0,
+            argv: [0; MAX_ARGS],
+            argc: 0,
+            envp: [0; MAX_ARGS],
+            envc: 0,
+        };
+        stack.random = stack.push(&random_bytes())?;
+        Ok(stack)
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<u64, &'static str> {
+        let sp = self.sp - bytes.len() as u64;
+        if USER_STACK_TOP - sp > ARG_MAX {
+            return Err("arguments too long");
+        }
+        self.space.write(sp, bytes)?;
+        self.sp = sp;
+        Ok(sp)
+    }
+
+    /// Copies `bytes` with a NUL after them, returns where they went
+    fn push_str(&mut self, bytes: &[u8]) -> Result<u64, &'static str> {
+        self.push(&[0])?;
+        self.push(bytes)
+    }
+
+    /// Adds the next `argv` entry
+    pub fn arg(&mut self, arg: &[u8]) -> Result<(), &'static str> {
+        if self.argc == MAX_ARGS {
+            return Err("too many arguments");
+        }
+        self.argv[self.argc] = self.push_str(arg)?;
+        self.argc += 1;
+        Ok(())
+    }
+
+    /// Adds the next `envp` entry, `NAME=value`
+    pub fn env(&mut self, var: &[u8]) -> Result<(), &'static str> {
+        if self.envc == MAX_ARGS {
+            return Err("too many environment variables");
+        }
+        self.envp[self.envc] = self.push_str(var)?;
+        self.envc += 1;
+        Ok(())
+    }
+
+    /// Writes argc, the pointer arrays and the auxv for `elf`. Returns the
+    /// stack pointer to start with, 16 byte aligned as the ABI wants
+    pub fn finish(self, elf: &Elf) -> Result<u64, &'static str> {
+        let mut words = [0u64; MAX_WORDS];
+        let mut len = 0;
+        let mut put = |word: u64| {
+            words[len] = word;
+            len += 1;
+        };
+        put(self.argc as u64);
+        self.argv[..self.argc].iter().for_each(|&arg| put(arg));
+        put(0);
+        self.envp[..self.envc].iter().for_each(|&var| put(var));
+        put(0);
+        if let Some(phdr) = elf.phdr_addr() {
+            put(AT_PHDR);
+            put(phdr);
+        }
+        let auxv = [
+            (AT_PHENT, elf.phent() as u64),
+            (AT_PHNUM, elf.phnum() as u64),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_ENTRY, elf.entry),
+            (AT_RANDOM, self.random),
+            (AT_NULL, 0),
+        ];
+        for (kind, value) in auxv {
+            put(kind);
+            put(value);
+        }
+        // the stack has room for ARG_MAX and all of the words
+        let sp = (self.sp - (len * 8) as u64) & !15;
+        for (i, word) in words[..len].iter().enumerate() {
+            self.space.write(sp + i as u64 * 8, &word.to_le_bytes())?;
+        }
+        Ok(sp)
+    }
+}
+
+/// Seed for `AT_RANDOM`. From the time stamp counter, so programs can use
+/// it for hashing but not for anything secret
+fn random_bytes() -> [u8; 16] {
+    let mut state = asm::read_tsc();
+    let mut next = || {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&next().to_le_bytes());
+    bytes[8..].copy_from_slice(&next().to_le_bytes());
+    bytes
+}
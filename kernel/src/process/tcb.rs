@@ -1,13 +1,147 @@
+use super::signal::SigSet;
+use crate::{
+    cpu::{CpuMask, ALL_CPUS},
+    paging,
+    threading::{context::Context, policy::DEFAULT_PRIORITY, ThreadFn},
+};
 
-use crate::descriptors::reg::*;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    /// Slot is unused
+    Free,
+    Ready,
+    Running,
+    Blocked,
+    /// Finished, holding its return value until joined
+    Exited,
+}
 
-#[repr(C)]
-struct TaskControlBlock {
-    thread_id: usize,
-    cr0: usize,
-    
+/// Where a thread spent its time, all times are in timer ticks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuStats {
+    /// Ticks that interrupted the thread in ring 3
+    pub user_ticks: u64,
+    /// Ticks that interrupted the thread in the kernel
+    pub system_ticks: u64,
+    /// Time spent runnable but waiting for a cpu
+    pub wait_ticks: u64,
+    /// Switches where the thread blocked, yielded or exited
+    pub voluntary_switches: u64,
+    /// Switches where the thread was preempted
+    pub involuntary_switches: u64,
+    pub last_cpu: usize,
+    /// Times the thread was moved to another cpu's run queue
+    pub migrations: u64,
+    /// Tick the thread last became ready at
+    pub ready_since: u64,
+}
+
+impl CpuStats {
+    pub const fn new() -> Self {
+        Self {
+            user_ticks: 0,
+            system_ticks: 0,
+            wait_ticks: 0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+            last_cpu: 0,
+            migrations: 0,
+            ready_since: 0,
+        }
+    }
+
+    /// Ticks the thread actually ran for
+    pub fn busy_ticks(&self) -> u64 {
+        self.user_ticks + self.system_ticks
+    }
 }
 
+#[repr(C)]
+pub struct TaskControlBlock {
+    pub thread_id: usize,
+    pub context: Context,
+    pub state: ThreadState,
+    pub name: &'static str,
+    pub stack_top: *mut u8,
+    pub entry: Option<ThreadFn>,
+    pub arg: usize,
+    pub retval: usize,
+    /// Ticks left before the timer preempts this thread
+    pub time_slice: u32,
+    /// Higher runs first under the priority policies
+    pub priority: u8,
+    /// -20..=19, lowers the effective priority when positive
+    pub nice: i8,
+    /// Current MLFQ level, 0 is the top
+    pub mlfq_level: u8,
+    /// Wait queue the thread is parked on, 0 when it is not
+    pub wait_channel: usize,
+    /// Tick at which a blocked thread is woken even if nobody wakes it
+    pub wake_at: Option<u64>,
+    /// Set when `wake_at` fired before the wait queue woke it
+    pub timed_out: bool,
+    /// Thread blocked in `join` on this one
+    pub joiner: Option<usize>,
+    /// Nobody will join, the slot is freed as soon as it exits
+    pub detached: bool,
+    pub stats: CpuStats,
+    /// Cpus the thread may run on
+    pub affinity: CpuMask,
+    /// Cpu whose run queue the thread was last put on
+    pub cpu: usize,
+    /// PML4 the thread runs on, 0 for the kernel's
+    pub pml4: u64,
+    /// Process the thread belongs to, None for kernel threads
+    pub process: Option<usize>,
+    /// Signals sent but not acted on yet
+    pub pending: SigSet,
+    /// Signals that stay pending until they are unblocked
+    pub blocked: SigSet,
+    /// Blocked somewhere a signal may cut short, see `signal::interruptible`
+    pub interruptible: bool,
+}
 
+impl TaskControlBlock {
+    pub const fn empty(thread_id: usize) -> Self {
+        Self {
+            thread_id,
+            context: Context { rsp: 0 },
+            state: ThreadState::Free,
+            name: "",
+            stack_top: core::ptr::null_mut(),
+            entry: None,
+            arg: 0,
+            retval: 0,
+            time_slice: 0,
+            priority: DEFAULT_PRIORITY,
+            nice: 0,
+            mlfq_level: 0,
+            wait_channel: 0,
+            wake_at: None,
+            timed_out: false,
+            joiner: None,
+            detached: false,
+            stats: CpuStats::new(),
+            affinity: ALL_CPUS,
+            cpu: 0,
+            pml4: 0,
+            process: None,
+            pending: SigSet::EMPTY,
+            blocked: SigSet::EMPTY,
+            interruptible: false,
+        }
+    }
 
+    /// What CR3 has to hold while the thread runs
+    pub fn page_table(&self) -> u64 {
+        if self.pml4 == 0 {
+            paging::kernel_pml4()
+        } else {
+            self.pml4
+        }
+    }
 
+    pub fn is_alive(&self) -> bool {
+        !matches!(self.state, ThreadState::Free | ThreadState::Exited)
+    }
+}
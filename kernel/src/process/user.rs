@@ -0,0 +1,88 @@
+use core::arch::asm;
+
+use crate::{
+    descriptors::gdt::{USER_CODE_SELECTOR, USER_DATA_SELECTOR},
+    threading::{self, context::RFLAGS_IF, schedlock::locked, THREADS},
+    utils::asm as x86,
+};
+
+/// User registers saved on the way into the kernel. Both syscall entries
+/// push this at the very top of the kernel stack, the last five words are
+/// laid out like the frame `iretq` pops
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub rcx: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub r10: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl UserFrame {
+    /// Fresh ring 3 state at `entry` with the stack pointer at `stack` and
+    /// every other register zeroed
+    pub fn new(entry: u64, stack: u64) -> Self {
+        Self {
+            rip: entry,
+            cs: USER_CODE_SELECTOR as u64,
+            rflags: RFLAGS_IF,
+            rsp: stack,
+            ss: USER_DATA_SELECTOR as u64,
+            ..Self::default()
+        }
+    }
+}
+
+/// Frame the running thread entered the kernel with from ring 3, by a
+/// syscall, an interrupt or an exception
+pub fn current_frame() -> &'static mut UserFrame {
+    let stack_top = locked(|| THREADS.take().threads[threading::current().id()].stack_top);
+    unsafe { &mut *(stack_top as *mut UserFrame).sub(1) }
+}
+
+/// Drops to ring 3 with the registers in `frame`. The page tables of the
+/// process have to be loaded and the TSS has to point at the kernel stack
+/// of the running thread. The GS base is switched to the user one last
+pub fn return_to_user(frame: UserFrame) -> ! {
+    x86::disable_interrupts();
+    unsafe {
+        asm!(
+            "mov rsp, {}",
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop rbp",
+            "pop rbx",
+            "pop r11",
+            "pop rcx",
+            "pop r9",
+            "pop r8",
+            "pop r10",
+            "pop rdx",
+            "pop rsi",
+            "pop rdi",
+            "pop rax",
+            "swapgs",
+            "iretq",
+            in(reg) &frame,
+            options(noreturn)
+        )
+    }
+}
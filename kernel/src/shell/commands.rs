@@ -0,0 +1,220 @@
+use core::time::Duration;
+
+use super::{top, Command};
+use crate::{
+    devices::speaker,
+    kprintln,
+    paging::{
+        address_space::{USER_STACK_TOP, USER_START},
+        AddressSpace, PageTableFlags, PAGE_SIZE,
+    },
+    process::{self, elf, programs, signal},
+    threading::{self, policy::PolicyKind, scheduler},
+};
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        help: "list commands",
+        run: help,
+    },
+    Command {
+        name: "beep",
+        help: "beep [freq_hz] [duration_ms]",
+        run: beep,
+    },
+    Command {
+        name: "sched",
+        help: "sched [rr|prio|mlfq]",
+        run: sched,
+    },
+    Command {
+        name: "prio",
+        help: "prio <tid> <0-7>",
+        run: prio,
+    },
+    Command {
+        name: "nice",
+        help: "nice <tid> <-20..19>",
+        run: nice,
+    },
+    Command {
+        name: "taskset",
+        help: "taskset <tid> <cpu mask>",
+        run: taskset,
+    },
+    Command {
+        name: "ps",
+        help: "list threads and their cpu time",
+        run: top::ps,
+    },
+    Command {
+        name: "top",
+        help: "top [interval_ms], q quits",
+        run: top::top,
+    },
+    Command {
+        name: "run",
+        help: "run <program> [args...], lists the programs without one",
+        run: run,
+    },
+    Command {
+        name: "kill",
+        help: "kill <pid> [signal], SIGTERM by default",
+        run: kill,
+    },
+    Command {
+        name: "ring3",
+        help: "ring3 [null|int|int80|syscall], run a tiny user program",
+        run: ring3,
+    },
+];
+
+fn help(_args: &[&str]) {
+    for cmd in COMMANDS {
+        kprintln!("{:<8} {}", cmd.name, cmd.help);
+    }
+}
+
+fn beep(args: &[&str]) {
+    let freq = args.get(1).and_then(|arg| arg.parse().ok()).unwrap_or(440);
+    let duration_ms = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(200);
+    speaker::beep(freq, Duration::from_millis(duration_ms));
+}
+
+fn sched(args: &[&str]) {
+    if let Some(name) = args.get(1) {
+        match PolicyKind::from_name(name) {
+            Some(kind) => threading::set_policy(kind),
+            None => {
+                kprintln!("unknown policy: {}", name);
+                return;
+            }
+        }
+    }
+    kprintln!("policy: {}", scheduler::policy_name());
+}
+
+fn parse_tid_and<T: core::str::FromStr>(args: &[&str]) -> Option<(usize, T)> {
+    let tid = args.get(1)?.parse().ok()?;
+    let val = args.get(2)?.parse().ok()?;
+    Some((tid, val))
+}
+
+fn prio(args: &[&str]) {
+    let Some((tid, priority)) = parse_tid_and(args) else {
+        kprintln!("usage: prio <tid> <0-7>");
+        return;
+    };
+    if let Err(err) = threading::set_priority(tid, priority) {
+        kprintln!("prio: {}", err);
+    }
+}
+
+fn nice(args: &[&str]) {
+    let Some((tid, nice)) = parse_tid_and(args) else {
+        kprintln!("usage: nice <tid> <-20..19>");
+        return;
+    };
+    if let Err(err) = threading::set_nice(tid, nice) {
+        kprintln!("nice: {}", err);
+    }
+}
+
+fn taskset(args: &[&str]) {
+    let Some((tid, mask)) = parse_tid_and(args) else {
+        kprintln!("usage: taskset <tid> <cpu mask>");
+        return;
+    };
+    if let Err(err) = threading::set_affinity(tid, mask) {
+        kprintln!("taskset: {}", err);
+    }
+}
+
+/// Tiny hand assembled user programs for `ring3`
+const RING3_PROGRAMS: &[(&str, &[u8])] = &[
+    // xor eax, eax; mov rax, [rax]
+    ("null", &[0x31, 0xc0, 0x48, 0x8b, 0x00]),
+    // int 0x20, a vector ring 3 may not raise
+    ("int", &[0xcd, 0x20]),
+    // mov eax, SYS_EXIT; mov edi, 42; int 0x80
+    (
+        "int80",
+        &[0xb8, 0x02, 0, 0, 0, 0xbf, 0x2a, 0, 0, 0, 0xcd, 0x80],
+    ),
+    // mov eax, SYS_EXIT; mov edi, 42; syscall
+    (
+        "syscall",
+        &[0xb8, 0x02, 0, 0, 0, 0xbf, 0x2a, 0, 0, 0, 0x0f, 0x05],
+    ),
+];
+
+fn spawn_raw(name: &'static str, code: &[u8]) -> Result<usize, &'static str> {
+    let mut space = AddressSpace::new()?;
+    space.map_user(USER_START, PageTableFlags::empty())?;
+    space.write(USER_START, code)?;
+    space.map_user(
+        USER_STACK_TOP - PAGE_SIZE,
+        PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+    )?;
+    process::spawn(name, space, USER_START, USER_STACK_TOP)
+}
+
+fn ring3(args: &[&str]) {
+    let which = args.get(1).copied().unwrap_or("null");
+    let Some(&(name, code)) = RING3_PROGRAMS.iter().find(|(name, _)| *name == which) else {
+        kprintln!("usage: ring3 [null|int|int80|syscall]");
+        return;
+    };
+    match spawn_raw(name, code).and_then(process::wait) {
+        Ok(state) => kprintln!("user program ended: {:?}", state),
+        Err(err) => kprintln!("ring3: {}", err),
+    }
+}
+
+fn run(args: &[&str]) {
+    let Some(name) = args.get(1) else {
+        for name in programs::names() {
+            kprintln!("{}", name);
+        }
+        return;
+    };
+    let Some((name, image)) = programs::find(name) else {
+        kprintln!("run: no program called {}", name);
+        return;
+    };
+    let wait = |pid| {
+        // Ctrl-C goes to it while the shell waits
+        signal::set_foreground(Some(pid));
+        let state = process::wait(pid);
+        signal::set_foreground(None);
+        state
+    };
+    match elf::spawn(name, image, &args[1..]).and_then(wait) {
+        Ok(state) => kprintln!("{} ended: {:?}", name, state),
+        Err(err) => kprintln!("run: {}", err),
+    }
+}
+
+/// A signal number, or its name with or without the SIG
+fn parse_signal(arg: &str) -> Option<i32> {
+    if let Ok(sig) = arg.parse() {
+        return signal::is_valid(sig).then_some(sig);
+    }
+    (1..signal::NSIG as i32).find(|&sig| {
+        let name = signal::name(sig);
+        name.starts_with("SIG") && (name == arg || name[3..] == *arg)
+    })
+}
+
+fn kill(args: &[&str]) {
+    let pid = args.get(1).and_then(|arg| arg.parse().ok());
+    let sig = args.get(2).map_or(Some(signal::SIGTERM), |arg| parse_signal(arg));
+    let (Some(pid), Some(sig)) = (pid, sig) else {
+        kprintln!("usage: kill <pid> [signal]");
+        return;
+    };
+    if let Err(err) = signal::send(pid, sig) {
+        kprintln!("kill: {}", err);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{io::reader::READER, kprint, kprintln};
+
+pub mod commands;
+pub mod top;
+
+const MAX_LINE: usize = 128;
+const MAX_ARGS: usize = 16;
+
+pub struct Command {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub run: fn(args: &[&str]),
+}
+
+/// Reads one line from the keyboard, handles backspace and stops at enter
+pub fn read_line(buf: &mut [u8]) -> &str {
+    let mut len: usize = 0;
+    loop {
+        let c = READER.take().input.process_buf_wait();
+        match c {
+            '\n' => break,
+            '\x08' => len = len.saturating_sub(1),
+            _ if c.is_ascii() && len < buf.len() => {
+                buf[len] = c as u8;
+                len += 1;
+            }
+            _ => {}
+        }
+    }
+    core::str::from_utf8(&buf[..len]).unwrap_or("")
+}
+
+pub fn dispatch(line: &str) {
+    let mut args = [""; MAX_ARGS];
+    let mut argc = 0;
+    for word in line.split_whitespace().take(MAX_ARGS) {
+        args[argc] = word;
+        argc += 1;
+    }
+    if argc == 0 {
+        return;
+    }
+    match commands::COMMANDS.iter().find(|cmd| cmd.name == args[0]) {
+        Some(cmd) => (cmd.run)(&args[..argc]),
+        None => kprintln!("unknown command: {}", args[0]),
+    }
+}
+
+pub fn run() -> ! {
+    let mut buf = [0u8; MAX_LINE];
+    loop {
+        kprint!("> ");
+        let line = read_line(&mut buf);
+        dispatch(line);
+    }
+}
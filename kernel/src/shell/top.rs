@@ -0,0 +1,162 @@
+use core::time::Duration;
+
+use crate::{
+    devices::vga::{ConsoleDisplay, BUFFER_HEIGHT},
+    io::{
+        reader::READER,
+        time::{self, Instant},
+        writer::WRITER,
+    },
+    kprintln,
+    process::tcb::ThreadState,
+    threading::{self, ThreadInfo, MAX_THREADS},
+};
+
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+/// How often `top` looks for a key press while waiting to refresh
+const KEY_POLL: Duration = Duration::from_millis(50);
+/// Title and column header
+const TOP_HEADER_LINES: usize = 3;
+
+fn state_name(state: ThreadState) -> &'static str {
+    match state {
+        ThreadState::Free => "free",
+        ThreadState::Ready => "ready",
+        ThreadState::Running => "running",
+        ThreadState::Blocked => "blocked",
+        ThreadState::Exited => "exited",
+    }
+}
+
+/// `part` out of `whole` in tenths of a percent
+fn permille(part: u64, whole: u64) -> u64 {
+    part * 1000 / whole.max(1)
+}
+
+/// Lifetime numbers of every thread, cpu usage is over the whole uptime
+pub fn ps(_args: &[&str]) {
+    let infos = threading::snapshot();
+    let uptime = Instant::now().0;
+    kprintln!(
+        "{:>3} {:<12} {:<8} {:>3} {:>6} {:>7} {:>7} {:>7} {:>6} {:>6}",
+        "TID",
+        "NAME",
+        "STATE",
+        "CPU",
+        "%CPU",
+        "USR",
+        "SYS",
+        "WAIT",
+        "VCSW",
+        "ICSW"
+    );
+    for info in infos.iter().flatten() {
+        let usage = permille(info.stats.busy_ticks(), uptime);
+        kprintln!(
+            "{:>3} {:<12} {:<8} {:>3} {:>4}.{} {:>7} {:>7} {:>7} {:>6} {:>6}",
+            info.tid,
+            info.name,
+            state_name(info.state),
+            info.stats.last_cpu,
+            usage / 10,
+            usage % 10,
+            info.stats.user_ticks,
+            info.stats.system_ticks,
+            info.stats.wait_ticks,
+            info.stats.voluntary_switches,
+            info.stats.involuntary_switches
+        );
+    }
+}
+
+/// Waits out one refresh interval, true if `q` was pressed meanwhile
+fn wait_for_quit(interval: Duration) -> bool {
+    let deadline = Instant::now() + interval;
+    while !deadline.has_passed() {
+        if READER.take().input.poll_char() == Some('q') {
+            return true;
+        }
+        time::sleep(KEY_POLL.min(interval));
+    }
+    false
+}
+
+fn draw_top(infos: &[Option<ThreadInfo>; MAX_THREADS], usage: &[(usize, u64)], interval_ms: u64) {
+    WRITER.take().display.clear();
+    kprintln!(
+        "top - {} threads, every {}ms, policy {}, q to quit",
+        infos.iter().flatten().count(),
+        interval_ms,
+        threading::scheduler::policy_name()
+    );
+    kprintln!("");
+    kprintln!(
+        "{:>3} {:<12} {:<8} {:>3} {:>6} {:>7} {:>7} {:>6} {:>6}",
+        "TID",
+        "NAME",
+        "STATE",
+        "CPU",
+        "%CPU",
+        "USR",
+        "SYS",
+        "VCSW",
+        "ICSW"
+    );
+    // leave the last line free so the screen does not scroll
+    for &(tid, permille) in usage.iter().take(BUFFER_HEIGHT - TOP_HEADER_LINES - 1) {
+        let Some(info) = infos[tid] else {
+            continue;
+        };
+        kprintln!(
+            "{:>3} {:<12} {:<8} {:>3} {:>4}.{} {:>7} {:>7} {:>6} {:>6}",
+            info.tid,
+            info.name,
+            state_name(info.state),
+            info.stats.last_cpu,
+            permille / 10,
+            permille % 10,
+            info.stats.user_ticks,
+            info.stats.system_ticks,
+            info.stats.voluntary_switches,
+            info.stats.involuntary_switches
+        );
+    }
+}
+
+/// Redraws the screen every interval with the threads sorted by how much
+/// cpu they used since the last refresh
+pub fn top(args: &[&str]) {
+    let interval_ms = args
+        .get(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_MS)
+        .max(1);
+    let interval = Duration::from_millis(interval_ms);
+    let mut prev = threading::snapshot();
+    let mut prev_now = Instant::now();
+    loop {
+        if wait_for_quit(interval) {
+            break;
+        }
+        let infos = threading::snapshot();
+        let now = Instant::now();
+        let elapsed = now.0 - prev_now.0;
+
+        let mut usage = [(0usize, 0u64); MAX_THREADS];
+        let mut len = 0;
+        for info in infos.iter().flatten() {
+            // a slot reused by a new thread shows 0 until the next refresh
+            let before = prev[info.tid].map_or(0, |old| old.stats.busy_ticks());
+            let busy = info.stats.busy_ticks().saturating_sub(before);
+            usage[len] = (info.tid, permille(busy, elapsed));
+            len += 1;
+        }
+        let usage = &mut usage[..len];
+        usage.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        draw_top(&infos, usage, interval_ms);
+
+        prev = infos;
+        prev_now = now;
+    }
+    WRITER.take().display.clear();
+}
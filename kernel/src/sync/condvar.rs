@@ -0,0 +1,65 @@
+use core::time::Duration;
+
+use super::{mutex::MutexGuard, waitqueue::WaitQueue};
+use crate::{io::time::Instant, threading::schedlock::locked};
+
+pub struct Condvar {
+    queue: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the lock while parked. The scheduler lock is held from the
+    /// unlock until the thread is on the queue so a notify in between is
+    /// not lost
+    fn park<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        deadline: Option<Instant>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let mutex = guard.mutex();
+        let woken = locked(|| {
+            drop(guard);
+            self.queue.park(deadline)
+        });
+        (mutex.lock(), woken)
+    }
+
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.park(guard, None).0
+    }
+
+    /// Returns the guard and whether the wait timed out
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let (guard, woken) = self.park(guard, Some(Instant::now() + timeout));
+        (guard, !woken)
+    }
+
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut cond: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while cond(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.queue.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.queue.wake_all();
+    }
+}
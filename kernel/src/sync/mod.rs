@@ -1,2 +1,6 @@
+pub mod condvar;
+pub mod mutex;
+pub mod semaphore;
 pub mod shitlock;
 pub mod spinlock;
+pub mod waitqueue;
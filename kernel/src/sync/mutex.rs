@@ -0,0 +1,134 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::waitqueue::WaitQueue;
+use crate::{cpu::percpu, threading};
+
+const NO_OWNER: usize = usize::MAX;
+
+/// Sleeping mutex, waiters are parked instead of spinning. Only for thread
+/// context, interrupt handlers have to stick to `spinlock::Mutex`
+pub struct Mutex<T> {
+    owner: AtomicUsize,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            owner: AtomicUsize::new(NO_OWNER),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    fn acquire(&self, tid: usize) -> bool {
+        self.owner
+            .compare_exchange(NO_OWNER, tid, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        if self.acquire(threading::current().id()) {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        assert!(
+            !percpu::in_interrupt(),
+            "sleeping mutex locked in an interrupt handler"
+        );
+        let tid = threading::current().id();
+        assert!(
+            self.owner() != Some(tid),
+            "mutex locked twice by thread {}",
+            tid
+        );
+        self.queue.wait(|| self.acquire(tid));
+        MutexGuard { mutex: self }
+    }
+
+    /// Thread holding the lock
+    pub fn owner(&self) -> Option<usize> {
+        match self.owner.load(Ordering::Acquire) {
+            NO_OWNER => None,
+            tid => Some(tid),
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.owner().is_some()
+    }
+
+    fn unlock(&self) {
+        self.owner.store(NO_OWNER, Ordering::Release);
+        self.queue.wake_one();
+    }
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+#[test_case]
+pub fn test_mutex_semaphore() {
+    crate::serial_info!("Testing sleeping mutex and semaphore");
+    threading::init();
+    static COUNTER: Mutex<usize> = Mutex::new(0);
+    static DONE: super::semaphore::Semaphore = super::semaphore::Semaphore::new(0);
+    fn worker(_arg: usize) -> usize {
+        for _ in 0..3 {
+            let mut counter = COUNTER.lock();
+            let seen = *counter;
+            // the others have to park on the lock while we are switched out
+            threading::yield_now();
+            *counter = seen + 1;
+        }
+        DONE.release();
+        0
+    }
+    let handles = [threading::spawn(worker, 0), threading::spawn(worker, 0)];
+    DONE.acquire();
+    DONE.acquire();
+    assert!(!DONE.try_acquire());
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(*COUNTER.lock(), 6);
+    assert!(!COUNTER.is_locked());
+}
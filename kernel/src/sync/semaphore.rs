@@ -0,0 +1,55 @@
+use core::{
+    sync::atomic::{AtomicIsize, Ordering},
+    time::Duration,
+};
+
+use super::waitqueue::WaitQueue;
+use crate::io::time::Instant;
+
+/// Counting semaphore, `release` may be called from interrupt handlers
+pub struct Semaphore {
+    count: AtomicIsize,
+    queue: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: isize) -> Self {
+        Self {
+            count: AtomicIsize::new(count),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count.load(Ordering::Acquire);
+        while count > 0 {
+            match self
+                .count
+                .compare_exchange(count, count - 1, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return true,
+                Err(now) => count = now,
+            }
+        }
+        false
+    }
+
+    pub fn acquire(&self) {
+        self.queue.wait(|| self.try_acquire());
+    }
+
+    /// Returns false if no unit became available in time
+    pub fn acquire_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.queue.wait_until(Some(deadline), || self.try_acquire())
+    }
+
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        self.queue.wake_one();
+    }
+
+    pub fn count(&self) -> isize {
+        self.count.load(Ordering::Acquire)
+    }
+}
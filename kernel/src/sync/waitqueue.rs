@@ -0,0 +1,136 @@
+use core::cell::UnsafeCell;
+
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    io::time::{self, Instant},
+    threading::{self, policy::EnqueueReason, schedlock::locked, scheduler, MAX_THREADS, THREADS},
+    utils::asm,
+};
+
+/// Threads parked until somebody wakes them. Every operation runs with
+/// interrupts disabled and the scheduler lock held, so an interrupt handler
+/// or another cpu can wake a queue without racing a thread that is about to
+/// park on it
+pub struct WaitQueue {
+    waiters: UnsafeCell<RingBuf<usize, MAX_THREADS>>,
+}
+
+unsafe impl Send for WaitQueue {}
+unsafe impl Sync for WaitQueue {}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: UnsafeCell::new(RingBuf::new()),
+        }
+    }
+
+    fn channel(&self) -> usize {
+        self as *const _ as usize
+    }
+
+    /// Runs `f` on the parked threads with the scheduler lock held, which
+    /// is what guards them. `f` must not touch this queue again
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut RingBuf<usize, MAX_THREADS>) -> R) -> R {
+        locked(|| f(unsafe { &mut *self.waiters.get() }))
+    }
+
+    /// Parks the current thread. Interrupts must already be disabled, and
+    /// the scheduler lock held if wakers can run on other cpus, from the
+    /// caller's condition check until here. Interrupts are disabled again
+    /// when this returns. Returns false if `deadline` passed first
+    pub fn park(&self, deadline: Option<Instant>) -> bool {
+        locked(|| self.park_locked(deadline))
+    }
+
+    fn park_locked(&self, deadline: Option<Instant>) -> bool {
+        let tid = threading::current().id();
+        {
+            let mut threads = THREADS.take();
+            let tcb = &mut threads.threads[tid];
+            tcb.wait_channel = self.channel();
+            tcb.wake_at = deadline.map(|deadline| deadline.0);
+            tcb.timed_out = false;
+        }
+        if let Some(deadline) = deadline {
+            time::request_wakeup(deadline);
+        }
+        self.with_waiters(|waiters| {
+            waiters
+                .push(tid)
+                .expect("wait queue is larger than the thread table")
+        });
+        scheduler::block_current();
+        asm::disable_interrupts();
+
+        let timed_out = THREADS.take().threads[tid].timed_out;
+        if timed_out {
+            self.remove(tid);
+        }
+        !timed_out
+    }
+
+    /// Parks until `cond` holds, checking it with interrupts disabled.
+    /// Returns false if the deadline passed first
+    pub fn wait_until(&self, deadline: Option<Instant>, mut cond: impl FnMut() -> bool) -> bool {
+        locked(|| loop {
+            if cond() {
+                return true;
+            }
+            if !self.park(deadline) {
+                return cond();
+            }
+        })
+    }
+
+    pub fn wait(&self, cond: impl FnMut() -> bool) {
+        self.wait_until(None, cond);
+    }
+
+    fn remove(&self, tid: usize) {
+        self.with_waiters(|waiters| {
+            for _ in 0..waiters.len {
+                if let Some(waiter) = waiters.take() {
+                    if waiter != tid {
+                        let _ = waiters.push(waiter);
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn wake_one_with(&self, reason: EnqueueReason) -> bool {
+        locked(|| {
+            while let Some(tid) = self.with_waiters(|waiters| waiters.take()) {
+                {
+                    let mut threads = THREADS.take();
+                    let tcb = &mut threads.threads[tid];
+                    if tcb.wait_channel != self.channel() {
+                        continue;
+                    }
+                    tcb.wait_channel = 0;
+                    tcb.wake_at = None;
+                }
+                scheduler::wake(tid, reason);
+                return true;
+            }
+            false
+        })
+    }
+
+    pub fn wake_one(&self) -> bool {
+        self.wake_one_with(EnqueueReason::Wakeup)
+    }
+
+    pub fn wake_all(&self) -> usize {
+        let mut woken = 0;
+        while self.wake_one() {
+            woken += 1;
+        }
+        woken
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.with_waiters(|waiters| waiters.empty())
+    }
+}
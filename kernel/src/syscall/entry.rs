@@ -0,0 +1,203 @@
+use core::arch::global_asm;
+
+use super::{Args, SYS_SIGRETURN};
+use crate::{
+    cpu::percpu::{KERNEL_STACK_OFFSET, USER_RSP_OFFSET},
+    descriptors::gdt::{KERNEL_CODE_SELECTOR, USER_CODE_SELECTOR, USER_DATA_SELECTOR},
+    paging::AddressSpace,
+    process::{self, signal, user::UserFrame, Fault},
+    utils::asm,
+};
+
+const IA32_EFER: u32 = 0xC000_0080;
+const IA32_STAR: u32 = 0xC000_0081;
+const IA32_LSTAR: u32 = 0xC000_0082;
+const IA32_FMASK: u32 = 0xC000_0084;
+const EFER_SCE: u64 = 1 << 0;
+const CR0_WP: u64 = 1 << 16;
+/// Vector of the `int` gate, same numbers and registers as `syscall`
+pub const SYSCALL_VECTOR: u8 = 0x80;
+/// TF, IF, DF and AC are cleared when entering the kernel
+const ENTRY_RFLAGS_MASK: u64 = (1 << 8) | (1 << 9) | (1 << 10) | (1 << 18);
+
+// Both entries leave a `UserFrame` at the top of the kernel stack. The
+// `syscall` one builds the interrupt frame part by hand: the user stack
+// pointer is parked in the per-CPU area only until it is on the kernel
+// stack, interrupts stay off until then. The thread may move to another CPU
+// while the call runs, so nothing else is kept there. The way out reloads
+// rip, rflags and rsp from the frame so handlers can change them. `sysret`
+// clobbers rcx and r11, so when every register has to come back, as after
+// `sigreturn`, it leaves through `iretq` instead.
+//
+// `int 0x80` is an interrupt gate like the others, so nothing comes in
+// before `swapgs` has put the per-CPU GS base in place, and interrupts are
+// turned on again once the call runs. Every register but rax is given back
+// unchanged. The CPU leaves the stack 16 byte aligned minus the 5 words it
+// pushed, the 15 pushed here fix that up for the call
+global_asm!(
+    r#"
+.macro push_user_regs
+    push rax
+    push rdi
+    push rsi
+    push rdx
+    push r10
+    push r8
+    push r9
+    push rcx
+    push r11
+    push rbx
+    push rbp
+    push r12
+    push r13
+    push r14
+    push r15
+.endm
+
+.macro pop_user_regs
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    pop r11
+    pop rcx
+    pop r9
+    pop r8
+    pop r10
+    pop rdx
+    pop rsi
+    pop rdi
+    pop rax
+.endm
+
+.global syscall_entry
+syscall_entry:
+    swapgs
+    mov gs:[{user_rsp}], rsp
+    mov rsp, gs:[{kernel_stack}]
+    push {user_ss}
+    push qword ptr gs:[{user_rsp}]
+    push r11
+    push {user_cs}
+    push rcx
+    push_user_regs
+    mov rdi, rsp
+    call {syscall_dispatch}
+    test al, al
+    jnz 1f
+    pop_user_regs
+    mov rcx, [rsp]
+    mov r11, [rsp + 16]
+    mov rsp, [rsp + 24]
+    swapgs
+    sysretq
+1:  pop_user_regs
+    swapgs
+    iretq
+
+.global int80_entry
+int80_entry:
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    swapgs
+1:  push_user_regs
+    mov rdi, rsp
+    call {int80_dispatch}
+    pop_user_regs
+    test byte ptr [rsp + 8], 3
+    jz 1f
+    swapgs
+1:  iretq
+"#,
+    user_rsp = const USER_RSP_OFFSET,
+    kernel_stack = const KERNEL_STACK_OFFSET,
+    user_ss = const USER_DATA_SELECTOR,
+    user_cs = const USER_CODE_SELECTOR,
+    syscall_dispatch = sym syscall_dispatch,
+    int80_dispatch = sym int80_dispatch,
+);
+
+extern "C" {
+    fn syscall_entry();
+    /// Handler of the `SYSCALL_VECTOR` gate
+    pub fn int80_entry();
+}
+
+/// The fault a return to `frame` would raise in ring 0. `sysret` to a
+/// non-canonical address faults there on some cpus, and so does `iretq`
+/// after `sigreturn` put back a forged rip
+fn bad_return(frame: &UserFrame) -> Option<Fault> {
+    (!AddressSpace::is_user(frame.rip)).then_some(Fault {
+        name: "bad syscall return address",
+        vector: 13,
+        rip: frame.rip,
+        error_code: 0,
+        address: 0,
+    })
+}
+
+/// Runs the call and then the pending signals, returns whether every
+/// register in `frame` has to be restored. Ends the process if it cannot
+/// go back to where `frame` points, whichever way out it takes
+fn dispatch(frame: &mut UserFrame) -> bool {
+    let nr = frame.rax;
+    let args = Args([
+        frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+    ]);
+    frame.rax = super::dispatch(nr, &args) as u64;
+    signal::deliver(frame);
+    if let Some(fault) = bad_return(frame) {
+        process::kill_current(fault);
+    }
+    nr == SYS_SIGRETURN
+}
+
+/// Runs with interrupts enabled, they are off again when it returns so the
+/// way back to ring 3 cannot be interrupted. True sends it out through
+/// `iretq`
+extern "C" fn syscall_dispatch(frame: &mut UserFrame) -> bool {
+    asm::enable_interrupts();
+    let full_restore = dispatch(frame);
+    asm::disable_interrupts();
+    full_restore
+}
+
+/// Same as `syscall_dispatch`, but `iretq` takes any return address
+extern "C" fn int80_dispatch(frame: &mut UserFrame) {
+    asm::enable_interrupts();
+    dispatch(frame);
+    asm::disable_interrupts();
+}
+
+/// Turns on `syscall`/`sysret` for the calling cpu, every cpu needs it.
+/// Also makes read-only pages binding in ring 0, so that copies to user
+/// memory break copy-on-write sharing instead of writing through it
+pub fn init_cpu() {
+    // sysret takes SS from the base + 8 and CS from the base + 16
+    let sysret_base = (USER_DATA_SELECTOR & !3) - 8;
+    let star = (sysret_base as u64) << 48 | (KERNEL_CODE_SELECTOR as u64) << 32;
+    unsafe {
+        asm::wrmsr(IA32_STAR, star);
+        asm::wrmsr(
+            IA32_LSTAR,
+            syscall_entry as unsafe extern "C" fn() as usize as u64,
+        );
+        asm::wrmsr(IA32_FMASK, ENTRY_RFLAGS_MASK);
+        asm::wrmsr(IA32_EFER, asm::rdmsr(IA32_EFER) | EFER_SCE);
+        asm::write_cr0(asm::read_cr0() | CR0_WP);
+    }
+}
+
+#[test_case]
+pub fn test_return_address_check() {
+    crate::serial_info!("Testing the check of syscall return addresses");
+    let mut frame = UserFrame::new(crate::paging::address_space::USER_START, 0);
+    assert!(bad_return(&frame).is_none());
+    // what a forged sigreturn frame could hold
+    for rip in [0x8000_0000_0000, u64::MAX, 0] {
+        frame.rip = rip;
+        assert_eq!(bad_return(&frame).map(|fault| fault.rip), Some(rip));
+    }
+}
@@ -0,0 +1,237 @@
+use super::{
+    uaccess::{UserPtr, UserSlice},
+    Args, Errno, SyscallResult,
+};
+use crate::{
+    cpu,
+    devices::vga::ConsoleDisplay,
+    io::{reader::READER, writer::WRITER},
+    process::{
+        self,
+        file::{self, FdTable, FileKind},
+        pipe::{self, PipeError},
+        signal,
+    },
+    threading::{self, schedlock::locked, scheduler, THREADS},
+};
+
+/// Bytes `read` and `write` move through the kernel at a time
+const CHUNK: usize = 256;
+
+/// fcntl commands, with the Linux values
+const F_DUPFD: u32 = 0;
+const F_GETFD: u32 = 1;
+const F_SETFD: u32 = 2;
+const FD_CLOEXEC: usize = 1;
+
+/// Runs `f` on the descriptors of the calling process
+fn with_files<R>(f: impl FnOnce(&mut FdTable) -> R) -> Result<R, Errno> {
+    process::with_current_files(f).map_err(|_| Errno::ESRCH)
+}
+
+/// Kind of the open file behind `fd`
+fn file_of(fd: i32) -> Result<FileKind, Errno> {
+    let file = with_files(|files| files.get(fd))?.ok_or(Errno::EBADF)?;
+    Ok(file::kind(file))
+}
+
+/// read(fd, buf, len), the console gives back at most one line and a pipe
+/// what it has, waiting for something if it is empty. 0 is the end of the
+/// file. A signal ends it early, with `EINTR` if nothing was read yet
+pub fn read(args: &Args) -> SyscallResult {
+    let fd: i32 = args.get(0)?;
+    let buf = UserSlice::new(args.get(1)?, args.get(2)?)?;
+    match file_of(fd)? {
+        FileKind::Console => read_console(&buf),
+        FileKind::PipeRead(pipe) => read_pipe(pipe, &buf),
+        FileKind::PipeWrite(_) => Err(Errno::EBADF),
+    }
+}
+
+fn read_console(buf: &UserSlice) -> SyscallResult {
+    on_boot_cpu(|| {
+        let mut read = 0;
+        while read < buf.len() {
+            let c = signal::interruptible(|| {
+                READER.take().input.process_buf_wait_or(signal::pending)
+            });
+            let Some(c) = c else {
+                return if read == 0 { Err(Errno::EINTR) } else { Ok(read) };
+            };
+            let mut utf8 = [0; 4];
+            let bytes = c.encode_utf8(&mut utf8).as_bytes();
+            let bytes = &bytes[..bytes.len().min(buf.len() - read)];
+            buf.skip(read).write(bytes)?;
+            read += bytes.len();
+            if c == '\n' {
+                break;
+            }
+        }
+        Ok(read)
+    })
+}
+
+/// Only the first chunk waits, the rest takes what is already there
+fn read_pipe(pipe: usize, buf: &UserSlice) -> SyscallResult {
+    let mut chunk = [0u8; CHUNK];
+    let mut read = 0;
+    while read < buf.len() {
+        let len = CHUNK.min(buf.len() - read);
+        let got = match pipe::read(pipe, &mut chunk[..len], read == 0) {
+            Ok(got) => got,
+            Err(PipeError::Interrupted) => return Err(Errno::EINTR),
+            Err(PipeError::Broken) => unreachable!("reads never see a broken pipe"),
+        };
+        buf.skip(read).write(&chunk[..got])?;
+        read += got;
+        if got < len {
+            break;
+        }
+    }
+    Ok(read)
+}
+
+/// write(fd, buf, len), waits until all of `buf` is in a pipe unless a
+/// signal comes in. A pipe nobody reads from any more raises `SIGPIPE` and
+/// fails with `EPIPE`
+pub fn write(args: &Args) -> SyscallResult {
+    let fd: i32 = args.get(0)?;
+    let buf = UserSlice::new(args.get(1)?, args.get(2)?)?;
+    match file_of(fd)? {
+        FileKind::Console => write_console(&buf),
+        FileKind::PipeWrite(pipe) => write_pipe(pipe, &buf),
+        FileKind::PipeRead(_) => Err(Errno::EBADF),
+    }
+}
+
+fn write_console(buf: &UserSlice) -> SyscallResult {
+    let mut chunk = [0u8; CHUNK];
+    let mut written = 0;
+    while written < buf.len() {
+        let len = CHUNK.min(buf.len() - written);
+        buf.skip(written).read(&mut chunk[..len])?;
+        WRITER
+            .take()
+            .display
+            .put_bytes(&chunk[..len])
+            .map_err(|_| Errno::EIO)?;
+        written += len;
+    }
+    Ok(written)
+}
+
+fn write_pipe(pipe: usize, buf: &UserSlice) -> SyscallResult {
+    let mut chunk = [0u8; CHUNK];
+    let mut written = 0;
+    while written < buf.len() {
+        let len = CHUNK.min(buf.len() - written);
+        buf.skip(written).read(&mut chunk[..len])?;
+        let mut moved = 0;
+        while moved < len {
+            match pipe::write(pipe, &chunk[moved..len]) {
+                Ok(n) => moved += n,
+                Err(_) if written + moved > 0 => return Ok(written + moved),
+                Err(PipeError::Interrupted) => return Err(Errno::EINTR),
+                Err(PipeError::Broken) => {
+                    let pid = process::current_pid().ok_or(Errno::ESRCH)?;
+                    let _ = signal::send(pid, signal::SIGPIPE);
+                    return Err(Errno::EPIPE);
+                }
+            }
+        }
+        written += len;
+    }
+    Ok(written)
+}
+
+/// dup(fd), the new descriptor is the lowest free one
+pub fn dup(args: &Args) -> SyscallResult {
+    let fd: i32 = args.get(0)?;
+    dup_from(fd, 0)
+}
+
+fn dup_from(fd: i32, min: usize) -> SyscallResult {
+    with_files(|files| {
+        files.get(fd).ok_or(Errno::EBADF)?;
+        files.dup(fd, min).map_err(|_| Errno::EMFILE)
+    })?
+    .map(|fd| fd as usize)
+}
+
+/// dup2(old, new), closes `new` first if it is open
+pub fn dup2(args: &Args) -> SyscallResult {
+    let old: i32 = args.get(0)?;
+    let new: i32 = args.get(1)?;
+    let fd = with_files(|files| files.dup2(old, new))?.map_err(|_| Errno::EBADF)?;
+    Ok(fd as usize)
+}
+
+/// close(fd), the open file goes once no descriptor refers to it
+pub fn close(args: &Args) -> SyscallResult {
+    let fd: i32 = args.get(0)?;
+    with_files(|files| files.close(fd))?.map_err(|_| Errno::EBADF)?;
+    Ok(0)
+}
+
+/// fcntl(fd, cmd, arg), knows `F_DUPFD`, `F_GETFD` and `F_SETFD`. The only
+/// descriptor flag is `FD_CLOEXEC`
+pub fn fcntl(args: &Args) -> SyscallResult {
+    let fd: i32 = args.get(0)?;
+    let cmd: u32 = args.get(1)?;
+    let arg: usize = args.get(2)?;
+    match cmd {
+        F_DUPFD => dup_from(fd, arg),
+        F_GETFD => {
+            let cloexec = with_files(|files| files.cloexec(fd))?.ok_or(Errno::EBADF)?;
+            Ok(if cloexec { FD_CLOEXEC } else { 0 })
+        }
+        F_SETFD => {
+            with_files(|files| files.set_cloexec(fd, arg & FD_CLOEXEC != 0))?
+                .ok_or(Errno::EBADF)?;
+            Ok(0)
+        }
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// pipe(fds), puts the read end in `fds[0]` and the write end in `fds[1]`
+pub fn pipe(args: &Args) -> SyscallResult {
+    let fds: UserPtr<[i32; 2]> = args.get(0)?;
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    let [read_end, write_end] = file::open_pipe().map_err(|_| Errno::ENFILE)?;
+    let installed = with_files(|files| {
+        let read_fd = files.install(read_end, 0);
+        let write_fd = read_fd.and_then(|_| files.install(write_end, 0));
+        if let (Some(read_fd), Some(write_fd)) = (read_fd, write_fd) {
+            return Some([read_fd, write_fd]);
+        }
+        match read_fd {
+            Some(read_fd) => files.close(read_fd).expect("was just installed"),
+            None => file::release(read_end),
+        }
+        file::release(write_end);
+        None
+    })?
+    .ok_or(Errno::EMFILE)?;
+    if let Err(errno) = fds.write(installed) {
+        with_files(|files| {
+            for fd in installed {
+                let _ = files.close(fd);
+            }
+        })?;
+        return Err(errno);
+    }
+    Ok(0)
+}
+
+/// The keyboard buffer is only filled and drained on the boot cpu, so the
+/// caller is moved there while it reads
+fn on_boot_cpu<R>(f: impl FnOnce() -> R) -> R {
+    let tid = threading::current().id();
+    let affinity = locked(|| THREADS.take().threads[tid].affinity);
+    threading::set_affinity(tid, cpu::cpu_mask(0)).expect("boot cpu is offline");
+    scheduler::yield_now();
+    let ret = f();
+    threading::set_affinity(tid, affinity).expect("affinity was valid before");
+    ret
+}
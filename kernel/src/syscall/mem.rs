@@ -0,0 +1,61 @@
+use super::{Args, Errno, SyscallResult};
+use crate::{
+    paging::{AddressSpace, PageTableFlags, PAGE_SIZE},
+    process,
+};
+
+const PROT_WRITE: u32 = 2;
+const PROT_EXEC: u32 = 4;
+const MAP_PRIVATE: u32 = 0x02;
+const MAP_FIXED: u32 = 0x10;
+const MAP_ANONYMOUS: u32 = 0x20;
+
+/// brk(addr), moves the end of the heap. Returns the new end, or the old
+/// one if it could not be moved. 0 just asks for it
+pub fn brk(args: &Args) -> SyscallResult {
+    let addr: u64 = args.get(0)?;
+    let brk = process::with_current_space(|space| {
+        if addr != 0 {
+            let _ = space.set_brk(addr);
+        }
+        space.brk()
+    })
+    .map_err(|_| Errno::ESRCH)?;
+    Ok(brk as usize)
+}
+
+/// mmap(addr, len, prot, flags, fd, offset), only private anonymous
+/// mappings at an address the kernel picks
+pub fn mmap(args: &Args) -> SyscallResult {
+    let len: u64 = args.get(1)?;
+    let prot: u32 = args.get(2)?;
+    let flags: u32 = args.get(3)?;
+    if flags & (MAP_PRIVATE | MAP_ANONYMOUS) != MAP_PRIVATE | MAP_ANONYMOUS {
+        return Err(Errno::ENODEV);
+    }
+    if flags & MAP_FIXED != 0 || len == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let mut page_flags = PageTableFlags::empty();
+    if prot & PROT_WRITE != 0 {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+    let addr = process::with_current_space(|space| space.map_anonymous(len, page_flags))
+        .map_err(|_| Errno::ESRCH)?
+        .map_err(|_| Errno::ENOMEM)?;
+    Ok(addr as usize)
+}
+
+/// munmap(addr, len)
+pub fn munmap(args: &Args) -> SyscallResult {
+    let addr: u64 = args.get(0)?;
+    let len: u64 = args.get(1)?;
+    if addr % PAGE_SIZE != 0 || !AddressSpace::is_user(addr) {
+        return Err(Errno::EINVAL);
+    }
+    process::with_current_space(|space| space.unmap(addr, len)).map_err(|_| Errno::ESRCH)?;
+    Ok(0)
+}
@@ -0,0 +1,213 @@
+pub mod entry;
+pub mod errno;
+mod fs;
+mod mem;
+mod proc;
+mod signal;
+pub mod uaccess;
+
+pub use errno::Errno;
+
+pub const SYS_READ: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+pub const SYS_EXIT: u64 = 2;
+pub const SYS_GETPID: u64 = 3;
+pub const SYS_YIELD: u64 = 4;
+pub const SYS_SLEEP: u64 = 5;
+pub const SYS_FORK: u64 = 6;
+pub const SYS_EXECVE: u64 = 7;
+pub const SYS_WAITPID: u64 = 8;
+pub const SYS_GETPPID: u64 = 9;
+pub const SYS_BRK: u64 = 10;
+pub const SYS_MMAP: u64 = 11;
+pub const SYS_MUNMAP: u64 = 12;
+pub const SYS_KILL: u64 = 13;
+pub const SYS_SIGACTION: u64 = 14;
+pub const SYS_SIGPROCMASK: u64 = 15;
+pub const SYS_SIGRETURN: u64 = 16;
+pub const SYS_DUP: u64 = 17;
+pub const SYS_DUP2: u64 = 18;
+pub const SYS_CLOSE: u64 = 19;
+pub const SYS_FCNTL: u64 = 20;
+pub const SYS_PIPE: u64 = 21;
+
+/// `waitpid` option to return 0 instead of blocking
+pub const WNOHANG: u32 = 1;
+
+pub type SyscallResult = Result<usize, Errno>;
+
+pub struct Syscall {
+    pub name: &'static str,
+    handler: fn(&Args) -> SyscallResult,
+}
+
+/// Indexed by syscall number
+pub static SYSCALLS: &[Syscall] = &[
+    Syscall {
+        name: "read",
+        handler: fs::read,
+    },
+    Syscall {
+        name: "write",
+        handler: fs::write,
+    },
+    Syscall {
+        name: "exit",
+        handler: proc::exit,
+    },
+    Syscall {
+        name: "getpid",
+        handler: proc::getpid,
+    },
+    Syscall {
+        name: "yield",
+        handler: proc::sched_yield,
+    },
+    Syscall {
+        name: "sleep",
+        handler: proc::sleep,
+    },
+    Syscall {
+        name: "fork",
+        handler: proc::fork,
+    },
+    Syscall {
+        name: "execve",
+        handler: proc::execve,
+    },
+    Syscall {
+        name: "waitpid",
+        handler: proc::waitpid,
+    },
+    Syscall {
+        name: "getppid",
+        handler: proc::getppid,
+    },
+    Syscall {
+        name: "brk",
+        handler: mem::brk,
+    },
+    Syscall {
+        name: "mmap",
+        handler: mem::mmap,
+    },
+    Syscall {
+        name: "munmap",
+        handler: mem::munmap,
+    },
+    Syscall {
+        name: "kill",
+        handler: signal::kill,
+    },
+    Syscall {
+        name: "sigaction",
+        handler: signal::sigaction,
+    },
+    Syscall {
+        name: "sigprocmask",
+        handler: signal::sigprocmask,
+    },
+    Syscall {
+        name: "sigreturn",
+        handler: signal::sigreturn,
+    },
+    Syscall {
+        name: "dup",
+        handler: fs::dup,
+    },
+    Syscall {
+        name: "dup2",
+        handler: fs::dup2,
+    },
+    Syscall {
+        name: "close",
+        handler: fs::close,
+    },
+    Syscall {
+        name: "fcntl",
+        handler: fs::fcntl,
+    },
+    Syscall {
+        name: "pipe",
+        handler: fs::pipe,
+    },
+];
+
+/// A raw syscall argument that can be turned into `Self`
+pub trait FromArg: Sized {
+    fn from_arg(raw: u64) -> Result<Self, Errno>;
+}
+
+impl FromArg for u64 {
+    fn from_arg(raw: u64) -> Result<Self, Errno> {
+        Ok(raw)
+    }
+}
+
+impl FromArg for usize {
+    fn from_arg(raw: u64) -> Result<Self, Errno> {
+        Ok(raw as usize)
+    }
+}
+
+impl FromArg for u32 {
+    fn from_arg(raw: u64) -> Result<Self, Errno> {
+        u32::try_from(raw).map_err(|_| Errno::EINVAL)
+    }
+}
+
+/// C passes an int sign extended or not at all, only the low half counts
+impl FromArg for i32 {
+    fn from_arg(raw: u64) -> Result<Self, Errno> {
+        Ok(raw as u32 as i32)
+    }
+}
+
+/// The six argument registers in calling order
+pub struct Args(pub [u64; 6]);
+
+impl Args {
+    pub fn get<T: FromArg>(&self, n: usize) -> Result<T, Errno> {
+        T::from_arg(self.0[n])
+    }
+}
+
+/// Runs syscall `nr`, the result is the value or the negated error number
+pub fn dispatch(nr: u64, args: &Args) -> i64 {
+    let result = usize::try_from(nr)
+        .ok()
+        .and_then(|nr| SYSCALLS.get(nr))
+        .ok_or(Errno::ENOSYS)
+        .and_then(|call| (call.handler)(args));
+    match result {
+        Ok(val) => val as i64,
+        Err(errno) => -(errno as i64),
+    }
+}
+
+#[test_case]
+pub fn test_syscall_table() {
+    crate::serial_info!("Testing the syscall table");
+    assert_eq!(SYSCALLS[SYS_READ as usize].name, "read");
+    assert_eq!(SYSCALLS[SYS_WRITE as usize].name, "write");
+    assert_eq!(SYSCALLS[SYS_SLEEP as usize].name, "sleep");
+    let args = Args([0; 6]);
+    assert_eq!(
+        dispatch(SYSCALLS.len() as u64, &args),
+        -(Errno::ENOSYS as i64)
+    );
+    assert_eq!(dispatch(u64::MAX, &args), -(Errno::ENOSYS as i64));
+    // the test kernel has no processes
+    assert_eq!(dispatch(SYS_GETPID, &args), -(Errno::ESRCH as i64));
+    assert_eq!(SYSCALLS[SYS_GETPPID as usize].name, "getppid");
+    assert_eq!(dispatch(SYS_FORK, &args), -(Errno::ESRCH as i64));
+    assert_eq!(SYSCALLS[SYS_SIGRETURN as usize].name, "sigreturn");
+    assert_eq!(dispatch(SYS_KILL, &args), -(Errno::ESRCH as i64));
+    assert_eq!(SYSCALLS[SYS_PIPE as usize].name, "pipe");
+    assert_eq!(dispatch(SYS_CLOSE, &args), -(Errno::ESRCH as i64));
+    assert_eq!(
+        Args([1 << 32, 0, 0, 0, 0, 0]).get::<u32>(0),
+        Err(Errno::EINVAL)
+    );
+    assert_eq!(Args([u64::MAX, 0, 0, 0, 0, 0]).get::<i32>(0), Ok(-1));
+}
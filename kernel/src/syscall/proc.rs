@@ -0,0 +1,151 @@
+use core::time::Duration;
+
+use super::{
+    uaccess::{strncpy_from_user, UserPtr},
+    Args, Errno, SyscallResult, WNOHANG,
+};
+use crate::{
+    io::time::Instant,
+    process::{
+        self,
+        elf::Elf,
+        programs, signal,
+        stack::{InitialStack, MAX_ARG_LEN},
+        user::{self, UserFrame},
+        ProcessState,
+    },
+    serial_info,
+    threading::{self, schedlock::locked, scheduler},
+};
+
+/// Longest path `execve` takes, with the NUL
+const PATH_MAX: usize = 256;
+
+/// exit(code), never returns
+pub fn exit(args: &Args) -> SyscallResult {
+    process::exit_current(ProcessState::Exited(args.get(0)?))
+}
+
+pub fn getpid(_args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)
+}
+
+pub fn sched_yield(_args: &Args) -> SyscallResult {
+    threading::yield_now();
+    Ok(0)
+}
+
+/// sleep(milliseconds), at most `u32::MAX` of them. A signal cuts it short
+/// with `EINTR`
+pub fn sleep(args: &Args) -> SyscallResult {
+    let ms: u32 = args.get(0)?;
+    let deadline = Instant::now() + Duration::from_millis(ms.into());
+    signal::interruptible(|| {
+        while !deadline.has_passed() {
+            // checked with the scheduler lock held so a signal cannot slip
+            // in before the thread is asleep
+            let interrupted = locked(|| {
+                let pending = signal::pending();
+                if !pending {
+                    scheduler::sleep_until(deadline);
+                }
+                pending
+            });
+            if interrupted {
+                return Err(Errno::EINTR);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// fork(), the parent gets the pid of the child and the child 0. A full
+/// process or thread table is EAGAIN, anything else failed to allocate
+pub fn fork(_args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    process::fork(user::current_frame()).map_err(|err| match err {
+        process::PROCESS_TABLE_FULL | threading::THREAD_TABLE_FULL => Errno::EAGAIN,
+        _ => Errno::ENOMEM,
+    })
+}
+
+/// execve(path, argv, envp), `path` names one of the built in programs.
+/// Only comes back on failure, the process starts over at the new entry
+/// with the strings of `argv` and `envp` on its stack
+pub fn execve(args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    let mut buf = [0u8; MAX_ARG_LEN];
+    let len = strncpy_from_user(&mut buf[..PATH_MAX], args.get(0)?)?;
+    let path = core::str::from_utf8(&buf[..len]).map_err(|_| Errno::ENOENT)?;
+    let (name, image) = programs::find(path).ok_or(Errno::ENOENT)?;
+    let elf = Elf::parse(image).map_err(|err| {
+        serial_info!("execve {}: {}", name, err);
+        Errno::ENOEXEC
+    })?;
+    let mut space = elf.load().map_err(|_| Errno::ENOMEM)?;
+    let mut stack = InitialStack::new(&mut space).map_err(|_| Errno::ENOMEM)?;
+    copy_strings(args.get(1)?, &mut buf, |arg| stack.arg(arg))?;
+    copy_strings(args.get(2)?, &mut buf, |var| stack.env(var))?;
+    let sp = stack.finish(&elf).map_err(|_| Errno::E2BIG)?;
+    process::exec(name, space).map_err(|_| Errno::ESRCH)?;
+    *user::current_frame() = UserFrame::new(elf.entry, sp);
+    Ok(0)
+}
+
+/// Hands each string of the NULL terminated user array at `array` to
+/// `add`, a NULL array counts as empty
+fn copy_strings(
+    array: u64,
+    buf: &mut [u8],
+    mut add: impl FnMut(&[u8]) -> Result<(), &'static str>,
+) -> Result<(), Errno> {
+    if array == 0 {
+        return Ok(());
+    }
+    let mut entry = array;
+    loop {
+        let ptr = UserPtr::<u64>::new(entry).read()?;
+        if ptr == 0 {
+            return Ok(());
+        }
+        let len = strncpy_from_user(buf, ptr).map_err(|err| match err {
+            Errno::ENAMETOOLONG => Errno::E2BIG,
+            err => err,
+        })?;
+        add(&buf[..len]).map_err(|_| Errno::E2BIG)?;
+        entry = entry.wrapping_add(8);
+    }
+}
+
+/// waitpid(pid, status, options), `pid` is -1 for any child. Returns the
+/// pid that was collected, or 0 if none has ended and `WNOHANG` is set
+pub fn waitpid(args: &Args) -> SyscallResult {
+    let parent = process::current_pid().ok_or(Errno::ESRCH)?;
+    let which = match args.get::<i32>(0)? {
+        -1 => None,
+        pid if pid > 0 => Some(pid as usize),
+        _ => return Err(Errno::EINVAL),
+    };
+    let status: UserPtr<i32> = args.get(1)?;
+    let options: u32 = args.get(2)?;
+    if options & !WNOHANG != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let block = options & WNOHANG == 0;
+    let reaped = signal::interruptible(|| process::waitpid(parent, which, block))
+        .map_err(|_| Errno::ECHILD)?;
+    let Some((pid, state)) = reaped else {
+        return if block { Err(Errno::EINTR) } else { Ok(0) };
+    };
+    // the child stays collectable if the status cannot be written
+    if !status.is_null() {
+        status.write(state.wait_status())?;
+    }
+    process::release(pid);
+    Ok(pid)
+}
+
+pub fn getppid(_args: &Args) -> SyscallResult {
+    let pid = process::current_pid().ok_or(Errno::ESRCH)?;
+    process::parent_of(pid).map_err(|_| Errno::ESRCH)
+}
@@ -0,0 +1,68 @@
+use super::{uaccess::UserPtr, Args, Errno, SyscallResult};
+use crate::process::{
+    self,
+    signal::{self, SigAction, SigSet},
+    user, INIT_PID,
+};
+
+/// kill(pid, sig), a `sig` of 0 only checks that `pid` exists
+pub fn kill(args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    let pid = match args.get::<i32>(0)? {
+        pid if pid > 0 => pid as usize,
+        _ => return Err(Errno::EINVAL),
+    };
+    let sig: i32 = args.get(1)?;
+    if sig != 0 && !signal::is_valid(sig) {
+        return Err(Errno::EINVAL);
+    }
+    if pid == INIT_PID {
+        return Err(Errno::EPERM);
+    }
+    signal::send(pid, sig).map_err(|_| Errno::ESRCH)?;
+    Ok(0)
+}
+
+/// sigaction(sig, act, oldact), either pointer may be NULL. `act` is a
+/// `struct kernel_sigaction`, a handler needs `SA_RESTORER`
+pub fn sigaction(args: &Args) -> SyscallResult {
+    let sig: i32 = args.get(0)?;
+    let act: UserPtr<SigAction> = args.get(1)?;
+    let oldact: UserPtr<SigAction> = args.get(2)?;
+    let new = if act.is_null() {
+        None
+    } else {
+        Some(act.read()?)
+    };
+    let old = signal::set_action(sig, new).map_err(|_| Errno::EINVAL)?;
+    if !oldact.is_null() {
+        oldact.write(old)?;
+    }
+    Ok(0)
+}
+
+/// sigprocmask(how, set, oldset), either pointer may be NULL. `SIGKILL` and
+/// `SIGSTOP` are never blocked
+pub fn sigprocmask(args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    let how: u32 = args.get(0)?;
+    let set: UserPtr<SigSet> = args.get(1)?;
+    let oldset: UserPtr<SigSet> = args.get(2)?;
+    let new = if set.is_null() {
+        None
+    } else {
+        Some(set.read()?)
+    };
+    let old = signal::set_blocked(how, new).map_err(|_| Errno::EINVAL)?;
+    if !oldset.is_null() {
+        oldset.write(old)?;
+    }
+    Ok(0)
+}
+
+/// sigreturn(), made by the restorer once a handler returns. Goes back to
+/// where the signal came in, with every register as it was there
+pub fn sigreturn(_args: &Args) -> SyscallResult {
+    process::current_pid().ok_or(Errno::ESRCH)?;
+    Ok(signal::sigreturn(user::current_frame()) as usize)
+}
@@ -0,0 +1,240 @@
+use core::{arch::global_asm, marker::PhantomData, mem::size_of};
+
+use super::{Errno, FromArg};
+use crate::paging::address_space::{USER_END, USER_START};
+
+// The only instructions that touch user memory on behalf of a syscall.
+// Each one that can fault has an entry in `exception_table`, a page fault
+// or general protection fault there resumes at the fixup instead of
+// panicking. `copy_user` leaves the bytes it did not copy in rax,
+// `strncpy_user` the length of the string or -1
+global_asm!(
+    r#"
+.global copy_user
+copy_user:
+    cld
+    mov rcx, rdx
+2:  rep movsb
+3:  mov rax, rcx
+    ret
+
+.global strncpy_user
+strncpy_user:
+    xor eax, eax
+4:  cmp rax, rdx
+    je 6f
+5:  mov cl, [rsi + rax]
+    mov [rdi + rax], cl
+    test cl, cl
+    jz 6f
+    inc rax
+    jmp 4b
+6:  ret
+7:  mov rax, -1
+    ret
+
+.pushsection .rodata.exception_table, "a"
+.balign 8
+.global exception_table
+exception_table:
+    .quad 2b - ., 3b - .
+    .quad 5b - ., 7b - .
+.global exception_table_end
+exception_table_end:
+.popsection
+"#
+);
+
+/// A faulting instruction and where to go on instead, both stored relative
+/// to the field itself so the table needs no relocations
+#[repr(C)]
+struct Fixup {
+    insn: i64,
+    resume: i64,
+}
+
+impl Fixup {
+    fn insn(&self) -> u64 {
+        (&self.insn as *const i64 as u64).wrapping_add_signed(self.insn)
+    }
+
+    fn resume(&self) -> u64 {
+        (&self.resume as *const i64 as u64).wrapping_add_signed(self.resume)
+    }
+}
+
+extern "C" {
+    fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn strncpy_user(dst: *mut u8, src: *const u8, max: usize) -> isize;
+    static exception_table: Fixup;
+    static exception_table_end: Fixup;
+}
+
+/// Where to continue after the kernel faulted at `rip`, if that was a user
+/// memory access
+pub fn fixup(rip: u64) -> Option<u64> {
+    let table = unsafe {
+        let start = &exception_table as *const Fixup;
+        let len = (&exception_table_end as *const Fixup).offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    };
+    table
+        .iter()
+        .find(|fixup| fixup.insn() == rip)
+        .map(|fixup| fixup.resume())
+}
+
+/// `len` bytes at `addr` have to lie in the user half. Whether they are
+/// mapped is left to the copy
+fn check_range(addr: u64, len: usize) -> Result<(), Errno> {
+    let end = addr.checked_add(len as u64).ok_or(Errno::EFAULT)?;
+    if len != 0 && (addr < USER_START || end > USER_END) {
+        return Err(Errno::EFAULT);
+    }
+    Ok(())
+}
+
+/// Copies `dst.len()` bytes from user address `src`
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<(), Errno> {
+    check_range(src, dst.len())?;
+    match unsafe { copy_user(dst.as_mut_ptr(), src as *const u8, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
+}
+
+/// Copies `src` to user address `dst`
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<(), Errno> {
+    check_range(dst, src.len())?;
+    match unsafe { copy_user(dst as *mut u8, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
+}
+
+/// Copies the NUL terminated string at user address `src` into `dst`, it
+/// has to fit with the NUL. Returns its length
+pub fn strncpy_from_user(dst: &mut [u8], src: u64) -> Result<usize, Errno> {
+    // the string may end well before the end of the user half
+    let max = dst.len().min(USER_END.saturating_sub(src) as usize);
+    check_range(src, max)?;
+    match unsafe { strncpy_user(dst.as_mut_ptr(), src as *const u8, max) } {
+        -1 => Err(Errno::EFAULT),
+        len if len as usize == dst.len() => Err(Errno::ENAMETOOLONG),
+        len if len as usize == max => Err(Errno::EFAULT),
+        len => Ok(len as usize),
+    }
+}
+
+/// A pointer into the running process, it is only ever read or written
+/// through a checked copy
+pub struct UserPtr<T> {
+    addr: u64,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: u64) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    pub fn read(&self) -> Result<T, Errno> {
+        let mut val = core::mem::MaybeUninit::<T>::uninit();
+        let bytes =
+            unsafe { core::slice::from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        copy_from_user(bytes, self.addr)?;
+        Ok(unsafe { val.assume_init() })
+    }
+
+    pub fn write(&self, val: T) -> Result<(), Errno> {
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&val as *const T as *const u8, size_of::<T>()) };
+        copy_to_user(self.addr, bytes)
+    }
+}
+
+impl<T> FromArg for UserPtr<T> {
+    fn from_arg(raw: u64) -> Result<Self, Errno> {
+        Ok(Self {
+            addr: raw,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// `len` bytes of the running process, checked to lie in the user half
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: u64,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(addr: u64, len: usize) -> Result<Self, Errno> {
+        check_range(addr, len)?;
+        Ok(Self { addr, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The part starting `offset` bytes in, empty past the end
+    pub fn skip(&self, offset: usize) -> Self {
+        let offset = offset.min(self.len);
+        Self {
+            addr: self.addr + offset as u64,
+            len: self.len - offset,
+        }
+    }
+
+    /// Fills `dst` from the start of the slice, which has to be long enough
+    pub fn read(&self, dst: &mut [u8]) -> Result<(), Errno> {
+        if dst.len() > self.len {
+            return Err(Errno::EFAULT);
+        }
+        copy_from_user(dst, self.addr)
+    }
+
+    /// Copies `src` to the start of the slice, which has to be long enough
+    pub fn write(&self, src: &[u8]) -> Result<(), Errno> {
+        if src.len() > self.len {
+            return Err(Errno::EFAULT);
+        }
+        copy_to_user(self.addr, src)
+    }
+}
+
+#[test_case]
+pub fn test_user_ranges() {
+    crate::serial_info!("Testing user pointer checks");
+    let mut buf = [0u8; 8];
+    assert_eq!(copy_from_user(&mut buf, 0), Err(Errno::EFAULT));
+    assert_eq!(copy_to_user(USER_END - 4, &buf), Err(Errno::EFAULT));
+    assert_eq!(copy_from_user(&mut buf, u64::MAX - 2), Err(Errno::EFAULT));
+    assert_eq!(strncpy_from_user(&mut buf, USER_END), Err(Errno::EFAULT));
+    assert!(UserSlice::new(USER_START, 0x1000).is_ok());
+    assert!(UserSlice::new(USER_END - 0x1000, 0x1001).is_err());
+    assert_eq!(
+        UserPtr::<u64>::new(0xffff_8000_0000_0000).read(),
+        Err(Errno::EFAULT)
+    );
+    let slice = UserSlice::new(USER_START, 16).unwrap();
+    assert_eq!(slice.skip(20).len(), 0);
+    assert_eq!(slice.skip(4).read(&mut [0; 16]), Err(Errno::EFAULT));
+}
@@ -0,0 +1,157 @@
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Waker},
+};
+
+use super::{Task, TaskId};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    serial_info,
+    sync::{spinlock::SpinLock, waitqueue::WaitQueue},
+    threading,
+    utils::asm,
+};
+
+pub const MAX_TASKS: usize = 64;
+
+/// Ids of the tasks that were woken. Wakers push from interrupt handlers
+/// and other cpus, so the ids sit behind a spin lock
+struct ReadyQueue {
+    ids: SpinLock<RingBuf<TaskId, MAX_TASKS>>,
+    /// The executor thread parks here when there is nothing to poll
+    idle: WaitQueue,
+}
+
+impl ReadyQueue {
+    fn new() -> Self {
+        Self {
+            ids: SpinLock::new(RingBuf::new()),
+            idle: WaitQueue::new(),
+        }
+    }
+
+    fn push(&self, id: TaskId) {
+        // a task is only ever queued once, so this cannot overflow
+        self.ids
+            .lock()
+            .push(id)
+            .expect("more ready tasks than MAX_TASKS");
+        self.idle.wake_one();
+    }
+
+    fn pop(&self) -> Option<TaskId> {
+        self.ids.lock().take()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ids.lock().empty()
+    }
+}
+
+struct TaskWaker {
+    id: TaskId,
+    /// Set while the id sits in the ready queue so repeated wakes do not
+    /// queue it twice
+    queued: AtomicBool,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.queue.push(self.id);
+        }
+    }
+}
+
+struct Entry {
+    task: Task,
+    state: Arc<TaskWaker>,
+    waker: Waker,
+}
+
+/// Cooperative executor, tasks run until they return `Pending` and are
+/// polled again once something wakes them
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Entry>,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            queue: Arc::new(ReadyQueue::new()),
+        }
+    }
+
+    pub fn spawn(&mut self, task: Task) -> Result<TaskId, &'static str> {
+        if self.tasks.len() == MAX_TASKS {
+            return Err("too many tasks");
+        }
+        let id = task.id;
+        let state = Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(false),
+            queue: self.queue.clone(),
+        });
+        let waker = Waker::from(state.clone());
+        self.tasks.insert(id, Entry { task, state, waker });
+        self.tasks[&id].waker.wake_by_ref();
+        Ok(id)
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Polls every task that is ready right now, returns how many were polled
+    pub fn run_ready_tasks(&mut self) -> usize {
+        let mut polled = 0;
+        while let Some(id) = self.queue.pop() {
+            let Some(entry) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+            entry.state.queued.store(false, Ordering::Release);
+            let mut cx = Context::from_waker(&entry.waker);
+            polled += 1;
+            if entry.task.poll(&mut cx).is_ready() {
+                serial_info!("task {} ({:?}) finished", entry.task.name, id);
+                self.tasks.remove(&id);
+            }
+        }
+        polled
+    }
+
+    /// Runs tasks until none of them is ready
+    pub fn run_until_stalled(&mut self) {
+        while self.run_ready_tasks() != 0 {}
+    }
+
+    /// Sleeps until a waker fires. Inside a kernel thread this blocks the
+    /// thread, before threads are up it halts the cpu
+    fn sleep_if_idle(&self) {
+        if threading::initialized() {
+            self.queue.idle.wait(|| !self.queue.is_empty());
+            return;
+        }
+        asm::disable_interrupts();
+        if self.queue.is_empty() {
+            asm::enable_interrupts_and_hlt();
+        } else {
+            asm::enable_interrupts();
+        }
+    }
+
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+}
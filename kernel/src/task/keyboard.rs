@@ -0,0 +1,90 @@
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+use super::{waker::AtomicWaker, Stream};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf, serial_info, sync::shitlock::Racy, utils::asm,
+};
+
+const SCANCODE_QUEUE_LEN: usize = 128;
+
+lazy_static::lazy_static! {
+    static ref SCANCODES: Racy<RingBuf<u8, SCANCODE_QUEUE_LEN>> = Racy::from(RingBuf::new());
+}
+static WAKER: AtomicWaker = AtomicWaker::new();
+/// Scancodes are only queued while a stream exists
+static STREAM_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Called from the keyboard interrupt with every raw scancode
+pub fn add_scancode(code: u8) {
+    if !STREAM_OPEN.load(Ordering::Acquire) {
+        return;
+    }
+    if SCANCODES.take().push(code).is_err() {
+        serial_info!("scancode queue full, dropping {:#x}", code);
+        return;
+    }
+    WAKER.wake();
+}
+
+/// Raw scancode set 1 bytes from the keyboard interrupt. There can only
+/// be one stream at a time
+pub struct ScancodeStream {
+    _private: (),
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        let taken = STREAM_OPEN.swap(true, Ordering::AcqRel);
+        assert!(!taken, "ScancodeStream already exists");
+        Self { _private: () }
+    }
+}
+
+impl Drop for ScancodeStream {
+    fn drop(&mut self) {
+        STREAM_OPEN.store(false, Ordering::Release);
+        asm::without_interrupts(|| while SCANCODES.take().take().is_some() {});
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let take = || asm::without_interrupts(|| SCANCODES.take().take());
+        if let Some(code) = take() {
+            return Poll::Ready(Some(code));
+        }
+        WAKER.register(cx.waker());
+        // the interrupt may have come in before the waker was registered
+        match take() {
+            Some(code) => Poll::Ready(Some(code)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test_case]
+pub fn test_scancode_stream() {
+    serial_info!("Testing scancode stream");
+    let mut cx = Context::from_waker(core::task::Waker::noop());
+    add_scancode(0x1e);
+    let mut stream = ScancodeStream::new();
+    assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Pending);
+    add_scancode(0x1e);
+    add_scancode(0x9e);
+    assert_eq!(
+        Pin::new(&mut stream).poll_next(&mut cx),
+        Poll::Ready(Some(0x1e))
+    );
+    assert_eq!(
+        Pin::new(&mut stream).poll_next(&mut cx),
+        Poll::Ready(Some(0x9e))
+    );
+    drop(stream);
+    add_scancode(0x1e);
+    assert!(SCANCODES.take().empty());
+}
@@ -0,0 +1,93 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::{waker::AtomicWaker, Stream};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    devices::serial::{SerialCom, COM1},
+    interrupts::setup::PIC,
+    ksprintln, serial_info,
+    sync::shitlock::Racy,
+    utils::asm,
+};
+
+pub const COM1_IRQ: u8 = 4;
+const RX_QUEUE_LEN: usize = 256;
+
+lazy_static::lazy_static! {
+    static ref RX: Racy<RingBuf<u8, RX_QUEUE_LEN>> = Racy::from(RingBuf::new());
+}
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Called from the COM1 interrupt, drains the receive FIFO
+pub fn receive() {
+    let port = SerialCom::new(COM1);
+    let mut rx = RX.take();
+    while let Some(byte) = port.try_read() {
+        if rx.push(byte).is_err() {
+            serial_info!("serial rx queue full, dropping {:#x}", byte);
+        }
+    }
+    drop(rx);
+    WAKER.wake();
+}
+
+pub(super) fn setup() {
+    SerialCom::new(COM1).enable_rx_interrupt();
+    PIC.clear_irq(COM1_IRQ);
+}
+
+/// Bytes received on COM1
+pub struct SerialReader {
+    _private: (),
+}
+
+impl SerialReader {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+
+    /// Reads up to a newline, which is not stored. Returns the line length
+    pub async fn read_line(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while let Some(byte) = self.next().await {
+            match byte {
+                b'\r' | b'\n' => break,
+                _ if len < buf.len() => {
+                    buf[len] = byte;
+                    len += 1;
+                }
+                _ => {}
+            }
+        }
+        len
+    }
+}
+
+impl Stream for SerialReader {
+    type Item = u8;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let take = || asm::without_interrupts(|| RX.take().take());
+        if let Some(byte) = take() {
+            return Poll::Ready(Some(byte));
+        }
+        WAKER.register(cx.waker());
+        match take() {
+            Some(byte) => Poll::Ready(Some(byte)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Echoes every line typed on the serial console back to it
+pub async fn echo() {
+    let mut reader = SerialReader::new();
+    let mut line = [0u8; 128];
+    loop {
+        let len = reader.read_line(&mut line).await;
+        let text = core::str::from_utf8(&line[..len]).unwrap_or("<not utf-8>");
+        ksprintln!("{}", text);
+    }
+}
@@ -0,0 +1,81 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use crate::{
+    interrupts::timer::register_timer_callback,
+    io::time::{self, Instant},
+    sync::shitlock::Racy,
+    utils::asm,
+};
+
+const MAX_SLEEPERS: usize = 32;
+
+lazy_static::lazy_static! {
+    static ref SLEEPERS: Racy<[Option<(u64, Waker)>; MAX_SLEEPERS]> =
+        Racy::from([const { None }; MAX_SLEEPERS]);
+}
+
+/// Future returned by `sleep`, ready once the deadline tick has passed
+pub struct Sleep {
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.deadline.has_passed() {
+            return Poll::Ready(());
+        }
+        register(self.deadline, cx.waker());
+        Poll::Pending
+    }
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline }
+}
+
+fn register(deadline: Instant, waker: &Waker) {
+    let registered = asm::without_interrupts(|| {
+        let mut sleepers = SLEEPERS.take();
+        let slot = match sleepers
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(|(_, old)| old.will_wake(waker)))
+        {
+            Some(idx) => &mut sleepers[idx],
+            None => match sleepers.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => slot,
+                None => return false,
+            },
+        };
+        *slot = Some((deadline.0, waker.clone()));
+        time::request_wakeup(deadline);
+        true
+    });
+    if !registered {
+        // no room to wait, get polled again instead
+        waker.wake_by_ref();
+    }
+}
+
+fn wake_sleepers(now: u64) {
+    for slot in SLEEPERS.take().iter_mut() {
+        if slot.as_ref().is_some_and(|(deadline, _)| *deadline <= now) {
+            if let Some((_, waker)) = slot.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub(super) fn setup() {
+    register_timer_callback(wake_sleepers).expect("no free timer callback for async sleep");
+}
@@ -0,0 +1,32 @@
+use core::task::Waker;
+
+use crate::sync::spinlock::SpinLock;
+
+/// Waker slot that an interrupt handler or another cpu can wake. The slot
+/// sits behind a spin lock so neither side sees half an update
+pub struct AtomicWaker {
+    waker: SpinLock<Option<Waker>>,
+}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            waker: SpinLock::new(None),
+        }
+    }
+
+    pub fn register(&self, waker: &Waker) {
+        let mut slot = self.waker.lock();
+        if !slot.as_ref().is_some_and(|old| old.will_wake(waker)) {
+            *slot = Some(waker.clone());
+        }
+    }
+
+    /// Wakes and forgets the registered waker, if any
+    pub fn wake(&self) {
+        let waker = self.waker.lock().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use core::arch::global_asm;
+
+/// Saved kernel stack pointer, the registers themselves are pushed on the
+/// thread's stack by `switch_context`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Context {
+    pub rsp: u64,
+}
+
+/// What `switch_context` leaves on the stack, lowest address first
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SwitchFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbx: u64,
+    pub rbp: u64,
+    pub rflags: u64,
+    pub rip: u64,
+}
+
+pub const RFLAGS_RESERVED: u64 = 1 << 1;
+pub const RFLAGS_IF: u64 = 1 << 9;
+
+// switch_context(prev_rsp: *mut u64, next_rsp: u64)
+// only the callee saved registers and rflags need saving, the caller has
+// already spilled everything else
+global_asm!(
+    r#"
+.global switch_context
+switch_context:
+    pushfq
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
+    mov [rdi], rsp
+    mov rsp, rsi
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    popfq
+    ret
+
+.global thread_trampoline
+thread_trampoline:
+    mov rdi, r12
+    mov rsi, r13
+    call {start}
+    ud2
+"#,
+    start = sym super::kthread_start,
+);
+
+extern "C" {
+    fn switch_context(prev_rsp: *mut u64, next_rsp: u64);
+    fn thread_trampoline();
+}
+
+/// Saves the running thread into `prev` and resumes `next`
+pub unsafe fn switch_to(prev: *mut Context, next: *const Context) {
+    switch_context(&mut (*prev).rsp, (*next).rsp);
+}
+
+/// Builds the frame a fresh thread is first switched to, it "returns" into
+/// `thread_trampoline` which calls `kthread_start(entry, arg)`
+pub unsafe fn init_stack(stack_top: *mut u8, entry: u64, arg: u64, rflags: u64) -> Context {
+    let top = (stack_top as u64) & !0xf;
+    let frame = (top - core::mem::size_of::<SwitchFrame>() as u64) as *mut SwitchFrame;
+    frame.write(SwitchFrame {
+        r12: entry,
+        r13: arg,
+        rflags: rflags | RFLAGS_RESERVED,
+        rip: thread_trampoline as unsafe extern "C" fn() as usize as u64,
+        ..Default::default()
+    });
+    Context { rsp: frame as u64 }
+}
+
+#[test_case]
+pub fn test_switch_frame_size() {
+    // 6 registers + rflags + return address keeps the stack 16 byte aligned
+    assert_eq!(core::mem::size_of::<SwitchFrame>(), 64);
+}
@@ -1,10 +1,421 @@
+use core::sync::atomic::{AtomicBool, Ordering};
 
+use crate::{
+    cpu::{self, percpu},
+    paging,
+    process::tcb::{CpuStats, TaskControlBlock, ThreadState},
+    serial_info,
+    sync::shitlock::Racy,
+    utils::asm,
+};
 
+use self::{
+    context::{init_stack, RFLAGS_IF},
+    schedlock::{locked, SCHED_LOCK},
+};
 
-// pub trait KThreadIface {
-//     pub 
-// }
+pub mod context;
+pub mod policy;
+pub mod runqueue;
+pub mod schedlock;
+pub mod scheduler;
+pub mod workqueue;
 
-pub struct KThread;
+pub use scheduler::{
+    preempt_disable, preempt_enable, schedule, set_affinity, set_nice, set_policy, set_priority,
+    yield_now, PreemptGuard,
+};
 
+pub type ThreadFn = fn(usize) -> usize;
 
+pub const MAX_THREADS: usize = 32;
+pub const KSTACK_SIZE: usize = 4096 * 4;
+/// Returned when every slot is taken
+pub const THREAD_TABLE_FULL: &str = "thread table full";
+
+#[repr(C, align(16))]
+struct KStack([u8; KSTACK_SIZE]);
+
+/// One stack per thread slot, slot 0 is the boot thread which keeps the
+/// stack the bootloader gave it
+static mut KSTACKS: [KStack; MAX_THREADS] = [const { KStack([0; KSTACK_SIZE]) }; MAX_THREADS];
+
+pub struct ThreadTable {
+    pub threads: [TaskControlBlock; MAX_THREADS],
+}
+
+lazy_static::lazy_static! {
+    pub static ref THREADS: Racy<ThreadTable> = Racy::from(ThreadTable::new());
+}
+
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+impl ThreadTable {
+    pub fn new() -> Self {
+        let mut tid = 0;
+        Self {
+            threads: [(); MAX_THREADS].map(|_| {
+                tid += 1;
+                TaskControlBlock::empty(tid - 1)
+            }),
+        }
+    }
+
+    fn free_slot(&self) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|tcb| tcb.state == ThreadState::Free)
+    }
+
+    /// Round robin over the table starting after `after`
+    pub fn next_ready(&self, after: usize) -> Option<usize> {
+        (1..=MAX_THREADS)
+            .map(|i| (after + i) % MAX_THREADS)
+            .find(|&tid| self.threads[tid].state == ThreadState::Ready)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TaskControlBlock> {
+        self.threads
+            .iter()
+            .filter(|tcb| tcb.state != ThreadState::Free)
+    }
+
+    /// Detached threads can only give their slot back once we are off their stack
+    pub(crate) fn reap_detached(&mut self, current: usize) {
+        for tcb in self.threads.iter_mut() {
+            if tcb.thread_id != current && tcb.detached && tcb.state == ThreadState::Exited {
+                tcb.state = ThreadState::Free;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KThread {
+    tid: usize,
+}
+
+impl KThread {
+    pub fn id(&self) -> usize {
+        self.tid
+    }
+}
+
+pub struct JoinHandle {
+    thread: KThread,
+    joined: bool,
+}
+
+impl JoinHandle {
+    pub fn thread(&self) -> KThread {
+        self.thread
+    }
+
+    /// Waits for the thread to exit and returns what it returned
+    pub fn join(mut self) -> usize {
+        self.joined = true;
+        let tid = self.thread.tid;
+        locked(|| loop {
+            let mut threads = THREADS.take();
+            let tcb = &mut threads.threads[tid];
+            if tcb.state == ThreadState::Exited {
+                tcb.state = ThreadState::Free;
+                return tcb.retval;
+            }
+            tcb.joiner = Some(current().tid);
+            drop(threads);
+            scheduler::block_current();
+        })
+    }
+}
+
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        if self.joined {
+            return;
+        }
+        locked(|| {
+            let mut threads = THREADS.take();
+            let tcb = &mut threads.threads[self.thread.tid];
+            if tcb.state == ThreadState::Exited {
+                tcb.state = ThreadState::Free;
+            } else {
+                tcb.detached = true;
+            }
+        });
+    }
+}
+
+/// Turns the code that is running right now into thread 0 and starts the
+/// idle thread of the boot cpu
+pub fn init() {
+    if INITIALIZED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    // still on the boot page tables, remember them for kernel threads
+    paging::kernel_pml4();
+    locked(|| {
+        let mut threads = THREADS.take();
+        let main = &mut threads.threads[0];
+        main.state = ThreadState::Running;
+        main.name = "main";
+        main.time_slice = scheduler::quantum();
+        // the shell runs here and reads the keyboard of the boot cpu
+        main.affinity = cpu::cpu_mask(0);
+    });
+    percpu::set_current_thread(0);
+    let idle = create_idle(0).expect("unable to start idle thread");
+    locked(|| runqueue::RUN_QUEUES.take()[0].idle_tid = Some(idle));
+}
+
+/// Idle thread pinned to `cpu`, not runnable until the cpu switches to it
+fn create_idle(cpu: usize) -> Result<usize, &'static str> {
+    let idle = create_thread("idle", scheduler::idle_thread, cpu)?;
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tcb = &mut threads.threads[idle];
+        tcb.affinity = cpu::cpu_mask(cpu);
+        tcb.cpu = cpu;
+        // the idle thread lives forever, nobody joins it
+        tcb.detached = true;
+    });
+    Ok(idle)
+}
+
+/// Where an application processor goes once it is set up, it starts taking
+/// threads from then on
+pub fn start_cpu(cpu: usize) -> ! {
+    let idle = create_idle(cpu).expect("unable to start idle thread");
+    scheduler::start_cpu(idle)
+}
+
+pub fn initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+pub fn current() -> KThread {
+    KThread {
+        tid: percpu::current_thread(),
+    }
+}
+
+/// Sets up the slot and initial stack frame of a new thread without making
+/// it runnable
+fn create_thread(name: &'static str, entry: ThreadFn, arg: usize) -> Result<usize, &'static str> {
+    let rflags = if asm::interrupts_enabled() {
+        RFLAGS_IF
+    } else {
+        0
+    };
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tid = threads.free_slot().ok_or(THREAD_TABLE_FULL)?;
+        let stack_top = unsafe {
+            let stack = core::ptr::addr_of_mut!(KSTACKS[tid]) as *mut u8;
+            stack.add(KSTACK_SIZE)
+        };
+        let tcb = &mut threads.threads[tid];
+        *tcb = TaskControlBlock::empty(tid);
+        tcb.name = name;
+        tcb.stack_top = stack_top;
+        tcb.entry = Some(entry);
+        tcb.arg = arg;
+        tcb.context = unsafe { init_stack(stack_top, entry as usize as u64, arg as u64, rflags) };
+        tcb.state = ThreadState::Blocked;
+        Ok(tid)
+    })
+}
+
+pub fn spawn_named(
+    name: &'static str,
+    entry: ThreadFn,
+    arg: usize,
+) -> Result<JoinHandle, &'static str> {
+    spawn_with(name, entry, arg, |_| {})
+}
+
+/// Like `spawn_named`, `configure` can change the slot before the thread
+/// becomes runnable
+pub fn spawn_with(
+    name: &'static str,
+    entry: ThreadFn,
+    arg: usize,
+    configure: impl FnOnce(&mut TaskControlBlock),
+) -> Result<JoinHandle, &'static str> {
+    let tid = create_thread(name, entry, arg)?;
+    locked(|| configure(&mut THREADS.take().threads[tid]));
+    scheduler::enqueue(tid, policy::EnqueueReason::New);
+    Ok(JoinHandle {
+        thread: KThread { tid },
+        joined: false,
+    })
+}
+
+pub fn spawn(entry: ThreadFn, arg: usize) -> JoinHandle {
+    spawn_named("kthread", entry, arg).expect("unable to spawn thread")
+}
+
+/// First Rust code a new thread runs, see `thread_trampoline`. It was
+/// switched to with the scheduler lock held and has nothing to unwind
+extern "C" fn kthread_start(entry: usize, arg: usize) -> ! {
+    asm::without_interrupts(|| SCHED_LOCK.release_all());
+    let entry: ThreadFn = unsafe { core::mem::transmute(entry) };
+    let retval = entry(arg);
+    exit(retval)
+}
+
+/// Ends the current thread, `retval` is handed to whoever joins it
+pub fn exit(retval: usize) -> ! {
+    asm::disable_interrupts();
+    // held until we are off this stack so nobody frees the slot before that
+    SCHED_LOCK.lock();
+    {
+        let mut threads = THREADS.take();
+        let tcb = &mut threads.threads[current().tid];
+        tcb.retval = retval;
+        tcb.state = ThreadState::Exited;
+        if let Some(joiner) = tcb.joiner.take() {
+            drop(threads);
+            scheduler::wake(joiner, policy::EnqueueReason::Wakeup);
+        }
+    }
+    scheduler::schedule();
+    unreachable!("exited thread was scheduled again");
+}
+
+pub fn dump() {
+    locked(|| {
+        for tcb in THREADS.take().iter() {
+            serial_info!(
+                "thread {:>2} {:<12} {:?} prio={} nice={} level={} slice={} rsp={:#x} usr={} sys={} wait={} vcsw={} icsw={} cpu={} affinity={:#x} migrations={}",
+                tcb.thread_id,
+                tcb.name,
+                tcb.state,
+                tcb.priority,
+                tcb.nice,
+                tcb.mlfq_level,
+                tcb.time_slice,
+                tcb.context.rsp,
+                tcb.stats.user_ticks,
+                tcb.stats.system_ticks,
+                tcb.stats.wait_ticks,
+                tcb.stats.voluntary_switches,
+                tcb.stats.involuntary_switches,
+                tcb.stats.last_cpu,
+                tcb.affinity,
+                tcb.stats.migrations
+            );
+        }
+    });
+    scheduler::dump();
+}
+
+/// What `ps` and `top` show about one thread
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadInfo {
+    pub tid: usize,
+    pub name: &'static str,
+    pub state: ThreadState,
+    pub priority: u8,
+    pub nice: i8,
+    pub stats: CpuStats,
+}
+
+/// Copies out every live thread, indexed by tid. Interrupts are off while
+/// copying so the numbers all come from the same tick
+pub fn snapshot() -> [Option<ThreadInfo>; MAX_THREADS] {
+    locked(|| {
+        let threads = THREADS.take();
+        let mut infos = [None; MAX_THREADS];
+        for tcb in threads.iter() {
+            infos[tcb.thread_id] = Some(ThreadInfo {
+                tid: tcb.thread_id,
+                name: tcb.name,
+                state: tcb.state,
+                priority: tcb.priority,
+                nice: tcb.nice,
+                stats: tcb.stats,
+            });
+        }
+        infos
+    })
+}
+
+#[test_case]
+pub fn test_spawn_join() {
+    serial_info!("Testing spawn and join");
+    init();
+    let handle = spawn(|arg| arg * 2, 21);
+    assert_eq!(handle.join(), 42);
+    let handles = [spawn(|arg| arg + 1, 1), spawn(|arg| arg + 2, 2)];
+    let total: usize = handles.into_iter().map(|handle| handle.join()).sum();
+    assert_eq!(total, 6);
+}
+
+#[test_case]
+pub fn test_round_robin() {
+    serial_info!("Testing round robin order");
+    init();
+    static mut LOG: [usize; 6] = [0; 6];
+    static LOG_LEN: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+    fn worker(id: usize) -> usize {
+        for _ in 0..2 {
+            let idx = LOG_LEN.fetch_add(1, Ordering::AcqRel);
+            unsafe { LOG[idx] = id };
+            yield_now();
+        }
+        0
+    }
+    let handles = [spawn(worker, 1), spawn(worker, 2), spawn(worker, 3)];
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(unsafe { LOG }, [1, 2, 3, 1, 2, 3]);
+}
+
+#[test_case]
+pub fn test_switch_accounting() {
+    serial_info!("Testing context switch accounting");
+    init();
+    let switches = || {
+        let stats = snapshot()[current().id()]
+            .expect("current thread is live")
+            .stats;
+        stats.voluntary_switches + stats.involuntary_switches
+    };
+    let before = switches();
+    // the child has not run yet, so the join has to switch away from us
+    spawn(|arg| arg, 0).join();
+    assert!(switches() > before);
+}
+
+#[test_case]
+pub fn test_tick_accounting() {
+    serial_info!("Testing that skipped ticks are charged");
+    init();
+    let ticks = || {
+        snapshot()[current().id()]
+            .expect("current thread is live")
+            .stats
+            .busy_ticks()
+    };
+    let before = ticks();
+    // what a tick after tickless idle looks like
+    scheduler::tick(scheduler::last_tick() + 3, false);
+    assert_eq!(ticks(), before + 3);
+}
+
+#[test_case]
+pub fn test_affinity() {
+    serial_info!("Testing cpu affinity");
+    init();
+    let tid = current().id();
+    // only the boot cpu is online in the test kernel
+    assert!(set_affinity(tid, cpu::cpu_mask(1)).is_err());
+    assert!(set_affinity(tid, cpu::ALL_CPUS).is_ok());
+    let handle = spawn(|arg| arg, 7);
+    set_affinity(handle.thread().id(), cpu::cpu_mask(0)).unwrap();
+    assert_eq!(handle.join(), 7);
+    assert_eq!(THREADS.take().threads[tid].cpu, 0);
+    set_affinity(tid, cpu::cpu_mask(0)).unwrap();
+}
@@ -0,0 +1,265 @@
+use crate::{datastructures::no_alloc::ringbuffer::RingBuf, process::tcb::TaskControlBlock};
+
+use super::MAX_THREADS;
+
+pub const NUM_PRIORITIES: usize = 8;
+pub const DEFAULT_PRIORITY: u8 = 4;
+pub const MAX_PRIORITY: u8 = NUM_PRIORITIES as u8 - 1;
+pub const MIN_NICE: i8 = -20;
+pub const MAX_NICE: i8 = 19;
+/// Every thread goes back to the top MLFQ level this often so that cpu
+/// bound threads are not starved forever
+pub const MLFQ_BOOST_TICKS: u64 = 100;
+
+type TidQueue = RingBuf<usize, MAX_THREADS>;
+
+/// Why a thread is being put back on the run queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnqueueReason {
+    New,
+    /// Used up its time slice
+    Preempted,
+    /// Gave up the cpu on its own
+    Yield,
+    Wakeup,
+    /// Woken up by user input, interactive threads get a boost
+    InteractiveWakeup,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    RoundRobin,
+    Priority,
+    Mlfq,
+}
+
+impl PolicyKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rr" => Some(Self::RoundRobin),
+            "prio" => Some(Self::Priority),
+            "mlfq" => Some(Self::Mlfq),
+            _ => None,
+        }
+    }
+}
+
+pub trait SchedPolicy {
+    fn name(&self) -> &'static str;
+    fn enqueue(&mut self, tcb: &mut TaskControlBlock, reason: EnqueueReason);
+    /// May return threads that stopped being ready, the caller skips those
+    fn pick_next(&mut self) -> Option<usize>;
+    fn is_empty(&self) -> bool;
+    fn quantum(&self, tcb: &TaskControlBlock, base: u32) -> u32;
+    /// Whether a freshly woken `woken` should kick `running` off the cpu
+    fn should_preempt(&self, _running: &TaskControlBlock, _woken: &TaskControlBlock) -> bool {
+        false
+    }
+    /// Called once per timer tick with the current tick count. True when
+    /// every thread went back to the top MLFQ level, the scheduler resets
+    /// the levels kept in the TCBs then
+    fn on_tick(&mut self, _now: u64) -> bool {
+        false
+    }
+}
+
+/// Priority with nice folded in, higher runs first
+pub fn effective_priority(tcb: &TaskControlBlock) -> usize {
+    let prio = tcb.priority as i16 - tcb.nice as i16 / 5;
+    prio.clamp(0, MAX_PRIORITY as i16) as usize
+}
+
+fn push(queue: &mut TidQueue, tid: usize) {
+    queue
+        .push(tid)
+        .expect("run queue is larger than the thread table");
+}
+
+pub struct RoundRobin {
+    queue: TidQueue,
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        Self {
+            queue: RingBuf::new(),
+        }
+    }
+}
+
+impl SchedPolicy for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+    fn enqueue(&mut self, tcb: &mut TaskControlBlock, _reason: EnqueueReason) {
+        push(&mut self.queue, tcb.thread_id);
+    }
+    fn pick_next(&mut self) -> Option<usize> {
+        self.queue.take()
+    }
+    fn is_empty(&self) -> bool {
+        self.queue.empty()
+    }
+    fn quantum(&self, _tcb: &TaskControlBlock, base: u32) -> u32 {
+        base
+    }
+}
+
+/// Always runs the highest priority ready thread, round robin within a level
+pub struct StrictPriority {
+    queues: [TidQueue; NUM_PRIORITIES],
+}
+
+impl StrictPriority {
+    pub const fn new() -> Self {
+        Self {
+            queues: [const { RingBuf::new() }; NUM_PRIORITIES],
+        }
+    }
+}
+
+impl SchedPolicy for StrictPriority {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+    fn enqueue(&mut self, tcb: &mut TaskControlBlock, _reason: EnqueueReason) {
+        push(&mut self.queues[effective_priority(tcb)], tcb.thread_id);
+    }
+    fn pick_next(&mut self) -> Option<usize> {
+        self.queues.iter_mut().rev().find_map(|queue| queue.take())
+    }
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(|queue| queue.empty())
+    }
+    fn quantum(&self, _tcb: &TaskControlBlock, base: u32) -> u32 {
+        base
+    }
+    fn should_preempt(&self, running: &TaskControlBlock, woken: &TaskControlBlock) -> bool {
+        effective_priority(woken) > effective_priority(running)
+    }
+}
+
+/// Multi-level feedback queue. Level 0 is the top, threads that burn their
+/// whole slice sink a level and get a longer slice, threads woken by input
+/// go back to the top
+pub struct Mlfq {
+    levels: [TidQueue; NUM_PRIORITIES],
+    last_boost: u64,
+}
+
+impl Mlfq {
+    pub const fn new() -> Self {
+        Self {
+            levels: [const { RingBuf::new() }; NUM_PRIORITIES],
+            last_boost: 0,
+        }
+    }
+
+    /// Threads start at a level matching their priority
+    pub fn initial_level(tcb: &TaskControlBlock) -> u8 {
+        MAX_PRIORITY - effective_priority(tcb) as u8
+    }
+}
+
+impl SchedPolicy for Mlfq {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+    fn enqueue(&mut self, tcb: &mut TaskControlBlock, reason: EnqueueReason) {
+        match reason {
+            EnqueueReason::New => tcb.mlfq_level = Self::initial_level(tcb),
+            EnqueueReason::Preempted => {
+                tcb.mlfq_level = (tcb.mlfq_level + 1).min(MAX_PRIORITY);
+            }
+            EnqueueReason::InteractiveWakeup => tcb.mlfq_level = 0,
+            EnqueueReason::Yield | EnqueueReason::Wakeup => {}
+        }
+        push(&mut self.levels[tcb.mlfq_level as usize], tcb.thread_id);
+    }
+    fn pick_next(&mut self) -> Option<usize> {
+        self.levels.iter_mut().find_map(|queue| queue.take())
+    }
+    fn is_empty(&self) -> bool {
+        self.levels.iter().all(|queue| queue.empty())
+    }
+    fn quantum(&self, tcb: &TaskControlBlock, base: u32) -> u32 {
+        base << tcb.mlfq_level.min(4)
+    }
+    fn should_preempt(&self, running: &TaskControlBlock, woken: &TaskControlBlock) -> bool {
+        woken.mlfq_level < running.mlfq_level
+    }
+    fn on_tick(&mut self, now: u64) -> bool {
+        if now - self.last_boost < MLFQ_BOOST_TICKS {
+            return false;
+        }
+        self.last_boost = now;
+        for level in 1..NUM_PRIORITIES {
+            while let Some(tid) = self.levels[level].take() {
+                push(&mut self.levels[0], tid);
+            }
+        }
+        true
+    }
+}
+
+/// All policies live side by side so that the active one can be switched at
+/// runtime
+pub struct Policies {
+    pub kind: PolicyKind,
+    round_robin: RoundRobin,
+    priority: StrictPriority,
+    mlfq: Mlfq,
+}
+
+impl Policies {
+    pub const fn new() -> Self {
+        Self {
+            kind: PolicyKind::RoundRobin,
+            round_robin: RoundRobin::new(),
+            priority: StrictPriority::new(),
+            mlfq: Mlfq::new(),
+        }
+    }
+
+    pub fn active(&mut self) -> &mut dyn SchedPolicy {
+        match self.kind {
+            PolicyKind::RoundRobin => &mut self.round_robin,
+            PolicyKind::Priority => &mut self.priority,
+            PolicyKind::Mlfq => &mut self.mlfq,
+        }
+    }
+}
+
+#[test_case]
+pub fn test_policy_order() {
+    let mut tcbs = [(); 4].map(|_| TaskControlBlock::empty(0));
+    for (tid, tcb) in tcbs.iter_mut().enumerate() {
+        tcb.thread_id = tid;
+        tcb.priority = tid as u8;
+    }
+
+    let mut rr = RoundRobin::new();
+    let mut prio = StrictPriority::new();
+    for tcb in tcbs.iter_mut() {
+        rr.enqueue(tcb, EnqueueReason::New);
+        prio.enqueue(tcb, EnqueueReason::New);
+    }
+    assert_eq!(rr.pick_next(), Some(0));
+    assert_eq!(prio.pick_next(), Some(3));
+    assert_eq!(prio.pick_next(), Some(2));
+
+    let mut mlfq = Mlfq::new();
+    tcbs[1].priority = DEFAULT_PRIORITY;
+    tcbs[2].priority = DEFAULT_PRIORITY;
+    mlfq.enqueue(&mut tcbs[1], EnqueueReason::New);
+    mlfq.enqueue(&mut tcbs[2], EnqueueReason::New);
+    assert_eq!(mlfq.pick_next(), Some(1));
+    // 1 burnt its slice and sinks below 2
+    mlfq.enqueue(&mut tcbs[1], EnqueueReason::Preempted);
+    assert_eq!(mlfq.pick_next(), Some(2));
+    mlfq.enqueue(&mut tcbs[2], EnqueueReason::InteractiveWakeup);
+    assert_eq!(tcbs[2].mlfq_level, 0);
+    assert_eq!(mlfq.pick_next(), Some(2));
+    assert_eq!(mlfq.pick_next(), Some(1));
+    assert!(mlfq.is_empty());
+}
@@ -0,0 +1,74 @@
+use super::policy::{EnqueueReason, Policies};
+use crate::{cpu::MAX_CPUS, process::tcb::TaskControlBlock, sync::shitlock::Racy};
+
+/// How threads moved between CPUs, counted on the CPU they moved to unless
+/// noted otherwise
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MigrationStats {
+    /// Taken from another queue because this CPU ran dry
+    pub stolen: u64,
+    /// Moved here by the periodic balancer
+    pub pulled: u64,
+    /// Moved away from here by the periodic balancer
+    pub pushed: u64,
+    /// Reschedule IPIs this CPU received
+    pub kicks: u64,
+}
+
+impl MigrationStats {
+    pub const fn new() -> Self {
+        Self {
+            stolen: 0,
+            pulled: 0,
+            pushed: 0,
+            kicks: 0,
+        }
+    }
+}
+
+/// Threads waiting for one CPU, ordered by that CPU's policy
+pub struct RunQueue {
+    pub policies: Policies,
+    /// Queued threads, entries that went stale are counted until picked
+    pub nr_queued: usize,
+    /// None until the CPU has joined the scheduler
+    pub idle_tid: Option<usize>,
+    pub stats: MigrationStats,
+}
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            policies: Policies::new(),
+            nr_queued: 0,
+            idle_tid: None,
+            stats: MigrationStats::new(),
+        }
+    }
+
+    pub fn online(&self) -> bool {
+        self.idle_tid.is_some()
+    }
+
+    pub fn push(&mut self, tcb: &mut TaskControlBlock, reason: EnqueueReason) {
+        self.policies.active().enqueue(tcb, reason);
+        self.nr_queued += 1;
+    }
+
+    /// May return threads that stopped being ready, the caller skips those
+    pub fn pop(&mut self) -> Option<usize> {
+        let tid = self.policies.active().pick_next()?;
+        self.nr_queued = self.nr_queued.saturating_sub(1);
+        Some(tid)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nr_queued == 0
+    }
+}
+
+lazy_static::lazy_static! {
+    /// One queue per CPU, only touched with the scheduler lock held
+    pub static ref RUN_QUEUES: Racy<[RunQueue; MAX_CPUS]> =
+        Racy::from([const { RunQueue::new() }; MAX_CPUS]);
+}
@@ -0,0 +1,79 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{cpu::percpu, utils::asm};
+
+const NO_OWNER: usize = usize::MAX;
+
+/// Guards the thread table, every run queue and the wait queues. It is only
+/// held with interrupts disabled and the CPU holding it can take it again.
+/// A context switch hands it over to the next thread, which goes on with the
+/// depth it had when it was switched out
+pub struct SchedLock {
+    owner: AtomicUsize,
+    depth: AtomicUsize,
+}
+
+pub static SCHED_LOCK: SchedLock = SchedLock::new();
+
+impl SchedLock {
+    pub const fn new() -> Self {
+        Self {
+            owner: AtomicUsize::new(NO_OWNER),
+            depth: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn lock(&self) {
+        let me = percpu::cpu_id();
+        if self.owner.load(Ordering::Acquire) == me {
+            self.depth.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        while self
+            .owner
+            .compare_exchange_weak(NO_OWNER, me, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        self.depth.store(1, Ordering::Relaxed);
+    }
+
+    pub fn unlock(&self) {
+        assert_eq!(
+            self.owner.load(Ordering::Relaxed),
+            percpu::cpu_id(),
+            "scheduler lock released by a cpu that does not hold it"
+        );
+        if self.depth.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.owner.store(NO_OWNER, Ordering::Release);
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Called by a thread right after it was switched back in, the lock is
+    /// still held by this CPU but at the depth of the thread it came from
+    pub fn resume(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Drops every level, for a new thread that was switched to with the
+    /// lock held and has nothing to unwind
+    pub fn release_all(&self) {
+        self.depth.store(0, Ordering::Relaxed);
+        self.owner.store(NO_OWNER, Ordering::Release);
+    }
+}
+
+/// Runs `f` with interrupts disabled and the scheduler lock held
+pub fn locked<R>(f: impl FnOnce() -> R) -> R {
+    asm::without_interrupts(|| {
+        SCHED_LOCK.lock();
+        let ret = f();
+        SCHED_LOCK.unlock();
+        ret
+    })
+}
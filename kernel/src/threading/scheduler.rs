@@ -0,0 +1,673 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use super::{
+    context::{switch_to, Context},
+    current,
+    policy::{EnqueueReason, PolicyKind, MAX_NICE, MAX_PRIORITY, MIN_NICE},
+    runqueue::{RunQueue, RUN_QUEUES},
+    schedlock::{locked, SCHED_LOCK},
+    THREADS,
+};
+use crate::{
+    cpu::{self, percpu, smp, CpuMask, MAX_CPUS},
+    descriptors::gdt,
+    devices::lapic::LocalApic,
+    interrupts::ipi::RESCHED_VECTOR,
+    io::time::{self, Instant},
+    paging,
+    process::tcb::{TaskControlBlock, ThreadState},
+    serial_info,
+    utils::asm,
+};
+
+use super::MAX_THREADS;
+
+pub const DEFAULT_QUANTUM_TICKS: u32 = 5;
+/// How often the boot cpu evens out the run queues
+pub const BALANCE_INTERVAL_TICKS: u64 = 20;
+
+static QUANTUM_TICKS: AtomicU32 = AtomicU32::new(DEFAULT_QUANTUM_TICKS);
+/// Tick count `tick` last charged up to, idle can skip several at once
+static LAST_TICK: AtomicU64 = AtomicU64::new(0);
+static LAST_BALANCE: AtomicU64 = AtomicU64::new(0);
+
+type RunQueues = [RunQueue; MAX_CPUS];
+type Tcbs = [TaskControlBlock; MAX_THREADS];
+
+pub fn set_quantum(ticks: u32) {
+    QUANTUM_TICKS.store(ticks.max(1), Ordering::Release);
+}
+
+pub fn quantum() -> u32 {
+    QUANTUM_TICKS.load(Ordering::Acquire)
+}
+
+fn allowed(tcb: &TaskControlBlock, cpu: usize) -> bool {
+    tcb.affinity & cpu::cpu_mask(cpu) != 0
+}
+
+fn is_idle(queues: &RunQueues, cpu: usize) -> bool {
+    queues[cpu].idle_tid == Some(percpu::cpu(cpu).current_thread())
+}
+
+/// Threads queued on `cpu` plus the one running there unless it is idle
+fn load(queues: &RunQueues, cpu: usize) -> usize {
+    queues[cpu].nr_queued + !is_idle(queues, cpu) as usize
+}
+
+/// Stays on the cpu the thread last ran on unless an allowed one is less busy
+fn select_cpu(queues: &RunQueues, tcb: &TaskControlBlock) -> usize {
+    let prev = tcb.cpu;
+    if queues[prev].online() && allowed(tcb, prev) && load(queues, prev) == 0 {
+        return prev;
+    }
+    (0..MAX_CPUS)
+        .filter(|&cpu| queues[cpu].online() && allowed(tcb, cpu))
+        .min_by_key(|&cpu| (load(queues, cpu), cpu != prev))
+        .unwrap_or(prev)
+}
+
+fn migrate(tcb: &mut TaskControlBlock, cpu: usize) {
+    if tcb.cpu != cpu {
+        tcb.cpu = cpu;
+        tcb.stats.migrations += 1;
+    }
+}
+
+/// Puts a ready thread on the run queue of the cpu it should run on
+fn place(queues: &mut RunQueues, tcb: &mut TaskControlBlock, reason: EnqueueReason) -> usize {
+    let cpu = select_cpu(queues, tcb);
+    if reason == EnqueueReason::New {
+        tcb.cpu = cpu;
+    } else {
+        migrate(tcb, cpu);
+    }
+    queues[cpu].push(tcb, reason);
+    cpu
+}
+
+/// Makes `cpu` go through the scheduler, through an IPI if it is not us
+fn kick(queues: &mut RunQueues, cpu: usize) {
+    if cpu == percpu::cpu_id() {
+        percpu::set_need_resched(true);
+        return;
+    }
+    if let Some(lapic) = LocalApic::get() {
+        queues[cpu].stats.kicks += 1;
+        lapic.send_fixed(smp::apic_id(cpu), RESCHED_VECTOR);
+    }
+}
+
+/// Pops the first ready thread of `queues[from]` that may run on `to`, the
+/// ones that may not go back on the queue
+fn take_movable(
+    queues: &mut RunQueues,
+    threads: &mut Tcbs,
+    from: usize,
+    to: usize,
+) -> Option<usize> {
+    let mut skipped = [0usize; MAX_THREADS];
+    let mut len = 0;
+    let mut found = None;
+    while let Some(tid) = queues[from].pop() {
+        let tcb = &threads[tid];
+        if tcb.state != ThreadState::Ready {
+            continue;
+        }
+        if allowed(tcb, to) {
+            found = Some(tid);
+            break;
+        }
+        skipped[len] = tid;
+        len += 1;
+    }
+    for &tid in &skipped[..len] {
+        queues[from].push(&mut threads[tid], EnqueueReason::Yield);
+    }
+    found
+}
+
+/// Takes a thread off the busiest queue that has one `this` may run
+fn steal(queues: &mut RunQueues, threads: &mut Tcbs, this: usize) -> Option<usize> {
+    let mut victims = [(0usize, 0usize); MAX_CPUS];
+    let mut len = 0;
+    for cpu in (0..MAX_CPUS).filter(|&cpu| cpu != this && !queues[cpu].is_empty()) {
+        victims[len] = (queues[cpu].nr_queued, cpu);
+        len += 1;
+    }
+    let victims = &mut victims[..len];
+    victims.sort_unstable_by(|a, b| b.cmp(a));
+    for &(_, victim) in victims.iter() {
+        if let Some(tid) = take_movable(queues, threads, victim, this) {
+            queues[this].stats.stolen += 1;
+            migrate(&mut threads[tid], this);
+            return Some(tid);
+        }
+    }
+    None
+}
+
+/// Moves one thread from the busiest to the least busy cpu when they are
+/// at least two threads apart
+fn balance(queues: &mut RunQueues, threads: &mut Tcbs) {
+    let online = || (0..MAX_CPUS).filter(|&cpu| queues[cpu].online());
+    let busiest = online().max_by_key(|&cpu| load(queues, cpu));
+    let idlest = online().min_by_key(|&cpu| load(queues, cpu));
+    let (Some(busiest), Some(idlest)) = (busiest, idlest) else {
+        return;
+    };
+    if load(queues, busiest) < load(queues, idlest) + 2 {
+        return;
+    }
+    let Some(tid) = take_movable(queues, threads, busiest, idlest) else {
+        return;
+    };
+    let tcb = &mut threads[tid];
+    migrate(tcb, idlest);
+    queues[idlest].push(tcb, EnqueueReason::Yield);
+    queues[busiest].stats.pushed += 1;
+    queues[idlest].stats.pulled += 1;
+    if is_idle(queues, idlest) {
+        kick(queues, idlest);
+    }
+}
+
+/// Marks `tid` ready and queues it, returns the cpu it was queued on
+fn make_ready(tid: usize, reason: EnqueueReason) -> Option<usize> {
+    let mut threads = THREADS.take();
+    let tcb = &mut threads.threads[tid];
+    if matches!(tcb.state, ThreadState::Ready | ThreadState::Running) {
+        return None;
+    }
+    tcb.state = ThreadState::Ready;
+    tcb.stats.ready_since = Instant::now().0;
+    Some(place(&mut RUN_QUEUES.take(), tcb, reason))
+}
+
+/// Makes `tid` runnable, a remote idle cpu it lands on is woken up
+pub fn enqueue(tid: usize, reason: EnqueueReason) {
+    locked(|| {
+        let Some(cpu) = make_ready(tid, reason) else {
+            return;
+        };
+        let mut queues = RUN_QUEUES.take();
+        if cpu != percpu::cpu_id() && is_idle(&queues, cpu) {
+            kick(&mut queues, cpu);
+        }
+    });
+}
+
+/// Makes a blocked thread runnable again and asks for a reschedule on the
+/// cpu it was queued on if the policy prefers it over whatever runs there
+pub fn wake(tid: usize, reason: EnqueueReason) {
+    locked(|| {
+        let Some(cpu) = make_ready(tid, reason) else {
+            return;
+        };
+        let mut queues = RUN_QUEUES.take();
+        let threads = THREADS.take();
+        let running = percpu::cpu(cpu).current_thread();
+        let preempt = is_idle(&queues, cpu)
+            || queues[cpu]
+                .policies
+                .active()
+                .should_preempt(&threads.threads[running], &threads.threads[tid]);
+        if preempt {
+            kick(&mut queues, cpu);
+        }
+    });
+}
+
+/// Interrupts the cpu `tid` runs on if that is not this one, so a thread in
+/// ring 3 there goes through the way back from the interrupt and acts on
+/// its signals
+pub fn notify(tid: usize) {
+    locked(|| {
+        let mut queues = RUN_QUEUES.take();
+        let cpu = {
+            let threads = THREADS.take();
+            let tcb = &threads.threads[tid];
+            (tcb.state == ThreadState::Running).then_some(tcb.cpu)
+        };
+        if let Some(cpu) = cpu.filter(|&cpu| cpu != percpu::cpu_id()) {
+            kick(&mut queues, cpu);
+        }
+    });
+}
+
+/// Puts the current thread to sleep until somebody calls `wake` on it.
+/// Callers that check a condition first must hold the scheduler lock from
+/// the check until here so that the wakeup cannot be lost
+pub fn block_current() {
+    locked(|| {
+        THREADS.take().threads[current().id()].state = ThreadState::Blocked;
+        schedule();
+    });
+}
+
+/// Blocks the current thread until tick `deadline`
+pub fn sleep_until(deadline: Instant) {
+    locked(|| {
+        {
+            let mut threads = THREADS.take();
+            let tcb = &mut threads.threads[current().id()];
+            tcb.wait_channel = 0;
+            tcb.wake_at = Some(deadline.0);
+        }
+        time::request_wakeup(deadline);
+        block_current();
+    });
+}
+
+/// Wakes the blocked threads whose `wake_at` has passed
+fn wake_expired(now: u64) {
+    let mut expired = [0usize; MAX_THREADS];
+    let mut len = 0;
+    for tcb in THREADS.take().threads.iter_mut() {
+        let due = tcb.wake_at.is_some_and(|wake_at| wake_at <= now);
+        if tcb.state == ThreadState::Blocked && due {
+            tcb.wake_at = None;
+            tcb.timed_out = tcb.wait_channel != 0;
+            tcb.wait_channel = 0;
+            expired[len] = tcb.thread_id;
+            len += 1;
+        }
+    }
+    for &tid in &expired[..len] {
+        wake(tid, EnqueueReason::Wakeup);
+    }
+}
+
+/// Earliest tick a blocked thread wants to be woken at
+pub fn next_wakeup() -> Option<Instant> {
+    locked(|| {
+        THREADS
+            .take()
+            .threads
+            .iter()
+            .filter(|tcb| tcb.state == ThreadState::Blocked)
+            .filter_map(|tcb| tcb.wake_at)
+            .min()
+            .map(Instant)
+    })
+}
+
+/// Switches the policy of every cpu, everything already queued moves over
+pub fn set_policy(kind: PolicyKind) {
+    locked(|| {
+        let mut queues = RUN_QUEUES.take();
+        let mut threads = THREADS.take();
+        for rq in queues.iter_mut().filter(|rq| rq.policies.kind != kind) {
+            let mut queued = [0usize; MAX_THREADS];
+            let mut len = 0;
+            while let Some(tid) = rq.pop() {
+                queued[len] = tid;
+                len += 1;
+            }
+            rq.policies.kind = kind;
+            for &tid in &queued[..len] {
+                let tcb = &mut threads.threads[tid];
+                if tcb.state == ThreadState::Ready {
+                    rq.push(tcb, EnqueueReason::New);
+                }
+            }
+        }
+    });
+}
+
+pub fn policy_name() -> &'static str {
+    locked(|| RUN_QUEUES.take()[0].policies.active().name())
+}
+
+/// Takes effect the next time the thread is queued
+pub fn set_priority(tid: usize, priority: u8) -> Result<(), &'static str> {
+    if priority > MAX_PRIORITY {
+        return Err("priority out of range");
+    }
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tcb = threads.threads.get_mut(tid).ok_or("no such thread")?;
+        if !tcb.is_alive() {
+            return Err("no such thread");
+        }
+        tcb.priority = priority;
+        Ok(())
+    })
+}
+
+pub fn set_nice(tid: usize, nice: i8) -> Result<(), &'static str> {
+    if !(MIN_NICE..=MAX_NICE).contains(&nice) {
+        return Err("nice out of range");
+    }
+    locked(|| {
+        let mut threads = THREADS.take();
+        let tcb = threads.threads.get_mut(tid).ok_or("no such thread")?;
+        if !tcb.is_alive() {
+            return Err("no such thread");
+        }
+        tcb.nice = nice;
+        Ok(())
+    })
+}
+
+/// Restricts `tid` to the cpus in `mask`. A queued thread moves the next
+/// time its queue picks it, a running one is kicked off a cpu it left
+pub fn set_affinity(tid: usize, mask: CpuMask) -> Result<(), &'static str> {
+    let mask = mask & cpu::ALL_CPUS;
+    locked(|| {
+        let mut queues = RUN_QUEUES.take();
+        let any_online =
+            (0..MAX_CPUS).any(|cpu| queues[cpu].online() && mask & cpu::cpu_mask(cpu) != 0);
+        if !any_online {
+            return Err("no online cpu in the mask");
+        }
+        let mut threads = THREADS.take();
+        let tcb = threads.threads.get_mut(tid).ok_or("no such thread")?;
+        if !tcb.is_alive() {
+            return Err("no such thread");
+        }
+        tcb.affinity = mask;
+        if tcb.state == ThreadState::Running && !allowed(tcb, tcb.cpu) {
+            kick(&mut queues, tcb.cpu);
+        }
+        Ok(())
+    })
+}
+
+/// Code between `preempt_disable` and `preempt_enable` is never switched out
+/// by the timer, the calls nest
+pub fn preempt_disable() {
+    percpu::preempt_count_inc();
+}
+
+pub fn preempt_enable() {
+    let prev = percpu::preempt_count_dec();
+    assert!(prev > 0, "unbalanced preempt_enable");
+    if prev == 1 && percpu::need_resched() && asm::interrupts_enabled() {
+        reschedule(EnqueueReason::Preempted);
+    }
+}
+
+/// Interrupt handlers are never switched out, `preempt_on_interrupt_return`
+/// runs after `irq_exit`
+pub fn preemptible() -> bool {
+    percpu::preempt_count() == 0 && !percpu::in_interrupt()
+}
+
+pub struct PreemptGuard;
+
+impl PreemptGuard {
+    pub fn new() -> Self {
+        preempt_disable();
+        Self
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        preempt_enable();
+    }
+}
+
+pub fn last_tick() -> u64 {
+    LAST_TICK.load(Ordering::Acquire)
+}
+
+/// Timer tick, only the boot cpu gets one. Charges the thread running on
+/// every cpu for the ticks since the last one, which are more than one after
+/// tickless idle, and kicks the cpus whose slice ran out. Only the boot cpu
+/// knows whether the tick interrupted ring 3, the others are charged system
+/// time
+pub fn tick(now: u64, from_user: bool) {
+    locked(|| {
+        let this = percpu::cpu_id();
+        let elapsed = now.saturating_sub(LAST_TICK.swap(now, Ordering::AcqRel));
+        wake_expired(now);
+
+        let mut queues = RUN_QUEUES.take();
+        let mut threads = THREADS.take();
+        for cpu in 0..MAX_CPUS {
+            if !queues[cpu].online() {
+                continue;
+            }
+            policy_tick(&mut queues[cpu], cpu, &mut threads.threads, now);
+            let tcb = &mut threads.threads[percpu::cpu(cpu).current_thread()];
+            if cpu == this && from_user {
+                tcb.stats.user_ticks += elapsed;
+            } else {
+                tcb.stats.system_ticks += elapsed;
+            }
+            if is_idle(&queues, cpu) {
+                if !queues[cpu].is_empty() {
+                    kick(&mut queues, cpu);
+                }
+                continue;
+            }
+            let charged = u32::try_from(elapsed).unwrap_or(u32::MAX);
+            tcb.time_slice = tcb.time_slice.saturating_sub(charged);
+            if tcb.time_slice == 0 {
+                kick(&mut queues, cpu);
+            }
+        }
+        if now - LAST_BALANCE.load(Ordering::Acquire) >= BALANCE_INTERVAL_TICKS {
+            LAST_BALANCE.store(now, Ordering::Release);
+            balance(&mut queues, &mut threads.threads);
+        }
+    });
+}
+
+/// Runs the policy of `cpu` for one tick. A boost puts every thread of that
+/// cpu back on the top MLFQ level, queued or not
+fn policy_tick(rq: &mut RunQueue, cpu: usize, threads: &mut [TaskControlBlock], now: u64) {
+    if rq.policies.active().on_tick(now) {
+        for tcb in threads.iter_mut().filter(|tcb| tcb.cpu == cpu) {
+            tcb.mlfq_level = 0;
+        }
+    }
+}
+
+/// Called at the end of an interrupt handler, after the EOI, so that the
+/// interrupted thread is switched out when it used up its slice or a more
+/// important thread was woken
+pub fn preempt_on_interrupt_return() {
+    if percpu::need_resched() && preemptible() {
+        reschedule(EnqueueReason::Preempted);
+    }
+}
+
+/// Gives up the rest of the time slice
+pub fn yield_now() -> bool {
+    schedule()
+}
+
+/// Switches to the next thread the policy picks. The previous thread is
+/// queued again if it is still runnable. Returns false if it kept running
+pub fn schedule() -> bool {
+    reschedule(EnqueueReason::Yield)
+}
+
+fn reschedule(reason: EnqueueReason) -> bool {
+    locked(|| {
+        if !preemptible() {
+            percpu::set_need_resched(true);
+            return false;
+        }
+        percpu::set_need_resched(false);
+
+        let this = percpu::cpu_id();
+        let prev = current().id();
+        let mut threads = THREADS.take();
+        let mut queues = RUN_QUEUES.take();
+        let idle = queues[this].idle_tid.unwrap_or(prev);
+
+        // threads whose affinity changed while they were queued here
+        let mut displaced = [0usize; MAX_THREADS];
+        let mut displaced_len = 0;
+        let mut next = None;
+        while let Some(tid) = queues[this].pop() {
+            let tcb = &threads.threads[tid];
+            if tcb.state != ThreadState::Ready {
+                continue;
+            }
+            if allowed(tcb, this) {
+                next = Some(tid);
+                break;
+            }
+            displaced[displaced_len] = tid;
+            displaced_len += 1;
+        }
+        for &tid in &displaced[..displaced_len] {
+            let cpu = place(&mut queues, &mut threads.threads[tid], EnqueueReason::Yield);
+            if is_idle(&queues, cpu) {
+                kick(&mut queues, cpu);
+            }
+        }
+        if next.is_none() {
+            next = steal(&mut queues, &mut threads.threads, this);
+        }
+
+        let prev_runnable = threads.threads[prev].state == ThreadState::Running;
+        let next = match next {
+            Some(tid) => tid,
+            None if prev_runnable && allowed(&threads.threads[prev], this) => {
+                let tcb = &mut threads.threads[prev];
+                tcb.time_slice = queues[this].policies.active().quantum(tcb, quantum());
+                return false;
+            }
+            None => idle,
+        };
+
+        let now = Instant::now().0;
+        let tcb = &mut threads.threads[prev];
+        if prev_runnable && reason == EnqueueReason::Preempted {
+            tcb.stats.involuntary_switches += 1;
+        } else {
+            tcb.stats.voluntary_switches += 1;
+        }
+        if prev_runnable {
+            tcb.state = ThreadState::Ready;
+            tcb.stats.ready_since = now;
+            if prev != idle {
+                // it cannot be picked elsewhere before we are off its stack,
+                // the lock is held until the switch is done
+                let cpu = place(&mut queues, tcb, reason);
+                if cpu != this && is_idle(&queues, cpu) {
+                    kick(&mut queues, cpu);
+                }
+            }
+        }
+        let tcb = &mut threads.threads[next];
+        tcb.state = ThreadState::Running;
+        if next != idle {
+            tcb.stats.wait_ticks += now.saturating_sub(tcb.stats.ready_since);
+        }
+        tcb.stats.last_cpu = this;
+        tcb.cpu = this;
+        tcb.time_slice = queues[this].policies.active().quantum(tcb, quantum());
+        percpu::set_current_thread(next);
+        // interrupts and syscalls from ring 3 land on the top of its stack
+        gdt::tss(this).privilege_stack_table[0] = tcb.stack_top as u64;
+        percpu::set_kernel_stack(tcb.stack_top as usize);
+        paging::write_cr3(tcb.page_table());
+
+        let prev_ctx = &mut threads.threads[prev].context as *mut _;
+        let next_ctx = &threads.threads[next].context as *const _;
+        drop(queues);
+        drop(threads);
+        let depth = SCHED_LOCK.depth();
+        unsafe { switch_to(prev_ctx, next_ctx) };
+        SCHED_LOCK.resume(depth);
+
+        THREADS.take().reap_detached(current().id());
+        true
+    })
+}
+
+/// Makes `idle` the idle thread of the calling cpu and switches to it, the
+/// code that was running is abandoned. Used by application processors
+pub fn start_cpu(idle: usize) -> ! {
+    asm::disable_interrupts();
+    SCHED_LOCK.lock();
+    let cpu = percpu::cpu_id();
+    let next_ctx = {
+        let mut threads = THREADS.take();
+        let tcb = &mut threads.threads[idle];
+        tcb.state = ThreadState::Running;
+        tcb.cpu = cpu;
+        tcb.stats.last_cpu = cpu;
+        RUN_QUEUES.take()[cpu].idle_tid = Some(idle);
+        &tcb.context as *const _
+    };
+    percpu::set_current_thread(idle);
+    let mut boot = Context::default();
+    // the new thread drops the lock in `kthread_start`
+    unsafe { switch_to(&mut boot, next_ctx) };
+    unreachable!("switched back to the boot context of cpu {}", cpu);
+}
+
+/// Per cpu run queue and migration numbers
+pub fn dump() {
+    locked(|| {
+        let queues = RUN_QUEUES.take();
+        for (cpu, rq) in queues.iter().enumerate().filter(|(_, rq)| rq.online()) {
+            serial_info!(
+                "cpu {:>2} queued={} running={} stolen={} pulled={} pushed={} kicks={}",
+                cpu,
+                rq.nr_queued,
+                percpu::cpu(cpu).current_thread(),
+                rq.stats.stolen,
+                rq.stats.pulled,
+                rq.stats.pushed,
+                rq.stats.kicks
+            );
+        }
+    });
+}
+
+/// The boot cpu also keeps the timer going when it has nothing to run,
+/// the others just halt until an IPI or an interrupt shows up
+pub(super) fn idle_thread(cpu: usize) -> usize {
+    loop {
+        asm::disable_interrupts();
+        let has_work = locked(|| !RUN_QUEUES.take()[cpu].is_empty());
+        if has_work {
+            asm::enable_interrupts();
+        } else if cpu == 0 {
+            if let Some(deadline) = next_wakeup() {
+                time::request_wakeup(deadline);
+            }
+            time::idle_once();
+        } else {
+            asm::enable_interrupts_and_hlt();
+        }
+        schedule();
+    }
+}
+
+#[test_case]
+pub fn test_mlfq_boost() {
+    use super::policy::{DEFAULT_PRIORITY, MLFQ_BOOST_TICKS};
+
+    serial_info!("Testing the MLFQ boost");
+    let mut rq = RunQueue::new();
+    rq.policies.kind = PolicyKind::Mlfq;
+    let mut tcbs = [(); 2].map(|_| TaskControlBlock::empty(0));
+    tcbs[1].thread_id = 1;
+    tcbs[1].priority = DEFAULT_PRIORITY;
+    rq.push(&mut tcbs[1], EnqueueReason::New);
+    for _ in 0..3 {
+        assert_eq!(rq.pop(), Some(1));
+        rq.push(&mut tcbs[1], EnqueueReason::Preempted);
+    }
+    assert!(tcbs[1].mlfq_level > 1);
+    assert_eq!(rq.pop(), Some(1));
+
+    // the boost also reaches threads that are running, not only queued ones
+    policy_tick(&mut rq, 0, &mut tcbs, MLFQ_BOOST_TICKS);
+    assert_eq!(tcbs[1].mlfq_level, 0);
+    rq.push(&mut tcbs[1], EnqueueReason::Preempted);
+    assert_eq!(tcbs[1].mlfq_level, 1);
+    // no second boost before another full interval
+    policy_tick(&mut rq, 0, &mut tcbs, MLFQ_BOOST_TICKS + 1);
+    assert_eq!(tcbs[1].mlfq_level, 1);
+}
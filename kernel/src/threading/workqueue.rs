@@ -0,0 +1,421 @@
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use super::{
+    policy::{DEFAULT_PRIORITY, MAX_PRIORITY},
+    schedlock::locked,
+    scheduler,
+};
+use crate::{
+    datastructures::no_alloc::ringbuffer::RingBuf,
+    interrupts::timer::register_timer_callback,
+    io::time::{self, Instant},
+    serial_info,
+    sync::{shitlock::Racy, waitqueue::WaitQueue},
+};
+
+pub const MAX_WORKERS: usize = 4;
+const MAX_PENDING: usize = 64;
+const MAX_DELAYED: usize = 32;
+
+const PENDING: u8 = 1 << 0;
+const RUNNING: u8 = 1 << 1;
+
+/// Queue for anything that can sleep but is not urgent
+pub static SYSTEM_WQ: WorkQueue = WorkQueue::new("events", 2, DEFAULT_PRIORITY);
+/// Queue whose workers run ahead of normal threads
+pub static HIGHPRI_WQ: WorkQueue = WorkQueue::new("events_highpri", 1, MAX_PRIORITY);
+
+/// Threads in `flush_work` and `flush_workqueue`, woken after every item
+static FLUSHERS: WaitQueue = WaitQueue::new();
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    static ref DELAYED: Racy<[Option<&'static DelayedWork>; MAX_DELAYED]> =
+        Racy::from([None; MAX_DELAYED]);
+}
+
+/// A function to run on a worker thread. Items are statics owned by whoever
+/// queues them and sit in at most one queue at a time. An item queued again
+/// while it runs stays queued until that run is done, so it never runs on
+/// two workers at once
+pub struct WorkItem {
+    func: fn(usize),
+    arg: usize,
+    state: AtomicU8,
+    /// Queue the item was last put on
+    wq: AtomicPtr<WorkQueue>,
+}
+
+impl WorkItem {
+    pub const fn new(func: fn(usize), arg: usize) -> Self {
+        Self {
+            func,
+            arg,
+            state: AtomicU8::new(0),
+            wq: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.state.load(Ordering::Acquire) & PENDING != 0
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.load(Ordering::Acquire) & RUNNING != 0
+    }
+
+    fn is_idle(&self) -> bool {
+        self.state.load(Ordering::Acquire) == 0
+    }
+}
+
+/// Work item that is queued once a timer runs out
+pub struct DelayedWork {
+    pub work: WorkItem,
+    /// Tick the item is queued at, 0 while the timer is not armed
+    deadline: AtomicU64,
+    wq: AtomicPtr<WorkQueue>,
+}
+
+impl DelayedWork {
+    pub const fn new(func: fn(usize), arg: usize) -> Self {
+        Self {
+            work: WorkItem::new(func, arg),
+            deadline: AtomicU64::new(0),
+            wq: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.deadline.load(Ordering::Acquire) != 0
+    }
+}
+
+/// Pending items and the pool of kernel threads that run them. The items
+/// are pushed from interrupt handlers and other cpus too, so the queue is
+/// only touched with the scheduler lock held
+pub struct WorkQueue {
+    name: &'static str,
+    items: UnsafeCell<RingBuf<&'static WorkItem, MAX_PENDING>>,
+    /// Idle workers park here
+    more_work: WaitQueue,
+    workers: usize,
+    priority: u8,
+    /// Workers in the middle of running an item
+    busy: AtomicUsize,
+    started: AtomicBool,
+}
+
+unsafe impl Send for WorkQueue {}
+unsafe impl Sync for WorkQueue {}
+
+impl WorkQueue {
+    /// Nothing runs until `start`, items queued before that wait for it
+    pub const fn new(name: &'static str, workers: usize, priority: u8) -> Self {
+        Self {
+            name,
+            items: UnsafeCell::new(RingBuf::new()),
+            more_work: WaitQueue::new(),
+            workers,
+            priority,
+            busy: AtomicUsize::new(0),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Runs `f` on the pending items with the scheduler lock held, which
+    /// is what guards them. `f` must not touch this queue again
+    fn with_items<R>(
+        &self,
+        f: impl FnOnce(&mut RingBuf<&'static WorkItem, MAX_PENDING>) -> R,
+    ) -> R {
+        locked(|| f(unsafe { &mut *self.items.get() }))
+    }
+
+    /// Spawns the worker threads, needs threads to be up
+    pub fn start(&'static self) -> Result<(), &'static str> {
+        if self.started.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        for _ in 0..self.workers.clamp(1, MAX_WORKERS) {
+            let handle = super::spawn_named(self.name, worker, self as *const _ as usize)?;
+            scheduler::set_priority(handle.thread().id(), self.priority)?;
+        }
+        Ok(())
+    }
+
+    /// Nothing queued and no worker running an item
+    pub fn is_idle(&self) -> bool {
+        locked(|| self.with_items(|items| items.empty()) && self.busy.load(Ordering::Acquire) == 0)
+    }
+
+    /// Whether an item is queued that is not running already
+    fn has_runnable(&self) -> bool {
+        self.with_items(|items| items.iter().any(|item| !item.is_running()))
+    }
+
+    /// Pops the first item that is not running on another worker and marks
+    /// it running, the ones skipped keep their place
+    fn take_next(&self) -> Option<&'static WorkItem> {
+        locked(|| {
+            let item = self.with_items(|items| {
+                let mut found = None;
+                for _ in 0..items.len {
+                    let queued = items.take()?;
+                    if found.is_none() && !queued.is_running() {
+                        found = Some(queued);
+                    } else {
+                        let _ = items.push(queued);
+                    }
+                }
+                found
+            })?;
+            item.state.store(RUNNING, Ordering::Release);
+            self.busy.fetch_add(1, Ordering::AcqRel);
+            Some(item)
+        })
+    }
+
+    /// Drops `item` from the pending items, true if it was there
+    fn remove(&self, item: &WorkItem) -> bool {
+        self.with_items(|items| {
+            let mut found = false;
+            for _ in 0..items.len {
+                if let Some(queued) = items.take() {
+                    if core::ptr::eq(queued, item) {
+                        found = true;
+                    } else {
+                        let _ = items.push(queued);
+                    }
+                }
+            }
+            found
+        })
+    }
+}
+
+fn worker(arg: usize) -> usize {
+    let wq = unsafe { &*(arg as *const WorkQueue) };
+    loop {
+        wq.more_work.wait(|| wq.has_runnable());
+        let Some(item) = wq.take_next() else {
+            // another worker got to it first
+            continue;
+        };
+        (item.func)(item.arg);
+        locked(|| {
+            item.state.fetch_and(!RUNNING, Ordering::AcqRel);
+            wq.busy.fetch_sub(1, Ordering::AcqRel);
+            // a run of the item that was held back can go now
+            if wq.has_runnable() {
+                wq.more_work.wake_one();
+            }
+            FLUSHERS.wake_all();
+        });
+    }
+}
+
+/// Queues `item` on `wq`. Returns Ok(false) if it was already pending.
+/// Safe to call from interrupt handlers
+pub fn queue_work(wq: &'static WorkQueue, item: &'static WorkItem) -> Result<bool, &'static str> {
+    locked(|| {
+        if item.is_pending() {
+            return Ok(false);
+        }
+        wq.with_items(|items| items.push(item))
+            .map_err(|_| "work queue full")?;
+        item.state.fetch_or(PENDING, Ordering::AcqRel);
+        item.wq
+            .store(wq as *const _ as *mut WorkQueue, Ordering::Release);
+        wq.more_work.wake_one();
+        Ok(true)
+    })
+}
+
+/// Queues `dwork` on `wq` once `delay` has passed. Returns Ok(false) if it
+/// is already waiting for its timer or pending
+pub fn queue_delayed_work(
+    wq: &'static WorkQueue,
+    dwork: &'static DelayedWork,
+    delay: Duration,
+) -> Result<bool, &'static str> {
+    let deadline = Instant::now() + delay;
+    if deadline.has_passed() {
+        return queue_work(wq, &dwork.work);
+    }
+    locked(|| {
+        if dwork.is_armed() || dwork.work.is_pending() {
+            return Ok(false);
+        }
+        let mut delayed = DELAYED.take();
+        let slot = delayed
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or("too many delayed work items")?;
+        *slot = Some(dwork);
+        dwork
+            .wq
+            .store(wq as *const _ as *mut WorkQueue, Ordering::Release);
+        dwork.deadline.store(deadline.0, Ordering::Release);
+        time::request_wakeup(deadline);
+        Ok(true)
+    })
+}
+
+/// Timer callback, queues the delayed items whose deadline passed
+fn run_delayed(now: u64) {
+    locked(|| run_due(now));
+}
+
+fn run_due(now: u64) {
+    for slot in DELAYED.take().iter_mut() {
+        let due = slot.is_some_and(|dwork| dwork.deadline.load(Ordering::Acquire) <= now);
+        if !due {
+            continue;
+        }
+        if let Some(dwork) = slot.take() {
+            dwork.deadline.store(0, Ordering::Release);
+            let wq = unsafe { &*dwork.wq.load(Ordering::Acquire) };
+            if let Err(err) = queue_work(wq, &dwork.work) {
+                serial_info!("dropping delayed work on {}: {}", wq.name, err);
+            }
+        }
+    }
+}
+
+/// Takes `item` off its queue if it has not started yet. Returns whether it
+/// was pending. A run that already started is left to finish
+pub fn cancel_work(item: &WorkItem) -> bool {
+    locked(|| {
+        if !item.is_pending() {
+            return false;
+        }
+        let wq = unsafe { &*item.wq.load(Ordering::Acquire) };
+        wq.remove(item);
+        item.state.fetch_and(!PENDING, Ordering::AcqRel);
+        true
+    })
+}
+
+/// Like `cancel_work` but also waits for a run in progress to finish
+pub fn cancel_work_sync(item: &WorkItem) -> bool {
+    let pending = cancel_work(item);
+    flush_work(item);
+    pending
+}
+
+/// Stops the timer of `dwork` or takes it off its queue. Returns whether
+/// it was armed or pending
+pub fn cancel_delayed_work(dwork: &DelayedWork) -> bool {
+    let disarmed = locked(|| {
+        let mut delayed = DELAYED.take();
+        let Some(slot) = delayed
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|armed| core::ptr::eq(armed, dwork)))
+        else {
+            return false;
+        };
+        *slot = None;
+        dwork.deadline.store(0, Ordering::Release);
+        true
+    });
+    disarmed | cancel_work(&dwork.work)
+}
+
+/// Waits until `item` is neither pending nor running. Must not be called
+/// from the item itself
+pub fn flush_work(item: &WorkItem) {
+    FLUSHERS.wait(|| item.is_idle());
+}
+
+/// Waits until `wq` has run everything queued on it and has nothing new.
+/// Must not be called from one of its own workers
+pub fn flush_workqueue(wq: &WorkQueue) {
+    FLUSHERS.wait(|| wq.is_idle());
+}
+
+/// Starts the system queues and the delayed work timer
+pub fn init() -> Result<(), &'static str> {
+    if INITIALIZED.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+    register_timer_callback(run_delayed)?;
+    SYSTEM_WQ.start()?;
+    HIGHPRI_WQ.start()
+}
+
+pub fn schedule_work(item: &'static WorkItem) -> Result<bool, &'static str> {
+    queue_work(&SYSTEM_WQ, item)
+}
+
+pub fn schedule_delayed_work(
+    dwork: &'static DelayedWork,
+    delay: Duration,
+) -> Result<bool, &'static str> {
+    queue_delayed_work(&SYSTEM_WQ, dwork, delay)
+}
+
+#[test_case]
+pub fn test_workqueue() {
+    serial_info!("Testing work queues");
+    super::init();
+    static TEST_WQ: WorkQueue = WorkQueue::new("test_wq", 1, DEFAULT_PRIORITY);
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn count(arg: usize) {
+        RUNS.fetch_add(arg, Ordering::AcqRel);
+    }
+    static ADD_ONE: WorkItem = WorkItem::new(count, 1);
+    static ADD_TEN: WorkItem = WorkItem::new(count, 10);
+
+    // nothing runs before the workers are started
+    assert_eq!(queue_work(&TEST_WQ, &ADD_ONE), Ok(true));
+    assert_eq!(queue_work(&TEST_WQ, &ADD_ONE), Ok(false));
+    assert_eq!(queue_work(&TEST_WQ, &ADD_TEN), Ok(true));
+    assert!(cancel_work(&ADD_TEN));
+    assert!(!ADD_TEN.is_pending());
+
+    TEST_WQ.start().unwrap();
+    flush_workqueue(&TEST_WQ);
+    assert_eq!(RUNS.load(Ordering::Acquire), 1);
+    assert_eq!(queue_work(&TEST_WQ, &ADD_TEN), Ok(true));
+    flush_work(&ADD_TEN);
+    assert_eq!(RUNS.load(Ordering::Acquire), 11);
+}
+
+#[test_case]
+pub fn test_work_not_reentrant() {
+    serial_info!("Testing that a work item runs on one worker at a time");
+    super::init();
+    static TWO_WQ: WorkQueue = WorkQueue::new("test_wq2", 2, DEFAULT_PRIORITY);
+    static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+    static OVERLAPS: AtomicUsize = AtomicUsize::new(0);
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    static AGAIN: WorkItem = WorkItem::new(requeue, 0);
+    fn requeue(_: usize) {
+        if ACTIVE.fetch_add(1, Ordering::AcqRel) > 0 {
+            OVERLAPS.fetch_add(1, Ordering::AcqRel);
+        }
+        if RUNS.fetch_add(1, Ordering::AcqRel) == 0 {
+            assert_eq!(queue_work(&TWO_WQ, &AGAIN), Ok(true));
+            // give the other worker every chance to pick it up
+            for _ in 0..10 {
+                scheduler::yield_now();
+            }
+        }
+        ACTIVE.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    TWO_WQ.start().unwrap();
+    assert_eq!(queue_work(&TWO_WQ, &AGAIN), Ok(true));
+    flush_work(&AGAIN);
+    assert_eq!(RUNS.load(Ordering::Acquire), 2);
+    assert_eq!(OVERLAPS.load(Ordering::Acquire), 0);
+}
@@ -57,6 +57,79 @@ pub fn enable_interrupts() {
     unsafe { asm!("sti") }
 }
 
+/// Halts until the next interrupt arrives
+pub fn hlt() {
+    unsafe { asm!("hlt", options(nomem, nostack)) }
+}
+
+/// `sti` only takes effect after the following instruction so an interrupt
+/// cannot slip in between enabling and halting and be lost
+pub fn enable_interrupts_and_hlt() {
+    unsafe { asm!("sti", "hlt", options(nomem, nostack)) }
+}
+
+pub fn interrupts_enabled() -> bool {
+    let flags: u64;
+    unsafe { asm!("pushfq", "pop {}", out(reg) flags, options(nomem, preserves_flags)) };
+    flags & (1 << 9) != 0
+}
+
+/// Runs `f` with interrupts disabled and restores the previous state
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = interrupts_enabled();
+    disable_interrupts();
+    let ret = f();
+    if enabled {
+        enable_interrupts();
+    }
+    ret
+}
+
+/// Address of the last page fault
+pub fn read_cr2() -> u64 {
+    let cr2: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+    cr2
+}
+
+/// Time stamp counter
+pub fn read_tsc() -> u64 {
+    let (lo, hi): (u32, u32);
+    unsafe { asm!("rdtsc", out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags)) };
+    (hi as u64) << 32 | lo as u64
+}
+
+pub fn read_cr0() -> u64 {
+    let cr0: u64;
+    unsafe { asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags)) };
+    cr0
+}
+
+pub unsafe fn write_cr0(cr0: u64) {
+    asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags));
+}
+
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (lo, hi): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") lo, out("edx") hi, options(nomem, nostack, preserves_flags));
+    (hi as u64) << 32 | lo as u64
+}
+
+pub unsafe fn wrmsr(msr: u32, val: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") val as u32,
+        in("edx") (val >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
+
+/// Exchanges `IA32_GS_BASE` with `IA32_KERNEL_GS_BASE`
+pub unsafe fn swapgs() {
+    asm!("swapgs", options(nostack, preserves_flags));
+}
+
 #[allow(dead_code)]
 pub unsafe fn lgdt(gdt_p: &GdtPointer) {
     asm!("lgdt [{}]", in(reg) gdt_p, options(readonly, nostack, preserves_flags));
@@ -14,20 +14,46 @@ use alloc::vec::Vec;
 
 use bootloader::BootInfo;
 // extern crate alloc;
-use kernel::{interrupts::timer::PIT_, io::reader::READER, *};
+use kernel::{interrupts::timer::PIT_, *};
 
 bootloader::entry_point!(kernel_main);
 
 
 pub fn kernel_main(bootinfo: &'static BootInfo) -> ! {
 
+    cpu::percpu::init_bsp();
     setup_boot_info(bootinfo);
     kprint!("\n\n");
+    threading::init();
     utils::asm::disable_interrupts(); // this fails if no handler is installed
+    descriptors::gdt::init_cpu(0);
+    syscall::entry::init_cpu();
     PIT_.setup(10);
     interrupts::setup::interrupt_setup();
     utils::asm::enable_interrupts(); // this fails if no handler is installed
+    devices::speaker::setup();
     discover_pages();
+    match cpu::smp::start_aps() {
+        Ok(cpus) => serial_info!("{} cpus online", cpus),
+        Err(err) => serial_info!("running on the boot cpu only: {}", err),
+    }
+    task::setup();
+    threading::workqueue::init().expect("unable to start the work queues");
+    process::init().expect("unable to start init");
+    // the async drivers are fed by interrupts that only the boot cpu takes
+    threading::spawn_with(
+        "executor",
+        |_| {
+            let mut executor = task::Executor::new();
+            executor
+                .spawn(task::Task::named("serial-echo", task::serial::echo()))
+                .expect("unable to spawn the serial echo task");
+            executor.run()
+        },
+        0,
+        |tcb| tcb.affinity = cpu::cpu_mask(0),
+    )
+    .expect("unable to start the executor thread");
     
     let mut x: Vec<i32, _> = Vec::new();
     for i in 1..10000 {
@@ -35,7 +61,5 @@ pub fn kernel_main(bootinfo: &'static BootInfo) -> ! {
     }
 
     // WRITER.take().display.clear();
-    loop {
-        READER.take().input.process_buf_wait();
-    }
+    shell::run();
 }
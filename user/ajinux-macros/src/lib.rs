@@ -0,0 +1,30 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, ItemFn};
+
+/// Marks the function a program starts in. It takes no arguments and
+/// returns anything that implements `ajinux::Termination`
+#[proc_macro_attribute]
+pub fn entry(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(proc_macro2::Span::call_site(), "entry takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let main = parse_macro_input!(item as ItemFn);
+    if !main.sig.inputs.is_empty() {
+        return syn::Error::new_spanned(&main.sig.inputs, "the entry function takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let name = &main.sig.ident;
+    quote! {
+        #main
+
+        #[export_name = "__ajinux_main"]
+        fn __ajinux_main() -> i32 {
+            ::ajinux::Termination::report(#name())
+        }
+    }
+    .into()
+}
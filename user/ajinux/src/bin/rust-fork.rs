@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+use ajinux::{
+    entry, println,
+    process::{self, ExitStatus, Fork},
+    Errno,
+};
+
+#[entry]
+fn main() -> Result<(), Errno> {
+    let mut value = 1;
+    match process::fork()? {
+        Fork::Child => {
+            value = 2;
+            println!("child {}: value {}", process::getpid(), value);
+            process::exit(7);
+        }
+        Fork::Parent { child } => {
+            let (pid, status) = process::wait()?;
+            println!("parent: child {} ended with {:?}", pid, status);
+            assert_eq!(pid, child);
+            assert_eq!(status, ExitStatus::Exited(7));
+            assert_eq!(value, 1, "the child wrote to our memory");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use ajinux::{entry, env, println, process};
+
+#[entry]
+fn main() -> i32 {
+    println!(
+        "hello from Rust, pid {} parent {}",
+        process::getpid(),
+        process::getppid()
+    );
+    for (i, arg) in env::args().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    let squares: Vec<u64> = (1..=10).map(|n| n * n).collect();
+    let mut line = String::new();
+    for square in &squares {
+        line.push_str(&alloc::format!("{} ", square));
+    }
+    println!("squares: {}", line.trim_end());
+    if squares.iter().sum::<u64>() == 385 {
+        0
+    } else {
+        1
+    }
+}
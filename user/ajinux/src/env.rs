@@ -0,0 +1,47 @@
+//! The command line and environment the process was started with
+use core::{
+    ffi::CStr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Called once by `_start` with what the kernel put on the stack
+pub(crate) unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv as *mut _, Ordering::Relaxed);
+    ENVP.store(envp as *mut _, Ordering::Relaxed);
+}
+
+/// Strings of a NULL terminated pointer array, invalid UTF-8 is skipped
+fn strings(array: *const *const u8) -> impl Iterator<Item = &'static str> {
+    let len = if array.is_null() {
+        0
+    } else {
+        (0..)
+            .take_while(|&i| unsafe { !(*array.add(i)).is_null() })
+            .count()
+    };
+    (0..len).filter_map(move |i| {
+        unsafe { CStr::from_ptr((*array.add(i)).cast()) }
+            .to_str()
+            .ok()
+    })
+}
+
+/// The arguments, the program name first
+pub fn args() -> impl Iterator<Item = &'static str> {
+    let argv = ARGV.load(Ordering::Relaxed);
+    strings(argv).take(ARGC.load(Ordering::Relaxed))
+}
+
+/// `NAME=value` pairs split at the first `=`
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    strings(ENVP.load(Ordering::Relaxed)).map(|var| var.split_once('=').unwrap_or((var, "")))
+}
+
+pub fn var(name: &str) -> Option<&'static str> {
+    vars().find(|&(key, _)| key == name).map(|(_, value)| value)
+}
@@ -0,0 +1,107 @@
+//! The global allocator. Memory comes from moving the break, freed blocks
+//! go on a free list by size and are handed out again first-fit. Processes
+//! have one thread, so there is no locking
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    ptr,
+};
+
+use crate::syscall;
+
+/// The break is moved at least this far at a time
+const GROWTH: usize = 16 * 1024;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+struct Heap {
+    /// Start of the memory not handed out yet
+    top: usize,
+    /// The break, `top..end` is mapped but unused
+    end: usize,
+    free: *mut FreeBlock,
+}
+
+pub struct BrkAllocator(UnsafeCell<Heap>);
+
+unsafe impl Sync for BrkAllocator {}
+
+#[global_allocator]
+static ALLOCATOR: BrkAllocator = BrkAllocator(UnsafeCell::new(Heap {
+    top: 0,
+    end: 0,
+    free: ptr::null_mut(),
+}));
+
+/// Blocks are big enough and aligned to hold a `FreeBlock` once freed
+fn block_layout(layout: Layout) -> Option<(usize, usize)> {
+    let layout = layout
+        .align_to(align_of::<FreeBlock>())
+        .ok()?
+        .pad_to_align();
+    Some((layout.size().max(size_of::<FreeBlock>()), layout.align()))
+}
+
+impl Heap {
+    unsafe fn take_free(&mut self, size: usize, align: usize) -> *mut u8 {
+        let mut link = &mut self.free as *mut *mut FreeBlock;
+        while !(*link).is_null() {
+            let block = *link;
+            if (*block).size == size && (block as usize).is_multiple_of(align) {
+                *link = (*block).next;
+                return block.cast();
+            }
+            link = &mut (*block).next;
+        }
+        ptr::null_mut()
+    }
+
+    unsafe fn bump(&mut self, size: usize, align: usize) -> *mut u8 {
+        if self.end == 0 {
+            self.top = syscall::brk(0);
+            self.end = self.top;
+        }
+        let start = self.top.next_multiple_of(align);
+        let Some(new_top) = start.checked_add(size) else {
+            return ptr::null_mut();
+        };
+        if new_top > self.end {
+            let want = new_top.max(self.end + GROWTH);
+            let got = syscall::brk(want);
+            if got < new_top {
+                return ptr::null_mut();
+            }
+            self.end = got;
+        }
+        self.top = new_top;
+        start as *mut u8
+    }
+}
+
+unsafe impl GlobalAlloc for BrkAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((size, align)) = block_layout(layout) else {
+            return ptr::null_mut();
+        };
+        let heap = &mut *self.0.get();
+        let block = heap.take_free(size, align);
+        if !block.is_null() {
+            return block;
+        }
+        heap.bump(size, align)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout).expect("layout was allocated");
+        let heap = &mut *self.0.get();
+        let block = ptr.cast::<FreeBlock>();
+        block.write(FreeBlock {
+            size,
+            next: heap.free,
+        });
+        heap.free = block;
+    }
+}
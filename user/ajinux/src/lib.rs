@@ -0,0 +1,79 @@
+//! Runtime for ajinux user programs written in Rust: the process entry
+//! point, typed syscalls, `print!` and a heap over `brk`
+#![no_std]
+
+extern crate alloc;
+
+pub mod env;
+mod heap;
+pub mod io;
+pub mod process;
+pub mod syscall;
+
+use core::{arch::global_asm, fmt::Debug, panic::PanicInfo};
+
+pub use ajinux_macros::entry;
+pub use syscall::Errno;
+
+// The kernel starts a process with rsp pointing at argc, followed by the
+// argv and envp arrays, each ended by a NULL pointer
+global_asm!(
+    r#"
+.global _start
+_start:
+    xor ebp, ebp
+    mov rdi, [rsp]
+    lea rsi, [rsp + 8]
+    lea rdx, [rsp + 8 * rdi + 16]
+    and rsp, -16
+    call {start}
+    ud2
+"#,
+    start = sym start,
+);
+
+extern "Rust" {
+    /// Generated by `#[entry]`
+    fn __ajinux_main() -> i32;
+}
+
+extern "C" fn start(argc: usize, argv: *const *const u8, envp: *const *const u8) -> ! {
+    unsafe { env::init(argc, argv, envp) };
+    let code = unsafe { __ajinux_main() };
+    process::exit(code)
+}
+
+/// What the entry function returns, turned into the exit status
+pub trait Termination {
+    fn report(self) -> i32;
+}
+
+impl Termination for () {
+    fn report(self) -> i32 {
+        0
+    }
+}
+
+impl Termination for i32 {
+    fn report(self) -> i32 {
+        self
+    }
+}
+
+impl<E: Debug> Termination for Result<(), E> {
+    fn report(self) -> i32 {
+        match self {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("Error: {:?}", err);
+                1
+            }
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    eprintln!("{}", info);
+    process::exit(101)
+}
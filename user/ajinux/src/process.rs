@@ -0,0 +1,54 @@
+//! Process control on top of the raw syscalls
+use crate::syscall::{self, Result};
+
+/// `waitpid` option to return None instead of blocking
+pub const WNOHANG: u32 = 1;
+
+pub use syscall::{getpid, getppid, kill, sched_yield, sleep_ms};
+
+pub fn exit(code: i32) -> ! {
+    syscall::exit(code)
+}
+
+pub enum Fork {
+    Parent { child: usize },
+    Child,
+}
+
+pub fn fork() -> Result<Fork> {
+    Ok(match syscall::fork()? {
+        0 => Fork::Child,
+        child => Fork::Parent { child },
+    })
+}
+
+/// How a child ended, decoded from the `waitpid` status word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(i32),
+}
+
+impl ExitStatus {
+    pub fn from_raw(status: i32) -> Self {
+        match status & 0x7f {
+            0 => Self::Exited((status >> 8) & 0xff),
+            signal => Self::Signaled(signal),
+        }
+    }
+}
+
+/// Waits for `pid`, or any child if it is None. None with `WNOHANG` if no
+/// child has ended yet
+pub fn waitpid(pid: Option<usize>, options: u32) -> Result<Option<(usize, ExitStatus)>> {
+    let mut status = 0;
+    let pid = pid.map_or(-1, |pid| pid as isize);
+    match syscall::waitpid(pid, Some(&mut status), options)? {
+        0 => Ok(None),
+        pid => Ok(Some((pid, ExitStatus::from_raw(status)))),
+    }
+}
+
+pub fn wait() -> Result<(usize, ExitStatus)> {
+    Ok(waitpid(None, 0)?.expect("blocking wait returned nothing"))
+}
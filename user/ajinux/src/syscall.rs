@@ -0,0 +1,200 @@
+//! Raw system calls. The numbers and the register convention match
+//! kernel/src/syscall/mod.rs
+use core::{arch::asm, fmt};
+
+pub const SYS_READ: usize = 0;
+pub const SYS_WRITE: usize = 1;
+pub const SYS_EXIT: usize = 2;
+pub const SYS_GETPID: usize = 3;
+pub const SYS_YIELD: usize = 4;
+pub const SYS_SLEEP: usize = 5;
+pub const SYS_FORK: usize = 6;
+pub const SYS_EXECVE: usize = 7;
+pub const SYS_WAITPID: usize = 8;
+pub const SYS_GETPPID: usize = 9;
+pub const SYS_BRK: usize = 10;
+pub const SYS_MMAP: usize = 11;
+pub const SYS_MUNMAP: usize = 12;
+pub const SYS_KILL: usize = 13;
+pub const SYS_SIGACTION: usize = 14;
+pub const SYS_SIGPROCMASK: usize = 15;
+pub const SYS_SIGRETURN: usize = 16;
+pub const SYS_DUP: usize = 17;
+pub const SYS_DUP2: usize = 18;
+pub const SYS_CLOSE: usize = 19;
+pub const SYS_FCNTL: usize = 20;
+pub const SYS_PIPE: usize = 21;
+
+/// An error number the kernel returned
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Errno(pub i32);
+
+impl Errno {
+    pub const ENOENT: Self = Self(2);
+    pub const ESRCH: Self = Self(3);
+    pub const EINTR: Self = Self(4);
+    pub const EBADF: Self = Self(9);
+    pub const ECHILD: Self = Self(10);
+    pub const EAGAIN: Self = Self(11);
+    pub const ENOMEM: Self = Self(12);
+    pub const EFAULT: Self = Self(14);
+    pub const EINVAL: Self = Self(22);
+    pub const EMFILE: Self = Self(24);
+    pub const EPIPE: Self = Self(32);
+    pub const ENOSYS: Self = Self(38);
+}
+
+impl fmt::Debug for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "errno {}", self.0)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Errno>;
+
+/// Makes system call `nr`, the kernel hands back a value or `-errno`
+///
+/// # Safety
+/// Pointer arguments have to be valid for what the call does with them
+pub unsafe fn syscall6(nr: usize, args: [usize; 6]) -> Result<usize> {
+    let ret: isize;
+    asm!(
+        "syscall",
+        inlateout("rax") nr as isize => ret,
+        in("rdi") args[0],
+        in("rsi") args[1],
+        in("rdx") args[2],
+        in("r10") args[3],
+        in("r8") args[4],
+        in("r9") args[5],
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    if (-4095..0).contains(&ret) {
+        Err(Errno(-ret as i32))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// # Safety
+/// See `syscall6`
+pub unsafe fn syscall3(nr: usize, a: usize, b: usize, c: usize) -> Result<usize> {
+    syscall6(nr, [a, b, c, 0, 0, 0])
+}
+
+pub fn read(fd: i32, buf: &mut [u8]) -> Result<usize> {
+    unsafe { syscall3(SYS_READ, fd as usize, buf.as_mut_ptr() as usize, buf.len()) }
+}
+
+pub fn write(fd: i32, buf: &[u8]) -> Result<usize> {
+    unsafe { syscall3(SYS_WRITE, fd as usize, buf.as_ptr() as usize, buf.len()) }
+}
+
+pub fn exit(code: i32) -> ! {
+    let _ = unsafe { syscall3(SYS_EXIT, code as usize, 0, 0) };
+    unreachable!("exit returned")
+}
+
+pub fn getpid() -> usize {
+    unsafe { syscall3(SYS_GETPID, 0, 0, 0) }.expect("getpid failed")
+}
+
+pub fn getppid() -> usize {
+    unsafe { syscall3(SYS_GETPPID, 0, 0, 0) }.expect("getppid failed")
+}
+
+pub fn sched_yield() {
+    let _ = unsafe { syscall3(SYS_YIELD, 0, 0, 0) };
+}
+
+pub fn sleep_ms(ms: u32) {
+    let _ = unsafe { syscall3(SYS_SLEEP, ms as usize, 0, 0) };
+}
+
+/// 0 in the child, the pid of the child in the parent
+pub fn fork() -> Result<usize> {
+    unsafe { syscall3(SYS_FORK, 0, 0, 0) }
+}
+
+/// `path` has to end with a NUL, the pointer arrays with a null pointer.
+/// Only comes back on failure
+pub fn execve(path: &[u8], argv: &[*const u8], envp: &[*const u8]) -> Result<usize> {
+    if path.last() != Some(&0) || argv.last() != Some(&core::ptr::null()) {
+        return Err(Errno::EINVAL);
+    }
+    if envp.last() != Some(&core::ptr::null()) {
+        return Err(Errno::EINVAL);
+    }
+    unsafe {
+        syscall3(
+            SYS_EXECVE,
+            path.as_ptr() as usize,
+            argv.as_ptr() as usize,
+            envp.as_ptr() as usize,
+        )
+    }
+}
+
+pub fn waitpid(pid: isize, status: Option<&mut i32>, options: u32) -> Result<usize> {
+    let status = status.map_or(0, |status| status as *mut i32 as usize);
+    unsafe { syscall3(SYS_WAITPID, pid as usize, status, options as usize) }
+}
+
+/// Sends signal `sig` to process `pid`, 0 only checks that it exists
+pub fn kill(pid: usize, sig: i32) -> Result<()> {
+    unsafe { syscall3(SYS_KILL, pid, sig as usize, 0) }.map(|_| ())
+}
+
+pub fn dup(fd: i32) -> Result<i32> {
+    unsafe { syscall3(SYS_DUP, fd as usize, 0, 0) }.map(|fd| fd as i32)
+}
+
+/// Makes `new` refer to what `old` does, closing `new` first
+pub fn dup2(old: i32, new: i32) -> Result<i32> {
+    unsafe { syscall3(SYS_DUP2, old as usize, new as usize, 0) }.map(|fd| fd as i32)
+}
+
+pub fn close(fd: i32) -> Result<()> {
+    unsafe { syscall3(SYS_CLOSE, fd as usize, 0, 0) }.map(|_| ())
+}
+
+pub const F_GETFD: u32 = 1;
+pub const F_SETFD: u32 = 2;
+pub const FD_CLOEXEC: usize = 1;
+
+pub fn fcntl(fd: i32, cmd: u32, arg: usize) -> Result<usize> {
+    unsafe { syscall3(SYS_FCNTL, fd as usize, cmd as usize, arg) }
+}
+
+/// A new pipe as (read end, write end)
+pub fn pipe() -> Result<(i32, i32)> {
+    let mut fds = [0i32; 2];
+    unsafe { syscall3(SYS_PIPE, fds.as_mut_ptr() as usize, 0, 0) }?;
+    Ok((fds[0], fds[1]))
+}
+
+/// Moves the end of the heap, returns where it ended up. 0 only asks
+pub fn brk(addr: usize) -> usize {
+    unsafe { syscall3(SYS_BRK, addr, 0, 0) }.unwrap_or(0)
+}
+
+pub const PROT_READ: u32 = 1;
+pub const PROT_WRITE: u32 = 2;
+pub const PROT_EXEC: u32 = 4;
+pub const MAP_PRIVATE: u32 = 0x02;
+pub const MAP_ANONYMOUS: u32 = 0x20;
+
+/// Maps `len` bytes of fresh private memory
+pub fn mmap_anonymous(len: usize, prot: u32) -> Result<*mut u8> {
+    let flags = (MAP_PRIVATE | MAP_ANONYMOUS) as usize;
+    let addr = unsafe { syscall6(SYS_MMAP, [0, len, prot as usize, flags, usize::MAX, 0]) }?;
+    Ok(addr as *mut u8)
+}
+
+/// # Safety
+/// Nothing may use the memory afterwards
+pub unsafe fn munmap(addr: *mut u8, len: usize) -> Result<()> {
+    syscall3(SYS_MUNMAP, addr as usize, len, 0).map(|_| ())
+}
@@ -0,0 +1,12 @@
+#ifndef _FCNTL_H
+#define _FCNTL_H
+
+#define F_DUPFD 0
+#define F_GETFD 1
+#define F_SETFD 2
+
+#define FD_CLOEXEC 1
+
+int fcntl(int fd, int cmd, ...);
+
+#endif
@@ -0,0 +1,94 @@
+#include <errno.h>
+#include <signal.h>
+#include <syscall.h>
+#include <unistd.h>
+
+#define STR(x) #x
+#define XSTR(x) STR(x)
+
+/* Handlers return here, sigreturn puts back what the signal interrupted */
+void __restore_rt(void);
+__asm__(".text\n"
+        ".global __restore_rt\n"
+        "__restore_rt:\n"
+        "    mov $" XSTR(SYS_sigreturn) ", %eax\n"
+        "    syscall\n");
+
+int kill(pid_t pid, int sig)
+{
+    return __syscall_ret(__syscall2(SYS_kill, pid, sig));
+}
+
+int raise(int sig)
+{
+    return kill(getpid(), sig);
+}
+
+int sigaction(int sig, const struct sigaction *act, struct sigaction *oldact)
+{
+    struct sigaction kact;
+    if (act) {
+        kact = *act;
+        kact.sa_flags |= SA_RESTORER;
+        kact.sa_restorer = __restore_rt;
+        act = &kact;
+    }
+    return __syscall_ret(__syscall3(SYS_sigaction, sig, act, oldact));
+}
+
+sighandler_t signal(int sig, sighandler_t handler)
+{
+    struct sigaction act = { .sa_handler = handler }, old;
+    if (sigaction(sig, &act, &old) < 0)
+        return SIG_ERR;
+    return old.sa_handler;
+}
+
+int sigprocmask(int how, const sigset_t *set, sigset_t *oldset)
+{
+    return __syscall_ret(__syscall3(SYS_sigprocmask, how, set, oldset));
+}
+
+static int valid(int sig)
+{
+    if (sig < 1 || sig >= NSIG) {
+        errno = EINVAL;
+        return 0;
+    }
+    return 1;
+}
+
+int sigemptyset(sigset_t *set)
+{
+    *set = 0;
+    return 0;
+}
+
+int sigfillset(sigset_t *set)
+{
+    *set = ~0UL;
+    return 0;
+}
+
+int sigaddset(sigset_t *set, int sig)
+{
+    if (!valid(sig))
+        return -1;
+    *set |= 1UL << (sig - 1);
+    return 0;
+}
+
+int sigdelset(sigset_t *set, int sig)
+{
+    if (!valid(sig))
+        return -1;
+    *set &= ~(1UL << (sig - 1));
+    return 0;
+}
+
+int sigismember(const sigset_t *set, int sig)
+{
+    if (!valid(sig))
+        return -1;
+    return (*set >> (sig - 1)) & 1;
+}
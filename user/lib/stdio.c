@@ -0,0 +1,398 @@
+#include <errno.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define F_READ 1
+#define F_WRITE 2
+/* Flush at every newline */
+#define F_LINE 4
+#define F_EOF 8
+#define F_ERROR 16
+/* Allocated by fdopen, freed by fclose */
+#define F_ALLOCATED 32
+
+struct FILE {
+    int fd;
+    int flags;
+    /* Buffered output waiting for a write, or input not handed out yet */
+    size_t len;
+    size_t pos;
+    unsigned char buf[BUFSIZ];
+};
+
+static FILE stdin_file = {.fd = STDIN_FILENO, .flags = F_READ};
+static FILE stdout_file = {.fd = STDOUT_FILENO, .flags = F_WRITE | F_LINE};
+static FILE stderr_file = {.fd = STDERR_FILENO, .flags = F_WRITE | F_LINE};
+
+FILE *stdin = &stdin_file;
+FILE *stdout = &stdout_file;
+FILE *stderr = &stderr_file;
+
+FILE *fdopen(int fd, const char *mode)
+{
+    int flags;
+    if (mode[0] == 'r')
+        flags = mode[1] == '+' ? F_READ | F_WRITE : F_READ;
+    else if (mode[0] == 'w' || mode[0] == 'a')
+        flags = mode[1] == '+' ? F_READ | F_WRITE : F_WRITE;
+    else {
+        errno = EINVAL;
+        return NULL;
+    }
+    FILE *f = calloc(1, sizeof(FILE));
+    if (!f)
+        return NULL;
+    f->fd = fd;
+    f->flags = flags | F_ALLOCATED;
+    return f;
+}
+
+int fclose(FILE *f)
+{
+    int ret = fflush(f);
+    if (f->flags & F_ALLOCATED)
+        free(f);
+    return ret;
+}
+
+int fileno(FILE *f)
+{
+    return f->fd;
+}
+
+int fflush(FILE *f)
+{
+    if (!(f->flags & F_WRITE))
+        return 0;
+    size_t done = 0;
+    while (done < f->len) {
+        ssize_t n = write(f->fd, f->buf + done, f->len - done);
+        if (n <= 0) {
+            f->flags |= F_ERROR;
+            f->len = 0;
+            return EOF;
+        }
+        done += n;
+    }
+    f->len = 0;
+    return 0;
+}
+
+size_t fwrite(const void *buf, size_t size, size_t count, FILE *f)
+{
+    const unsigned char *bytes = buf;
+    size_t total = size * count;
+    int flush = 0;
+    if (!(f->flags & F_WRITE)) {
+        f->flags |= F_ERROR;
+        errno = EBADF;
+        return 0;
+    }
+    for (size_t i = 0; i < total; i++) {
+        if (f->len == BUFSIZ && fflush(f) == EOF)
+            return size ? i / size : 0;
+        f->buf[f->len++] = bytes[i];
+        if (bytes[i] == '\n' && (f->flags & F_LINE))
+            flush = 1;
+    }
+    if (flush && fflush(f) == EOF)
+        return 0;
+    return count;
+}
+
+int fputc(int c, FILE *f)
+{
+    unsigned char byte = c;
+    return fwrite(&byte, 1, 1, f) == 1 ? byte : EOF;
+}
+
+int fputs(const char *s, FILE *f)
+{
+    size_t len = strlen(s);
+    return fwrite(s, 1, len, f) == len ? 0 : EOF;
+}
+
+int putchar(int c)
+{
+    return fputc(c, stdout);
+}
+
+int puts(const char *s)
+{
+    if (fputs(s, stdout) == EOF)
+        return EOF;
+    return fputc('\n', stdout) == EOF ? EOF : 0;
+}
+
+static int fill(FILE *f)
+{
+    if (!(f->flags & F_READ)) {
+        f->flags |= F_ERROR;
+        errno = EBADF;
+        return EOF;
+    }
+    /* prompts written without a newline show up before we block */
+    fflush(stdout);
+    ssize_t n = read(f->fd, f->buf, BUFSIZ);
+    if (n <= 0) {
+        f->flags |= n == 0 ? F_EOF : F_ERROR;
+        return EOF;
+    }
+    f->pos = 0;
+    f->len = n;
+    return 0;
+}
+
+int fgetc(FILE *f)
+{
+    if (f->pos == f->len && fill(f) == EOF)
+        return EOF;
+    return f->buf[f->pos++];
+}
+
+int getchar(void)
+{
+    return fgetc(stdin);
+}
+
+char *fgets(char *buf, int size, FILE *f)
+{
+    int i = 0;
+    while (i < size - 1) {
+        int c = fgetc(f);
+        if (c == EOF)
+            break;
+        buf[i++] = c;
+        if (c == '\n')
+            break;
+    }
+    if (i == 0 || size <= 0)
+        return NULL;
+    buf[i] = 0;
+    return buf;
+}
+
+size_t fread(void *buf, size_t size, size_t count, FILE *f)
+{
+    unsigned char *bytes = buf;
+    size_t total = size * count;
+    for (size_t i = 0; i < total; i++) {
+        int c = fgetc(f);
+        if (c == EOF)
+            return size ? i / size : 0;
+        bytes[i] = c;
+    }
+    return count;
+}
+
+int feof(FILE *f)
+{
+    return !!(f->flags & F_EOF);
+}
+
+int ferror(FILE *f)
+{
+    return !!(f->flags & F_ERROR);
+}
+
+/* printf: %d %i %u %x %X %o %c %s %p %%, the l, ll and z length modifiers,
+ * the - 0 flags and a field width, precision only for strings */
+
+struct sink {
+    /* Either a FILE or a buffer of `size` bytes */
+    FILE *file;
+    char *buf;
+    size_t size;
+    size_t written;
+};
+
+static void emit(struct sink *out, const char *s, size_t len)
+{
+    if (out->file) {
+        fwrite(s, 1, len, out->file);
+    } else {
+        for (size_t i = 0; i < len; i++)
+            if (out->written + i + 1 < out->size)
+                out->buf[out->written + i] = s[i];
+    }
+    out->written += len;
+}
+
+static void pad(struct sink *out, char c, int count)
+{
+    while (count-- > 0)
+        emit(out, &c, 1);
+}
+
+static int format(struct sink *out, const char *fmt, va_list ap)
+{
+    for (; *fmt; fmt++) {
+        if (*fmt != '%') {
+            const char *end = strchr(fmt, '%');
+            size_t len = end ? (size_t)(end - fmt) : strlen(fmt);
+            emit(out, fmt, len);
+            fmt += len - 1;
+            continue;
+        }
+        fmt++;
+        int left = 0, zero = 0;
+        for (;; fmt++) {
+            if (*fmt == '-')
+                left = 1;
+            else if (*fmt == '0')
+                zero = 1;
+            else
+                break;
+        }
+        int width = 0;
+        if (*fmt == '*') {
+            width = va_arg(ap, int);
+            fmt++;
+        }
+        while (*fmt >= '0' && *fmt <= '9')
+            width = width * 10 + *fmt++ - '0';
+        int precision = -1;
+        if (*fmt == '.') {
+            precision = 0;
+            fmt++;
+            while (*fmt >= '0' && *fmt <= '9')
+                precision = precision * 10 + *fmt++ - '0';
+        }
+        int longs = 0;
+        while (*fmt == 'l' || *fmt == 'z') {
+            longs++;
+            fmt++;
+        }
+
+        char digits[24];
+        const char *s = digits;
+        size_t len = 0;
+        int negative = 0;
+        unsigned long long value;
+        unsigned base = 10;
+        const char *alphabet = "0123456789abcdef";
+        switch (*fmt) {
+        case 'd':
+        case 'i': {
+            long long v = longs ? va_arg(ap, long) : va_arg(ap, int);
+            negative = v < 0;
+            value = negative ? -(unsigned long long)v : (unsigned long long)v;
+            goto number;
+        }
+        case 'p':
+            emit(out, "0x", 2);
+            value = (uintptr_t)va_arg(ap, void *);
+            base = 16;
+            goto number;
+        case 'X':
+            alphabet = "0123456789ABCDEF";
+            /* fall through */
+        case 'x':
+            base = 16;
+            /* fall through */
+        case 'o':
+            if (*fmt == 'o')
+                base = 8;
+            /* fall through */
+        case 'u':
+            value = longs ? va_arg(ap, unsigned long) : va_arg(ap, unsigned);
+        number: {
+            char *p = digits + sizeof(digits);
+            do {
+                *--p = alphabet[value % base];
+                value /= base;
+            } while (value);
+            if (negative)
+                *--p = '-';
+            s = p;
+            len = digits + sizeof(digits) - p;
+            break;
+        }
+        case 'c':
+            digits[0] = va_arg(ap, int);
+            len = 1;
+            break;
+        case 's':
+            s = va_arg(ap, const char *);
+            if (!s)
+                s = "(null)";
+            len = strlen(s);
+            if (precision >= 0 && (size_t)precision < len)
+                len = precision;
+            zero = 0;
+            break;
+        case '%':
+            digits[0] = '%';
+            len = 1;
+            break;
+        default:
+            /* unknown conversions are printed as they are */
+            emit(out, "%", 1);
+            if (!*fmt)
+                return out->written;
+            emit(out, fmt, 1);
+            continue;
+        }
+        int fill = width - (int)len;
+        if (!left && zero && negative) {
+            emit(out, s++, 1);
+            len--;
+        }
+        if (!left)
+            pad(out, zero ? '0' : ' ', fill);
+        emit(out, s, len);
+        if (left)
+            pad(out, ' ', fill);
+    }
+    return out->written;
+}
+
+int vfprintf(FILE *f, const char *fmt, va_list ap)
+{
+    struct sink out = {f, NULL, 0, 0};
+    return format(&out, fmt, ap);
+}
+
+int vprintf(const char *fmt, va_list ap)
+{
+    return vfprintf(stdout, fmt, ap);
+}
+
+int vsnprintf(char *buf, size_t size, const char *fmt, va_list ap)
+{
+    struct sink out = {NULL, buf, size, 0};
+    int len = format(&out, fmt, ap);
+    if (size)
+        buf[out.written < size ? out.written : size - 1] = 0;
+    return len;
+}
+
+int printf(const char *fmt, ...)
+{
+    va_list ap;
+    va_start(ap, fmt);
+    int len = vprintf(fmt, ap);
+    va_end(ap);
+    return len;
+}
+
+int fprintf(FILE *f, const char *fmt, ...)
+{
+    va_list ap;
+    va_start(ap, fmt);
+    int len = vfprintf(f, fmt, ap);
+    va_end(ap);
+    return len;
+}
+
+int snprintf(char *buf, size_t size, const char *fmt, ...)
+{
+    va_list ap;
+    va_start(ap, fmt);
+    int len = vsnprintf(buf, size, fmt, ap);
+    va_end(ap);
+    return len;
+}
@@ -0,0 +1,43 @@
+#ifndef _STDIO_H
+#define _STDIO_H
+
+#include <stdarg.h>
+#include <stddef.h>
+
+#define EOF (-1)
+#define BUFSIZ 256
+
+/* A buffered file descriptor. Output is flushed when the buffer fills, at
+ * every newline for terminals and at exit */
+typedef struct FILE FILE;
+
+extern FILE *stdin;
+extern FILE *stdout;
+extern FILE *stderr;
+
+FILE *fdopen(int fd, const char *mode);
+int fclose(FILE *f);
+int fflush(FILE *f);
+int fileno(FILE *f);
+
+int fputc(int c, FILE *f);
+int fputs(const char *s, FILE *f);
+size_t fwrite(const void *buf, size_t size, size_t count, FILE *f);
+int fgetc(FILE *f);
+char *fgets(char *buf, int size, FILE *f);
+size_t fread(void *buf, size_t size, size_t count, FILE *f);
+int feof(FILE *f);
+int ferror(FILE *f);
+
+int putchar(int c);
+int puts(const char *s);
+int getchar(void);
+
+int printf(const char *fmt, ...);
+int fprintf(FILE *f, const char *fmt, ...);
+int snprintf(char *buf, size_t size, const char *fmt, ...);
+int vprintf(const char *fmt, va_list ap);
+int vfprintf(FILE *f, const char *fmt, va_list ap);
+int vsnprintf(char *buf, size_t size, const char *fmt, va_list ap);
+
+#endif
@@ -0,0 +1,242 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+int main(int argc, char **argv, char **envp);
+
+char **environ;
+
+/* Called by crt0 */
+_Noreturn void __libc_start_main(int argc, char **argv, char **envp)
+{
+    environ = envp;
+    exit(main(argc, argv, envp));
+}
+
+#define MAX_ATEXIT 32
+
+static void (*atexit_fns[MAX_ATEXIT])(void);
+static int atexit_count;
+
+int atexit(void (*fn)(void))
+{
+    if (atexit_count == MAX_ATEXIT)
+        return -1;
+    atexit_fns[atexit_count++] = fn;
+    return 0;
+}
+
+_Noreturn void exit(int status)
+{
+    while (atexit_count)
+        atexit_fns[--atexit_count]();
+    fflush(stdout);
+    fflush(stderr);
+    _exit(status);
+}
+
+_Noreturn void abort(void)
+{
+    fflush(stdout);
+    signal(SIGABRT, SIG_DFL);
+    raise(SIGABRT);
+    /* only if SIGABRT is blocked */
+    fputs("abort\n", stderr);
+    _exit(127);
+}
+
+/* malloc: small blocks come from the brk heap and go back on an address
+ * ordered free list that merges neighbours, big ones get their own mmap */
+
+#define ALIGN 16
+#define MMAP_THRESHOLD (64 * 1024)
+#define HEAP_GROWTH (16 * 1024)
+/* Low bit of the size, the block was mapped on its own */
+#define MAPPED 1
+
+struct block {
+    /* Whole block with this header, a multiple of ALIGN */
+    size_t size;
+    struct block *next;
+};
+
+#define HEADER ((sizeof(struct block) + ALIGN - 1) & ~(size_t)(ALIGN - 1))
+
+static struct block *free_list;
+
+static size_t block_size(size_t size)
+{
+    if (size > SIZE_MAX - HEADER - ALIGN)
+        return 0;
+    return (size + HEADER + ALIGN - 1) & ~(size_t)(ALIGN - 1);
+}
+
+/* Puts `b` back, merging it with the free blocks right before and after */
+static void insert_free(struct block *b)
+{
+    struct block **link = &free_list;
+    while (*link && *link < b)
+        link = &(*link)->next;
+    b->next = *link;
+    *link = b;
+    if (b->next && (char *)b + b->size == (char *)b->next) {
+        b->size += b->next->size;
+        b->next = b->next->next;
+    }
+    if (link != &free_list) {
+        struct block *prev = (struct block *)((char *)link - offsetof(struct block, next));
+        if ((char *)prev + prev->size == (char *)b) {
+            prev->size += b->size;
+            prev->next = b->next;
+        }
+    }
+}
+
+static int grow_heap(size_t size)
+{
+    if (size < HEAP_GROWTH)
+        size = HEAP_GROWTH;
+    char *start = sbrk(0);
+    /* the break starts page aligned, keep blocks aligned after that */
+    size_t pad = (ALIGN - (uintptr_t)start % ALIGN) % ALIGN;
+    if (sbrk(size + pad) == (void *)-1)
+        return -1;
+    struct block *b = (struct block *)(start + pad);
+    b->size = size;
+    insert_free(b);
+    return 0;
+}
+
+void *malloc(size_t size)
+{
+    size_t need = block_size(size);
+    if (!need) {
+        errno = ENOMEM;
+        return NULL;
+    }
+    if (need >= MMAP_THRESHOLD) {
+        struct block *b = mmap(NULL, need, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if (b == MAP_FAILED)
+            return NULL;
+        b->size = need | MAPPED;
+        return (char *)b + HEADER;
+    }
+    for (int tries = 0; tries < 2; tries++) {
+        for (struct block **link = &free_list; *link; link = &(*link)->next) {
+            struct block *b = *link;
+            if (b->size < need)
+                continue;
+            if (b->size - need >= HEADER + ALIGN) {
+                struct block *rest = (struct block *)((char *)b + need);
+                rest->size = b->size - need;
+                rest->next = b->next;
+                b->size = need;
+                *link = rest;
+            } else {
+                *link = b->next;
+            }
+            return (char *)b + HEADER;
+        }
+        if (grow_heap(need) < 0) {
+            errno = ENOMEM;
+            return NULL;
+        }
+    }
+    return NULL;
+}
+
+void free(void *ptr)
+{
+    if (!ptr)
+        return;
+    struct block *b = (struct block *)((char *)ptr - HEADER);
+    if (b->size & MAPPED) {
+        munmap(b, b->size & ~(size_t)MAPPED);
+        return;
+    }
+    insert_free(b);
+}
+
+void *calloc(size_t count, size_t size)
+{
+    if (size && count > SIZE_MAX / size) {
+        errno = ENOMEM;
+        return NULL;
+    }
+    void *ptr = malloc(count * size);
+    if (ptr)
+        memset(ptr, 0, count * size);
+    return ptr;
+}
+
+void *realloc(void *ptr, size_t size)
+{
+    if (!ptr)
+        return malloc(size);
+    if (!size) {
+        free(ptr);
+        return NULL;
+    }
+    struct block *b = (struct block *)((char *)ptr - HEADER);
+    size_t have = (b->size & ~(size_t)MAPPED) - HEADER;
+    if (size <= have)
+        return ptr;
+    void *copy = malloc(size);
+    if (copy) {
+        memcpy(copy, ptr, have);
+        free(ptr);
+    }
+    return copy;
+}
+
+static int digit_value(char c)
+{
+    if (c >= '0' && c <= '9')
+        return c - '0';
+    if (c >= 'a' && c <= 'z')
+        return c - 'a' + 10;
+    if (c >= 'A' && c <= 'Z')
+        return c - 'A' + 10;
+    return 36;
+}
+
+long strtol(const char *s, char **end, int base)
+{
+    while (*s == ' ' || (*s >= '\t' && *s <= '\r'))
+        s++;
+    int negative = *s == '-';
+    if (*s == '-' || *s == '+')
+        s++;
+    if ((base == 0 || base == 16) && s[0] == '0' && (s[1] == 'x' || s[1] == 'X')) {
+        s += 2;
+        base = 16;
+    } else if (base == 0) {
+        base = s[0] == '0' ? 8 : 10;
+    }
+    unsigned long value = 0;
+    for (int d; (d = digit_value(*s)) < base; s++)
+        value = value * base + d;
+    if (end)
+        *end = (char *)s;
+    return negative ? -(long)value : (long)value;
+}
+
+int atoi(const char *s)
+{
+    return strtol(s, NULL, 10);
+}
+
+char *getenv(const char *name)
+{
+    size_t len = strlen(name);
+    for (char **var = environ; var && *var; var++) {
+        if (strncmp(*var, name, len) == 0 && (*var)[len] == '=')
+            return *var + len + 1;
+    }
+    return NULL;
+}
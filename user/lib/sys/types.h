@@ -0,0 +1,8 @@
+#ifndef _SYS_TYPES_H
+#define _SYS_TYPES_H
+
+typedef long ssize_t;
+typedef int pid_t;
+typedef long off_t;
+
+#endif
@@ -0,0 +1,141 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdarg.h>
+#include <syscall.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int errno;
+
+long __syscall_ret(long ret)
+{
+    if (ret < 0 && ret > -4096) {
+        errno = -ret;
+        return -1;
+    }
+    return ret;
+}
+
+ssize_t read(int fd, void *buf, size_t len)
+{
+    return __syscall_ret(__syscall3(SYS_read, fd, buf, len));
+}
+
+ssize_t write(int fd, const void *buf, size_t len)
+{
+    return __syscall_ret(__syscall3(SYS_write, fd, buf, len));
+}
+
+_Noreturn void _exit(int status)
+{
+    __syscall1(SYS_exit, status);
+    __builtin_unreachable();
+}
+
+int dup(int fd)
+{
+    return __syscall_ret(__syscall1(SYS_dup, fd));
+}
+
+int dup2(int old, int new)
+{
+    return __syscall_ret(__syscall2(SYS_dup2, old, new));
+}
+
+int close(int fd)
+{
+    return __syscall_ret(__syscall1(SYS_close, fd));
+}
+
+/* Every command the kernel knows takes an int or nothing */
+int fcntl(int fd, int cmd, ...)
+{
+    va_list ap;
+    va_start(ap, cmd);
+    int arg = va_arg(ap, int);
+    va_end(ap);
+    return __syscall_ret(__syscall3(SYS_fcntl, fd, cmd, arg));
+}
+
+int pipe(int fds[2])
+{
+    return __syscall_ret(__syscall1(SYS_pipe, fds));
+}
+
+pid_t getpid(void)
+{
+    return __syscall_ret(__syscall0(SYS_getpid));
+}
+
+pid_t getppid(void)
+{
+    return __syscall_ret(__syscall0(SYS_getppid));
+}
+
+pid_t fork(void)
+{
+    return __syscall_ret(__syscall0(SYS_fork));
+}
+
+int execve(const char *path, char *const argv[], char *const envp[])
+{
+    return __syscall_ret(__syscall3(SYS_execve, path, argv, envp));
+}
+
+int sched_yield(void)
+{
+    return __syscall_ret(__syscall0(SYS_yield));
+}
+
+int msleep(unsigned int ms)
+{
+    return __syscall_ret(__syscall1(SYS_sleep, ms));
+}
+
+pid_t waitpid(pid_t pid, int *status, int options)
+{
+    return __syscall_ret(__syscall3(SYS_waitpid, pid, status, options));
+}
+
+pid_t wait(int *status)
+{
+    return waitpid(-1, status, 0);
+}
+
+/* The kernel hands back the break it ended up with */
+static char *current_brk;
+
+int brk(void *addr)
+{
+    char *brk = (char *)__syscall1(SYS_brk, addr);
+    current_brk = brk;
+    if (brk != addr) {
+        errno = ENOMEM;
+        return -1;
+    }
+    return 0;
+}
+
+void *sbrk(long increment)
+{
+    if (!current_brk)
+        current_brk = (char *)__syscall1(SYS_brk, 0);
+    char *old = current_brk;
+    if (increment && brk(old + increment) < 0)
+        return (void *)-1;
+    return old;
+}
+
+void *mmap(void *addr, size_t len, int prot, int flags, int fd, off_t offset)
+{
+    long ret = __syscall6(SYS_mmap, (long)addr, len, prot, flags, fd, offset);
+    if (__syscall_ret(ret) == -1)
+        return MAP_FAILED;
+    return (void *)ret;
+}
+
+int munmap(void *addr, size_t len)
+{
+    return __syscall_ret(__syscall2(SYS_munmap, addr, len));
+}
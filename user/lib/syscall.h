@@ -0,0 +1,50 @@
+#ifndef _SYSCALL_H
+#define _SYSCALL_H
+
+/* Kept in sync with kernel/src/syscall/mod.rs */
+#define SYS_read 0
+#define SYS_write 1
+#define SYS_exit 2
+#define SYS_getpid 3
+#define SYS_yield 4
+#define SYS_sleep 5
+#define SYS_fork 6
+#define SYS_execve 7
+#define SYS_waitpid 8
+#define SYS_getppid 9
+#define SYS_brk 10
+#define SYS_mmap 11
+#define SYS_munmap 12
+#define SYS_kill 13
+#define SYS_sigaction 14
+#define SYS_sigprocmask 15
+#define SYS_sigreturn 16
+#define SYS_dup 17
+#define SYS_dup2 18
+#define SYS_close 19
+#define SYS_fcntl 20
+#define SYS_pipe 21
+
+/* Raw syscall, returns the value or -errno */
+static inline long __syscall6(long n, long a, long b, long c, long d, long e, long f)
+{
+    register long r10 __asm__("r10") = d;
+    register long r8 __asm__("r8") = e;
+    register long r9 __asm__("r9") = f;
+    long ret;
+    __asm__ volatile("syscall"
+                     : "=a"(ret)
+                     : "a"(n), "D"(a), "S"(b), "d"(c), "r"(r10), "r"(r8), "r"(r9)
+                     : "rcx", "r11", "memory");
+    return ret;
+}
+
+#define __syscall0(n) __syscall6(n, 0, 0, 0, 0, 0, 0)
+#define __syscall1(n, a) __syscall6(n, (long)(a), 0, 0, 0, 0, 0)
+#define __syscall2(n, a, b) __syscall6(n, (long)(a), (long)(b), 0, 0, 0, 0)
+#define __syscall3(n, a, b, c) __syscall6(n, (long)(a), (long)(b), (long)(c), 0, 0, 0)
+
+/* Turns a raw result into the C convention: -1 with errno set */
+long __syscall_ret(long ret);
+
+#endif
@@ -0,0 +1,29 @@
+#ifndef _UNISTD_H
+#define _UNISTD_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+#define STDIN_FILENO 0
+#define STDOUT_FILENO 1
+#define STDERR_FILENO 2
+
+ssize_t read(int fd, void *buf, size_t len);
+ssize_t write(int fd, const void *buf, size_t len);
+_Noreturn void _exit(int status);
+pid_t getpid(void);
+pid_t getppid(void);
+pid_t fork(void);
+int dup(int fd);
+int dup2(int old, int new);
+int close(int fd);
+/* fds[0] is the read end, fds[1] the write end */
+int pipe(int fds[2]);
+int execve(const char *path, char *const argv[], char *const envp[]);
+int sched_yield(void);
+/* Sleeps for `ms` milliseconds */
+int msleep(unsigned int ms);
+int brk(void *addr);
+void *sbrk(long increment);
+
+#endif
@@ -0,0 +1,39 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHILDREN 3
+
+/* Written by each child after the fork, the parent must not see it */
+static int shared = 42;
+
+int main(void)
+{
+    for (int i = 0; i < CHILDREN; i++) {
+        pid_t pid = fork();
+        if (pid < 0) {
+            printf("fork failed\n");
+            return 1;
+        }
+        if (pid == 0) {
+            shared = i;
+            printf("child %d: pid %d, shared %d\n", i, getpid(), shared);
+            exit(10 + i);
+        }
+    }
+    for (int i = 0; i < CHILDREN; i++) {
+        int status;
+        pid_t pid = wait(&status);
+        if (WIFEXITED(status))
+            printf("reaped %d, status %d\n", pid, WEXITSTATUS(status));
+        else
+            printf("reaped %d, signal %d\n", pid, WTERMSIG(status));
+    }
+    if (wait(NULL) != -1) {
+        printf("wait without children succeeded\n");
+        return 1;
+    }
+    printf("parent: shared is still %d\n", shared);
+    return shared == 42 ? 0 : 1;
+}
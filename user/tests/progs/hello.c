@@ -0,0 +1,35 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+int main(int argc, char **argv)
+{
+    printf("hello from pid %d, parent %d\n", getpid(), getppid());
+    for (int i = 0; i < argc; i++)
+        printf("argv[%d] = %s\n", i, argv[i]);
+    const char *home = getenv("HOME");
+    printf("HOME=%s\n", home ? home : "(unset)");
+
+    char *words[8];
+    for (int i = 0; i < 8; i++) {
+        words[i] = malloc(32);
+        snprintf(words[i], 32, "word %d", i);
+    }
+    for (int i = 0; i < 8; i++) {
+        printf("%s%s", words[i], i == 7 ? "\n" : ", ");
+        free(words[i]);
+    }
+
+    /* big enough to be mapped on its own */
+    size_t len = 256 * 1024;
+    char *big = malloc(len);
+    if (!big) {
+        printf("malloc of %zu bytes failed\n", len);
+        return 1;
+    }
+    memset(big, 'x', len);
+    printf("filled %zu bytes at %p\n", len, big);
+    free(big);
+    return 0;
+}
@@ -0,0 +1,78 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+/* More than the kernel buffers, so the writer has to wait for the reader */
+#define BIG 10000
+
+static int check(int ok, const char *what)
+{
+    printf("%s: %s\n", what, ok ? "ok" : "FAILED");
+    return ok ? 0 : 1;
+}
+
+/* Reads until end of file, returns how many bytes came through */
+static long drain(int fd)
+{
+    char buf[512];
+    long total = 0;
+    ssize_t n;
+    while ((n = read(fd, buf, sizeof(buf))) > 0)
+        total += n;
+    return n < 0 ? -1 : total;
+}
+
+int main(void)
+{
+    int failed = 0;
+    int fds[2];
+    char buf[16];
+
+    failed += check(pipe(fds) == 0, "pipe");
+    write(fds[1], "hello", 5);
+    failed += check(read(fds[0], buf, sizeof(buf)) == 5 && !memcmp(buf, "hello", 5), "read back");
+
+    int copy = dup(fds[1]);
+    failed += check(copy > fds[1], "dup takes the lowest free descriptor");
+    failed += check(dup2(copy, 10) == 10, "dup2");
+    close(copy);
+    write(10, "x", 1);
+    failed += check(read(fds[0], buf, sizeof(buf)) == 1 && buf[0] == 'x', "write through a dup");
+    close(10);
+
+    failed += check(fcntl(fds[0], F_GETFD) == 0, "no close-on-exec by default");
+    fcntl(fds[0], F_SETFD, FD_CLOEXEC);
+    failed += check(fcntl(fds[0], F_GETFD) == FD_CLOEXEC, "close-on-exec is set");
+    failed += check(close(42) < 0 && errno == EBADF, "closing a bad descriptor");
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        static char big[BIG];
+        close(fds[0]);
+        memset(big, 'a', sizeof(big));
+        _exit(write(fds[1], big, sizeof(big)) == BIG ? 0 : 1);
+    }
+    close(fds[1]);
+    failed += check(drain(fds[0]) == BIG, "a big write arrives whole, then EOF");
+    int status = 0;
+    waitpid(pid, &status, 0);
+    failed += check(WIFEXITED(status) && WEXITSTATUS(status) == 0, "writer saw no error");
+    close(fds[0]);
+
+    pipe(fds);
+    close(fds[0]);
+    signal(SIGPIPE, SIG_IGN);
+    failed += check(write(fds[1], "x", 1) < 0 && errno == EPIPE, "EPIPE without a reader");
+    signal(SIGPIPE, SIG_DFL);
+    pid = fork();
+    if (pid == 0)
+        _exit(write(fds[1], "x", 1));
+    waitpid(pid, &status, 0);
+    failed += check(WIFSIGNALED(status) && WTERMSIG(status) == SIGPIPE, "SIGPIPE without a reader");
+    close(fds[1]);
+    return failed;
+}
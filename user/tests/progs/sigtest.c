@@ -0,0 +1,108 @@
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static volatile int caught;
+static volatile int faults;
+
+static void on_usr(int sig)
+{
+    caught = sig;
+}
+
+static void on_segv(int sig)
+{
+    /* returning runs the faulting store again */
+    if (++faults == 3)
+        _exit(100 + sig);
+}
+
+static int check(int ok, const char *what)
+{
+    printf("%s: %s\n", what, ok ? "ok" : "FAILED");
+    return ok ? 0 : 1;
+}
+
+/* Forks a child that runs `child` and returns how it ended */
+static int run_child(void (*child)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        child();
+        _exit(0);
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    return status;
+}
+
+static void spin(void)
+{
+    for (;;)
+        ;
+}
+
+static void fault(void)
+{
+    signal(SIGSEGV, on_segv);
+    *(volatile int *)0 = 1;
+}
+
+static void unhandled_fault(void)
+{
+    signal(SIGSEGV, SIG_DFL);
+    *(volatile int *)0 = 1;
+}
+
+int main(void)
+{
+    int failed = 0;
+
+    signal(SIGUSR1, on_usr);
+    raise(SIGUSR1);
+    failed += check(caught == SIGUSR1, "handler runs");
+
+    sigset_t set;
+    sigemptyset(&set);
+    sigaddset(&set, SIGUSR1);
+    caught = 0;
+    sigprocmask(SIG_BLOCK, &set, NULL);
+    raise(SIGUSR1);
+    failed += check(caught == 0, "blocked signal waits");
+    sigprocmask(SIG_UNBLOCK, &set, NULL);
+    failed += check(caught == SIGUSR1, "unblocked signal arrives");
+
+    signal(SIGUSR2, SIG_IGN);
+    raise(SIGUSR2);
+    failed += check(caught == SIGUSR1, "ignored signal is dropped");
+
+    pid_t pid = fork();
+    if (pid == 0)
+        spin();
+    kill(pid, SIGTERM);
+    int status = 0;
+    waitpid(pid, &status, 0);
+    failed += check(WIFSIGNALED(status) && WTERMSIG(status) == SIGTERM, "kill ends a busy child");
+
+    caught = 0;
+    pid = fork();
+    if (pid == 0) {
+        while (!caught)
+            ;
+        _exit(caught);
+    }
+    kill(pid, SIGUSR1);
+    waitpid(pid, &status, 0);
+    failed += check(WIFEXITED(status) && WEXITSTATUS(status) == SIGUSR1, "busy child runs its handler");
+
+    status = run_child(fault);
+    failed += check(WIFEXITED(status) && WEXITSTATUS(status) == 100 + SIGSEGV, "SIGSEGV handler returns");
+
+    status = run_child(unhandled_fault);
+    failed += check(WTERMSIG(status) == SIGSEGV && WCOREDUMP(status), "SIGSEGV dumps core");
+
+    failed += check(sigaction(SIGKILL, &(struct sigaction){ 0 }, NULL) < 0, "SIGKILL cannot be caught");
+    return failed;
+}